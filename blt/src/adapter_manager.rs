@@ -1,42 +1,305 @@
-use anyhow::Result;
-use bluer::{
-    adv::{Advertisement, AdvertisementHandle},
-    gatt::local::{Application, ApplicationHandle},
-    Adapter,
+use crate::backend::local::Application;
+use crate::backend::{
+    AdapterEvent, AdapterPresence, AdvertisementHandle, AgentHandle, ApplicationHandle, BltAdapter,
+    BltDevice, BluerAdapter, PresenceEvents,
 };
+use crate::config::AdapterSettings;
+use crate::pairing_agent::PairingAgent;
+use anyhow::Result;
+use bluer::Address;
+use futures::{future, StreamExt};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::{timeout_at, Instant};
 use uuid::Uuid;
 
-pub struct AdapterManager {
-    adapter: Adapter,
+/// Properties of a remote device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub address: Address,
+    pub name: Option<String>,
+    pub alias: String,
+    /// Advertised service UUIDs, sorted.
+    pub uuids: Vec<Uuid>,
+    pub paired: bool,
+    pub connected: bool,
 }
 
-impl AdapterManager {
-    pub async fn new() -> Result<Self> {
+impl DeviceInfo {
+    pub async fn read(device: &dyn BltDevice) -> Result<Self> {
+        let mut uuids: Vec<Uuid> = device
+            .uuids()
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        uuids.sort();
+        Ok(Self {
+            address: device.address(),
+            name: device.name().await?,
+            alias: device.alias().await?,
+            uuids,
+            paired: device.is_paired().await?,
+            connected: device.is_connected().await?,
+        })
+    }
+}
+
+/// Capability an adapter may be required to have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AdapterCapability {
+    /// LE advertising, needed to serve applications.
+    Advertising,
+    /// Extended advertising on the LE 2M PHY.
+    Le2M,
+    /// Extended advertising on the LE Coded PHY (long range).
+    LeCoded,
+}
+
+impl FromStr for AdapterCapability {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.to_lowercase().as_str() {
+            "advertising" => Ok(AdapterCapability::Advertising),
+            "le_2m" => Ok(AdapterCapability::Le2M),
+            "le_coded" => Ok(AdapterCapability::LeCoded),
+            _ => Err(anyhow::Error::msg(format!(
+                "Unknown adapter capability '{}' (available: advertising, le_2m, le_coded).",
+                input
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for AdapterCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdapterCapability::Advertising => write!(f, "advertising"),
+            AdapterCapability::Le2M => write!(f, "le_2m"),
+            AdapterCapability::LeCoded => write!(f, "le_coded"),
+        }
+    }
+}
+
+/// Properties of a local adapter, used to select it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdapterInfo {
+    pub name: String,
+    pub address: Address,
+    pub capabilities: BTreeSet<AdapterCapability>,
+}
+
+impl fmt::Display for AdapterInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.name, self.address)?;
+        for capability in &self.capabilities {
+            write!(f, " {}", capability)?;
+        }
+        Ok(())
+    }
+}
+
+/// Which adapter to use: the first one with the name, address and capabilities requested.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdapterSelector {
+    pub name: Option<String>,
+    pub address: Option<Address>,
+    pub capabilities: BTreeSet<AdapterCapability>,
+}
+
+impl AdapterSelector {
+    pub fn from_settings(settings: &AdapterSettings) -> Result<Self> {
         Ok(Self {
-            adapter: AdapterManager::connect_adapter().await?,
+            name: settings.name.clone(),
+            address: settings
+                .address
+                .as_ref()
+                .map(|address| {
+                    address.parse().map_err(|_| {
+                        anyhow::Error::msg(format!("Invalid adapter address '{}'.", address))
+                    })
+                })
+                .transpose()?,
+            capabilities: settings
+                .capabilities
+                .iter()
+                .map(|capability| capability.parse())
+                .collect::<Result<_>>()?,
         })
     }
 
-    async fn connect_adapter() -> Result<Adapter> {
-        let session = bluer::Session::new().await?;
-        let adapter_names = session.adapter_names().await?;
-        let adapter_name = adapter_names.first().expect("No Bluetooth adapter present");
-        let adapter = session.adapter(adapter_name)?;
-        adapter.set_powered(true).await?;
-        adapter.set_pairable_timeout(15).await?;
+    pub fn matches(&self, adapter: &AdapterInfo) -> bool {
+        self.name.as_ref().is_none_or(|name| *name == adapter.name)
+            && self
+                .address
+                .is_none_or(|address| address == adapter.address)
+            && self.capabilities.is_subset(&adapter.capabilities)
+    }
 
-        Ok(adapter)
+    pub fn select<'a>(&self, adapters: &'a [AdapterInfo]) -> Result<&'a AdapterInfo> {
+        if adapters.is_empty() {
+            return Err(anyhow::Error::msg("No Bluetooth adapter present."));
+        }
+        adapters
+            .iter()
+            .find(|adapter| self.matches(adapter))
+            .ok_or_else(|| {
+                let available: Vec<String> = adapters.iter().map(ToString::to_string).collect();
+                anyhow::Error::msg(format!(
+                    "No Bluetooth adapter {} (available: {}).",
+                    self,
+                    available.join(", ")
+                ))
+            })
     }
+}
 
-    pub fn adapter(&self) -> &Adapter {
-        &self.adapter
+impl fmt::Display for AdapterSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut criteria = Vec::new();
+        if let Some(name) = &self.name {
+            criteria.push(format!("named '{}'", name));
+        }
+        if let Some(address) = &self.address {
+            criteria.push(format!("with address {}", address));
+        }
+        if !self.capabilities.is_empty() {
+            let capabilities: Vec<String> =
+                self.capabilities.iter().map(ToString::to_string).collect();
+            criteria.push(format!("supporting {}", capabilities.join(", ")));
+        }
+        if criteria.is_empty() {
+            write!(f, "available")
+        } else {
+            write!(f, "{}", criteria.join(" "))
+        }
+    }
+}
+
+pub struct AdapterManager {
+    adapter: Box<dyn BltAdapter>,
+    agent: Option<AgentHandle>,
+}
+
+impl AdapterManager {
+    pub async fn new(settings: &AdapterSettings) -> Result<Self> {
+        Ok(AdapterManager::with_adapter(Box::new(
+            BluerAdapter::new(settings).await?,
+        )))
+    }
+
+    pub fn with_adapter(adapter: Box<dyn BltAdapter>) -> Self {
+        Self {
+            adapter,
+            agent: None,
+        }
+    }
+
+    pub fn adapter(&self) -> &dyn BltAdapter {
+        self.adapter.as_ref()
+    }
+
+    /// Events about the adapter being removed and available again.
+    pub fn presence_events(&self) -> PresenceEvents {
+        self.adapter.presence_events()
+    }
+
+    /// Waits until `events` reports `presence`, forever if the adapter stops reporting.
+    pub async fn wait_for_presence(events: &mut PresenceEvents, presence: AdapterPresence) {
+        while let Some(event) = events.next().await {
+            if event == presence {
+                return;
+            }
+        }
+        future::pending::<()>().await;
+    }
+
+    /// Answers the pairing requests of devices with `agent` while the manager is alive, replacing
+    /// the agent registered before, if any.
+    pub async fn register_pairing_agent(&mut self, agent: PairingAgent) -> Result<()> {
+        self.agent = None;
+        self.agent = Some(self.adapter.register_pairing_agent(agent).await?);
+        Ok(())
+    }
+
+    /// Leaves the adapter as it was before being used. Done explicitly before the manager is
+    /// dropped, since dropping it can't wait for the adapter.
+    pub async fn shutdown(&self) -> Result<()> {
+        self.adapter.shutdown().await
     }
 
     pub async fn serve_gatt_application(
         &self,
         application: Application,
     ) -> Result<ApplicationHandle> {
-        Ok(self.adapter.serve_gatt_application(application).await?)
+        self.adapter.serve_gatt_application(application).await
+    }
+
+    /// Devices discovered during `duration`, sorted by address.
+    pub async fn scan(&self, duration: Duration) -> Result<Vec<DeviceInfo>> {
+        let deadline = Instant::now() + duration;
+        let mut discover = self.adapter.discover_devices().await?;
+        let mut addresses = BTreeSet::new();
+        while let Ok(Some(event)) = timeout_at(deadline, discover.next()).await {
+            match event {
+                AdapterEvent::DeviceAdded(address) => addresses.insert(address),
+                AdapterEvent::DeviceRemoved(address) => addresses.remove(&address),
+            };
+        }
+
+        let mut devices = Vec::new();
+        for address in addresses {
+            // Devices may go away before their properties are read.
+            if let Ok(device) = self.adapter.device(address) {
+                if let Ok(device_info) = DeviceInfo::read(device.as_ref()).await {
+                    devices.push(device_info);
+                }
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Device with `address`, discovering devices for up to `duration` if the adapter doesn't
+    /// know it yet.
+    pub async fn find_device(
+        &self,
+        address: Address,
+        duration: Duration,
+    ) -> Result<Box<dyn BltDevice>> {
+        if let Ok(device) = self.adapter.device(address) {
+            if device.alias().await.is_ok() {
+                return Ok(device);
+            }
+        }
+
+        let deadline = Instant::now() + duration;
+        let mut discover = self.adapter.discover_devices().await?;
+        while let Ok(Some(event)) = timeout_at(deadline, discover.next()).await {
+            if event == AdapterEvent::DeviceAdded(address) {
+                return self.adapter.device(address);
+            }
+        }
+        Err(anyhow::Error::msg(format!(
+            "Device {} not found after {} seconds.",
+            address,
+            duration.as_secs()
+        )))
+    }
+
+    pub async fn pair_device(&self, address: Address, duration: Duration) -> Result<DeviceInfo> {
+        let device = self.find_device(address, duration).await?;
+        if !device.is_paired().await? {
+            device.pair().await?;
+        }
+        DeviceInfo::read(device.as_ref()).await
+    }
+
+    /// Removes the device, and with it its pairing.
+    pub async fn unpair_device(&self, address: Address) -> Result<()> {
+        self.adapter.remove_device(address).await
     }
 
     pub async fn advertise_gatt_service(
@@ -44,14 +307,8 @@ impl AdapterManager {
         service_uuid: Uuid,
         local_name: &str,
     ) -> Result<AdvertisementHandle> {
-        Ok(self
-            .adapter
-            .advertise(Advertisement {
-                service_uuids: vec![service_uuid].into_iter().collect(),
-                discoverable: Some(true),
-                local_name: Some(local_name.to_string()),
-                ..Default::default()
-            })
-            .await?)
+        self.adapter
+            .advertise_gatt_service(service_uuid, local_name)
+            .await
     }
 }
@@ -1,107 +1,174 @@
-use crate::{AdapterManager, ApplicationDescriptor, BltApplication};
+use crate::backend::{AdapterEvent, AdapterPresence, BltCharacteristic, BltDevice, BltService};
+use crate::backoff::Backoff;
+use crate::capture::{Capture, CaptureDevice, CaptureSender};
+use crate::config::{ClientSettings, Config};
+use crate::device_database::{DeviceDatabase, PairingPolicy};
+use crate::pairing_agent::PairingAgent;
+use crate::shutdown::Shutdown;
+use crate::{AdapterManager, ApplicationDescriptor, BltApplication, ClientManager};
 use anyhow::Result;
-use bluer::{
-    gatt::remote::{Characteristic, Service},
-    AdapterEvent, Device,
-};
-use futures::{pin_mut, StreamExt};
+use bluer::Address;
+use futures::StreamExt;
 use std::collections::HashMap;
+use tokio::time::{sleep, Instant};
 use uuid::Uuid;
 
-include!("../../resources/database.inc");
-
 pub struct ApplicationClient {
     adapter_manager: AdapterManager,
     blt_application: Box<dyn BltApplication>,
     application_descriptor: ApplicationDescriptor,
-    service: Option<Service>,
-    characteristics: HashMap<Uuid, Characteristic>,
+    session: Option<ClientSession>,
+    capture_sender: CaptureSender,
+    settings: ClientSettings,
+    device_database: DeviceDatabase,
+    shutdown: Shutdown,
+}
+
+/// Connected device serving the application, with the characteristics it is exercised with.
+pub struct ClientSession {
+    pub device: Box<dyn BltDevice>,
+    pub service: Box<dyn BltService>,
+    pub characteristics: HashMap<Uuid, Box<dyn BltCharacteristic>>,
+    /// Sends the readings of the session tagged with its device.
+    pub capture_sender: CaptureSender,
 }
 
 impl ApplicationClient {
-    pub async fn start(blt_application: Box<dyn BltApplication>) -> Result<()> {
-        let mut application_client = ApplicationClient::new(blt_application).await?;
+    pub async fn start(
+        blt_application: Box<dyn BltApplication>,
+        config: &Config,
+        capture_sender: CaptureSender,
+        shutdown: Shutdown,
+    ) -> Result<()> {
+        let agent = PairingAgent::from_config(config)?;
+        let device_database = DeviceDatabase::load(config.client.device_database.as_deref())?;
+        let mut adapter_manager = AdapterManager::new(&config.adapter).await?;
+        if let Some(agent) = agent {
+            if let Err(error) = adapter_manager.register_pairing_agent(agent).await {
+                adapter_manager.shutdown().await?;
+                return Err(error);
+            }
+        }
+
+        let application_client = ApplicationClient::new(blt_application, adapter_manager)
+            .with_settings(config.client.clone())
+            .with_device_database(device_database)
+            .with_capture_sender(capture_sender)
+            .with_shutdown(shutdown);
+        if config.client.max_sessions > 1 {
+            ClientManager::new(application_client).run().await
+        } else {
+            application_client.run().await
+        }
+    }
+
+    pub fn new(blt_application: Box<dyn BltApplication>, adapter_manager: AdapterManager) -> Self {
+        Self {
+            adapter_manager,
+            application_descriptor: blt_application.application_descriptor(),
+            blt_application,
+            session: None,
+            capture_sender: CaptureSender::default(),
+            settings: ClientSettings::default(),
+            device_database: DeviceDatabase::default(),
+            shutdown: Shutdown::default(),
+        }
+    }
+
+    /// Devices to pair, and how.
+    pub fn with_device_database(mut self, device_database: DeviceDatabase) -> Self {
+        self.device_database = device_database;
+        self
+    }
+
+    pub fn with_settings(mut self, settings: ClientSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Where the readings of the application are sent.
+    pub fn with_capture_sender(mut self, capture_sender: CaptureSender) -> Self {
+        self.capture_sender = capture_sender;
+        self
+    }
+
+    /// Stops the client, Ctrl+C by default.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.shutdown
+    }
+
+    pub fn adapter_manager(&self) -> &AdapterManager {
+        &self.adapter_manager
+    }
+
+    pub fn settings(&self) -> &ClientSettings {
+        &self.settings
+    }
 
-        let adapter = application_client.adapter_manager.adapter();
+    /// Discovers and exercises the application until it stops or the shutdown is requested, then
+    /// shuts the adapter down.
+    pub async fn run(mut self) -> Result<()> {
+        let result = self.discover().await;
+        result.and(self.adapter_manager.shutdown().await)
+    }
+
+    async fn discover(&mut self) -> Result<()> {
+        let adapter = self.adapter_manager.adapter();
         println!(
             "Discovering on Bluetooth adapter {} with address {}.",
             adapter.name(),
             adapter.address().await?
         );
-        application_client.discover_service().await?;
-
-        application_client.exercise_characteristics().await?;
+        if let Some(seed) = self.blt_application.seed() {
+            println!("Random seed: {}.", seed);
+        }
 
-        Ok(())
-    }
+        // Discovery starts over if the adapter is removed, once it is back.
+        let mut presence = self.adapter_manager.presence_events();
+        let shutdown = self.shutdown.clone();
+        loop {
+            tokio::select! {
+                biased;
+                _ = AdapterManager::wait_for_presence(&mut presence, AdapterPresence::Lost) => {
+                    println!("Bluetooth adapter lost, waiting for it to discover again.");
+                }
+                result = async {
+                    self.discover_service().await?;
+                    self.exercise_characteristics().await
+                } => return result,
+                _ = shutdown.requested() => return Ok(()),
+            }
 
-    pub async fn new(blt_application: Box<dyn BltApplication>) -> Result<Self> {
-        Ok(Self {
-            adapter_manager: AdapterManager::new().await?,
-            application_descriptor: blt_application.application_descriptor(),
-            blt_application,
-            service: None,
-            characteristics: HashMap::new(),
-        })
+            self.session = None;
+            tokio::select! {
+                _ = AdapterManager::wait_for_presence(&mut presence, AdapterPresence::Restored) => {
+                    println!("Bluetooth adapter restored, resuming discovery.");
+                }
+                _ = shutdown.requested() => return Ok(()),
+            }
+        }
     }
 
     pub async fn discover_service(&mut self) -> Result<()> {
         let adapter = self.adapter_manager.adapter();
-        let discover = adapter.discover_devices().await?;
-        pin_mut!(discover);
+        let mut discover = adapter.discover_devices().await?;
         while let Some(event) = discover.next().await {
             match event {
                 AdapterEvent::DeviceAdded(address) => {
-                    let device = adapter.device(address)?;
-
-                    println!(
-                        "\nDiscovered device {}. [Name: '{}'. Alias: '{}']",
-                        device.address(),
-                        if let Some(value) = device.name().await? {
-                            value
-                        } else {
-                            String::from("")
-                        },
-                        if let Ok(value) = device.alias().await {
-                            value
-                        } else {
-                            String::from("")
-                        },
-                    );
-
-                    match self.find_application_service(&device).await {
-                        Ok(Some(service)) => match self.find_characteristics(&service).await {
-                            Ok(Some(characteristics)) => {
-                                self.service = Some(service);
-                                self.characteristics = characteristics;
-                                break;
-                            }
-                            Ok(None) => (),
-                            Err(error) => {
-                                println!("\tDevice failed: {}.", &error);
-                                let _ = adapter.remove_device(device.address()).await;
-                            }
-                        },
-                        Ok(None) => (),
-                        Err(error) => {
-                            println!("\tDevice failed: {}.", &error);
-                            let _ = adapter.remove_device(device.address()).await;
-                        }
-                    }
-
-                    if device.is_connected().await? {
-                        match device.disconnect().await {
-                            Ok(()) => println!("\tDevice disconnected."),
-                            Err(error) => println!("\tDevice disconnection failed: {}.", &error),
-                        }
+                    if let Some(session) = self.open_session(adapter.device(address)?).await? {
+                        self.session = Some(session);
+                        break;
                     }
                 }
 
                 AdapterEvent::DeviceRemoved(address) => {
                     println!("Device removed {}.", address);
                 }
-
-                _ => (),
             }
         }
 
@@ -110,8 +177,64 @@ impl ApplicationClient {
         Ok(())
     }
 
-    async fn find_application_service(&self, device: &Device) -> Result<Option<Service>> {
-        self.device_prepare_for_discovering(device).await?;
+    /// Opens a session with `device` if it serves the application, pairing it if needed. Devices
+    /// failing are removed, and the ones not serving the application are disconnected.
+    pub async fn open_session(&self, device: Box<dyn BltDevice>) -> Result<Option<ClientSession>> {
+        let address = device.address();
+        let name = device.name().await?;
+
+        println!(
+            "\nDiscovered device {}. [Name: '{}'. Alias: '{}']",
+            address,
+            name.clone().unwrap_or_default(),
+            device.alias().await.unwrap_or_default(),
+        );
+
+        let adapter = self.adapter_manager.adapter();
+        match self.find_application_service(device.as_ref()).await {
+            Ok(Some(service)) => match self.find_characteristics(service.as_ref()).await {
+                Ok(Some(characteristics)) => {
+                    return Ok(Some(ClientSession {
+                        device,
+                        service,
+                        characteristics,
+                        capture_sender: self.capture_sender.clone().with_device(CaptureDevice {
+                            address: address.to_string(),
+                            name,
+                        }),
+                    }));
+                }
+                Ok(None) => (),
+                Err(error) => {
+                    println!("\tDevice failed: {}.", &error);
+                    let _ = adapter.remove_device(address).await;
+                }
+            },
+            Ok(None) => (),
+            Err(error) => {
+                println!("\tDevice failed: {}.", &error);
+                let _ = adapter.remove_device(address).await;
+            }
+        }
+
+        if device.is_connected().await? {
+            match device.disconnect().await {
+                Ok(()) => println!("\tDevice disconnected."),
+                Err(error) => println!("\tDevice disconnection failed: {}.", &error),
+            }
+        }
+        Ok(None)
+    }
+
+    async fn find_application_service(
+        &self,
+        device: &dyn BltDevice,
+    ) -> Result<Option<Box<dyn BltService>>> {
+        let pairing_policy = self.device_database.pairing_policy(device).await;
+        if pairing_policy == PairingPolicy::Always {
+            println!("\tDevice needs to be paired before scan it for provided services.");
+            self.device_pair(device).await?;
+        }
 
         let uuids = device.uuids().await?.unwrap_or_default();
         if uuids.contains(self.application_descriptor.service_uuid()) {
@@ -119,13 +242,17 @@ impl ApplicationClient {
                 "\tDevice provides service '{}'.",
                 self.application_descriptor.service_name()
             );
-            self.device_connect(device).await?;
-
-            for service in device.services().await? {
-                if service.uuid().await? == *self.application_descriptor.service_uuid() {
-                    return Ok(Some(service));
+            return match self.connect_to_application_service(device).await {
+                Err(error)
+                    if pairing_policy == PairingPolicy::IfRequired
+                        && !device.is_paired().await? =>
+                {
+                    println!("\tDevice failed without pairing: {}.", &error);
+                    self.device_pair(device).await?;
+                    self.connect_to_application_service(device).await
                 }
-            }
+                result => result,
+            };
         } else {
             println!("\tDevice doesn't provide our service.");
             match device.disconnect().await {
@@ -137,25 +264,33 @@ impl ApplicationClient {
         Ok(None)
     }
 
-    async fn device_prepare_for_discovering(&self, device: &Device) -> Result<()> {
-        if let Ok(need_pair) = self.device_need_pair(device).await {
-            if need_pair {
-                println!("\tDevice needs to be paired before scan it for provided services.");
-                self.device_pair(device).await?
+    async fn connect_to_application_service(
+        &self,
+        device: &dyn BltDevice,
+    ) -> Result<Option<Box<dyn BltService>>> {
+        self.device_connect(device).await?;
+
+        for service in device.services().await? {
+            if service.uuid().await? == *self.application_descriptor.service_uuid() {
+                return Ok(Some(service));
             }
         }
 
-        Ok(())
-    }
-
-    async fn device_need_pair(&self, device: &Device) -> Result<bool> {
-        Ok(DEVICES_TO_BE_PAIRED.contains(&device.alias().await.unwrap().as_str()))
+        Ok(None)
     }
 
-    async fn device_pair(&self, device: &Device) -> Result<()> {
+    async fn device_pair(&self, device: &dyn BltDevice) -> Result<()> {
         if !device.is_paired().await? {
             println!("\tPairing...");
-            let mut retries = 5;
+            if let Some(passkey) = self
+                .device_database
+                .device_rule(device)
+                .await
+                .and_then(|rule| rule.passkey)
+            {
+                println!("\tExpected passkey: {:06}.", passkey);
+            }
+            let mut retries = self.settings.pair_retries;
             loop {
                 match device.pair().await {
                     Ok(()) => break,
@@ -163,7 +298,7 @@ impl ApplicationClient {
                         println!("\tPairing error: {}", &error);
                         retries -= 1;
                     }
-                    Err(error) => return Err(anyhow::Error::new(error)),
+                    Err(error) => return Err(error),
                 }
             }
             println!("\tPaired.");
@@ -173,10 +308,10 @@ impl ApplicationClient {
         Ok(())
     }
 
-    async fn device_connect(&self, device: &Device) -> Result<()> {
+    async fn device_connect(&self, device: &dyn BltDevice) -> Result<()> {
         if !device.is_connected().await? {
             println!("\tConnecting...");
-            let mut retries = 2;
+            let mut retries = self.settings.connect_retries;
             loop {
                 match device.connect().await {
                     Ok(()) => break,
@@ -184,7 +319,7 @@ impl ApplicationClient {
                         println!("\tConnect error: {}", &error);
                         retries -= 1;
                     }
-                    Err(error) => return Err(anyhow::Error::new(error)),
+                    Err(error) => return Err(error),
                 }
             }
             println!("\tConnected.");
@@ -196,8 +331,8 @@ impl ApplicationClient {
 
     async fn find_characteristics(
         &self,
-        service: &Service,
-    ) -> Result<Option<HashMap<Uuid, Characteristic>>> {
+        service: &dyn BltService,
+    ) -> Result<Option<HashMap<Uuid, Box<dyn BltCharacteristic>>>> {
         let mut characteristics = HashMap::new();
         for characteristic in service.characteristics().await? {
             let uuid = characteristic.uuid().await?;
@@ -214,7 +349,15 @@ impl ApplicationClient {
             }
         }
 
-        if characteristics.len() != self.application_descriptor.characteristics_uuids().len() {
+        if self
+            .application_descriptor
+            .characteristics_uuids()
+            .iter()
+            .any(|uuid| {
+                !characteristics.contains_key(uuid)
+                    && !self.application_descriptor.is_optional_characteristic(uuid)
+            })
+        {
             println!(
                 "\tInvalid service characteristics (service doesn't support all characteristics)."
             );
@@ -224,13 +367,123 @@ impl ApplicationClient {
         Ok(Some(characteristics))
     }
 
-    async fn exercise_characteristics(&self) -> Result<()> {
-        if self.service.is_some() {
-            self.blt_application
-                .exercise_characteristics(&self.characteristics)
-                .await?;
+    async fn exercise_characteristics(&mut self) -> Result<()> {
+        match self.session.take() {
+            Some(session) => self.run_session(session).await,
+            None => Ok(()),
         }
+    }
 
-        Ok(())
+    /// Runs the application with the characteristics of `session`.
+    pub async fn exercise_session(&self, session: &ClientSession) -> Result<()> {
+        self.blt_application
+            .exercise_characteristics(&session.characteristics, &session.capture_sender)
+            .await
+    }
+
+    /// Runs the application with `session` until it ends. If the application fails because the
+    /// connection with the device was lost, the device is reconnected following the
+    /// `client.reconnect_*` backoff and the application runs again, subscribing again to its
+    /// notifications. Every gap is sent as a signal loss capture. Reconnection stops when the
+    /// shutdown of the client is requested.
+    pub async fn run_session(&self, mut session: ClientSession) -> Result<()> {
+        loop {
+            // The application stops cleanly when its notifications are closed, which only ends the
+            // session unless the device reports being disconnected, like when out of range.
+            let error = match self.exercise_session(&session).await {
+                Ok(()) => match session.device.is_connected().await {
+                    Ok(false) => anyhow::Error::msg("Device disconnected"),
+                    _ => return Ok(()),
+                },
+                Err(error) => {
+                    if session.device.is_connected().await.unwrap_or(false) {
+                        return Err(error);
+                    }
+                    error
+                }
+            };
+
+            let address = session.device.address();
+            println!("\nConnection with device {} lost: {}.", address, error);
+            let lost = Instant::now();
+            let reconnection = self.reconnect(&session).await;
+            let gap = lost.elapsed();
+            println!(
+                "[{}] Signal loss of device {}: {:.1} seconds.",
+                chrono::Utc::now().format("%F %T%.3f"),
+                address,
+                gap.as_secs_f64()
+            );
+            session.capture_sender.send(Capture::signal_loss(gap));
+            session = match reconnection? {
+                Some(session) => session,
+                None => return Ok(()),
+            };
+        }
+    }
+
+    /// New session with the device of `session`, or None if the shutdown is requested meanwhile.
+    async fn reconnect(&self, session: &ClientSession) -> Result<Option<ClientSession>> {
+        let address = session.device.address();
+        let backoff = Backoff::from_settings(&self.settings);
+        for attempt in 1..=backoff.attempts {
+            let delay = backoff.delay(attempt);
+            println!(
+                "\tReconnecting in {:.1} seconds (attempt {} of {}).",
+                delay.as_secs_f64(),
+                attempt,
+                backoff.attempts
+            );
+            let reopened = tokio::select! {
+                reopened = async {
+                    sleep(delay).await;
+                    self.reopen_session(address, session.capture_sender.clone()).await
+                } => reopened,
+                _ = self.shutdown.requested() => {
+                    println!("\tReconnection of device {} cancelled.", address);
+                    return Ok(None);
+                }
+            };
+            match reopened {
+                Ok(session) => {
+                    println!("\tDevice {} reconnected.", address);
+                    return Ok(Some(session));
+                }
+                Err(error) => println!("\tReconnection failed: {}.", error),
+            }
+        }
+        Err(anyhow::Error::msg(format!(
+            "Connection with device {} lost, not reconnected after {} attempts.",
+            address, backoff.attempts
+        )))
+    }
+
+    /// Session with a device that was already in session, keeping its pairing if it fails.
+    async fn reopen_session(
+        &self,
+        address: Address,
+        capture_sender: CaptureSender,
+    ) -> Result<ClientSession> {
+        let device = self.adapter_manager.adapter().device(address)?;
+        let service = self
+            .connect_to_application_service(device.as_ref())
+            .await?
+            .ok_or_else(|| {
+                anyhow::Error::msg(format!(
+                    "Device doesn't provide service '{}'",
+                    self.application_descriptor.service_name()
+                ))
+            })?;
+        let characteristics = self
+            .find_characteristics(service.as_ref())
+            .await?
+            .ok_or_else(|| anyhow::Error::msg("Invalid service characteristics"))?;
+
+        Ok(ClientSession {
+            device,
+            service,
+            characteristics,
+            capture_sender,
+        })
     }
 }
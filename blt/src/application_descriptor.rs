@@ -1,14 +1,15 @@
-use crate::GattApplication;
-use bluer::gatt::local::{
-    Application, Characteristic, CharacteristicNotify, CharacteristicNotifyMethod,
-    CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod, Service,
+use crate::backend::local::{
+    self, Application, Characteristic, CharacteristicNotify, CharacteristicRead,
+    CharacteristicWrite, CharacteristicWriteMethod, Service,
 };
-use bluer::Uuid;
+use crate::GattApplication;
+use uuid::Uuid;
 
 pub struct ApplicationDescriptor {
     service_uuid: Uuid,
     service_name: &'static str,
     characteristics_uuids: Vec<Uuid>,
+    optional_characteristics_uuids: Vec<Uuid>,
     read_functions: Vec<Option<CharacteristicRead>>,
     write_functions: Vec<Option<CharacteristicWrite>>,
     notify_functions: Vec<Option<CharacteristicNotify>>,
@@ -27,12 +28,19 @@ impl ApplicationDescriptor {
             service_uuid,
             service_name,
             characteristics_uuids,
+            optional_characteristics_uuids: Vec::new(),
             read_functions,
             write_functions,
             notify_functions,
         }
     }
 
+    /// Marks characteristics that clients should not require remote devices to provide.
+    pub fn with_optional_characteristics(mut self, uuids: Vec<Uuid>) -> Self {
+        self.optional_characteristics_uuids = uuids;
+        self
+    }
+
     pub fn service_uuid(&self) -> &Uuid {
         &self.service_uuid
     }
@@ -45,6 +53,10 @@ impl ApplicationDescriptor {
         &self.characteristics_uuids
     }
 
+    pub fn is_optional_characteristic(&self, uuid: &Uuid) -> bool {
+        self.optional_characteristics_uuids.contains(uuid)
+    }
+
     pub fn default_read() -> Option<CharacteristicRead> {
         None
     }
@@ -60,7 +72,6 @@ impl ApplicationDescriptor {
     pub fn default_notify() -> Option<CharacteristicNotify> {
         Some(CharacteristicNotify {
             notify: true,
-            method: CharacteristicNotifyMethod::Io,
             ..Default::default()
         })
     }
@@ -75,9 +86,9 @@ impl ApplicationDescriptor {
         let mut notify_functions = Vec::new();
 
         for _ in 0..characteristics_uuids.len() {
-            read_functions.push(None);
-            write_functions.push(None);
-            notify_functions.push(None);
+            read_functions.push(ApplicationDescriptor::default_read());
+            write_functions.push(ApplicationDescriptor::default_write());
+            notify_functions.push(ApplicationDescriptor::default_notify());
         }
 
         ApplicationDescriptor::new(
@@ -97,11 +108,14 @@ impl From<ApplicationDescriptor> for GattApplication {
         let mut characteristics_controls = Vec::new();
         for _ in 0..application_descriptor.characteristics_uuids.len() {
             let (characteristic_control, characteristic_control_handle) =
-                bluer::gatt::local::characteristic_control();
+                local::characteristic_control();
             characteristics_controls_handles.push(characteristic_control_handle);
             characteristics_controls.push(characteristic_control);
         }
         characteristics_controls_handles.reverse();
+        application_descriptor.read_functions.reverse();
+        application_descriptor.write_functions.reverse();
+        application_descriptor.notify_functions.reverse();
 
         GattApplication::new(
             Application {
@@ -117,12 +131,9 @@ impl From<ApplicationDescriptor> for GattApplication {
                             write: application_descriptor.write_functions.pop().unwrap(),
                             notify: application_descriptor.notify_functions.pop().unwrap(),
                             control_handle: characteristics_controls_handles.pop().unwrap(),
-                            ..Default::default()
                         })
                         .collect(),
-                    ..Default::default()
                 }],
-                ..Default::default()
             },
             characteristics_controls,
             application_descriptor,
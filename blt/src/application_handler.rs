@@ -1,12 +1,11 @@
+use crate::backend::local::CharacteristicControl;
+use crate::backend::{AdvertisementHandle, ApplicationHandle};
 use crate::ApplicationDescriptor;
-use bluer::{
-    adv::AdvertisementHandle,
-    gatt::local::{ApplicationHandle, CharacteristicControl},
-};
+use uuid::Uuid;
 
 pub struct ApplicationHandler {
     application_descriptor: ApplicationDescriptor,
-    characteristics_controls: Vec<CharacteristicControl>,
+    characteristics_controls: Vec<Option<CharacteristicControl>>,
     application_handle: ApplicationHandle,
     advertisement_handle: AdvertisementHandle,
 }
@@ -20,7 +19,7 @@ impl ApplicationHandler {
     ) -> Self {
         Self {
             application_descriptor,
-            characteristics_controls,
+            characteristics_controls: characteristics_controls.into_iter().map(Some).collect(),
             application_handle,
             advertisement_handle,
         }
@@ -30,12 +29,20 @@ impl ApplicationHandler {
         self.application_descriptor.service_name()
     }
 
-    pub fn characteristics_controls(&self) -> &Vec<CharacteristicControl> {
-        &self.characteristics_controls
+    pub fn pop_characteristic_control(&mut self) -> Option<CharacteristicControl> {
+        self.characteristics_controls
+            .iter_mut()
+            .rev()
+            .find_map(|characteristic_control| characteristic_control.take())
     }
 
-    pub fn pop_characteristic_control(&mut self) -> Option<CharacteristicControl> {
-        self.characteristics_controls.pop()
+    pub fn take_characteristic_control(&mut self, uuid: &Uuid) -> Option<CharacteristicControl> {
+        let index = self
+            .application_descriptor
+            .characteristics_uuids()
+            .iter()
+            .position(|characteristic_uuid| characteristic_uuid == uuid)?;
+        self.characteristics_controls[index].take()
     }
 
     pub fn application_handle(&self) -> &ApplicationHandle {
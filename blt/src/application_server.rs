@@ -1,3 +1,6 @@
+use crate::backend::AdapterPresence;
+use crate::config::{Config, ServerSettings};
+use crate::shutdown::Shutdown;
 use crate::{AdapterManager, ApplicationHandler, BltApplication};
 use anyhow::Result;
 use std::time::Duration;
@@ -6,37 +9,95 @@ use tokio::time::sleep;
 pub struct ApplicationServer {
     blt_application: Box<dyn BltApplication>,
     adapter_manager: AdapterManager,
+    settings: ServerSettings,
+    shutdown: Shutdown,
 }
 
 impl ApplicationServer {
-    pub async fn start(blt_application: Box<dyn BltApplication>) -> Result<()> {
-        let mut application_server = ApplicationServer::new(blt_application).await?;
+    pub async fn start(
+        blt_application: Box<dyn BltApplication>,
+        config: &Config,
+        shutdown: Shutdown,
+    ) -> Result<()> {
+        ApplicationServer::new(blt_application, AdapterManager::new(&config.adapter).await?)
+            .with_settings(config.server.clone())
+            .with_shutdown(shutdown)
+            .run()
+            .await
+    }
+
+    pub fn new(blt_application: Box<dyn BltApplication>, adapter_manager: AdapterManager) -> Self {
+        Self {
+            blt_application,
+            adapter_manager,
+            settings: ServerSettings::default(),
+            shutdown: Shutdown::default(),
+        }
+    }
+
+    pub fn with_settings(mut self, settings: ServerSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Stops the server, Ctrl+C by default.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
 
-        let adapter = application_server.adapter_manager.adapter();
+    /// Serves the application until it stops or the shutdown is requested, then shuts the adapter
+    /// down.
+    pub async fn run(mut self) -> Result<()> {
+        let result = self.advertise().await;
+        result.and(self.adapter_manager.shutdown().await)
+    }
+
+    async fn advertise(&mut self) -> Result<()> {
+        let adapter = self.adapter_manager.adapter();
         println!(
             "Advertising on Bluetooth adapter {} with address {}.",
             adapter.name(),
             adapter.address().await?
         );
+        if let Some(seed) = self.blt_application.seed() {
+            println!("Random seed: {}.", seed);
+        }
 
-        application_server.serve().await?;
-
-        Ok(())
-    }
-
-    pub async fn new(blt_application: Box<dyn BltApplication>) -> Result<Self> {
-        Ok(Self {
-            blt_application,
-            adapter_manager: AdapterManager::new().await?,
-        })
+        let shutdown = self.shutdown.clone();
+        shutdown.run_until(self.serve()).await
     }
 
+    /// Serves the application until it stops. If the adapter is removed, the application and its
+    /// advertisement are registered again once the adapter is back.
     pub async fn serve(&mut self) -> Result<()> {
-        let gatt_application = self.blt_application.gatt_application();
-        let application_handler = gatt_application.init(&self.adapter_manager).await?;
-        let application_handler = self.blt_application.serve(application_handler).await?;
-        ApplicationServer::teardown(application_handler).await;
-        Ok(())
+        let mut presence = self.adapter_manager.presence_events();
+        loop {
+            let gatt_application = self
+                .blt_application
+                .gatt_application()
+                .with_local_name(self.settings.local_name.clone());
+            let application_handler = gatt_application.init(&self.adapter_manager).await?;
+
+            // Removing the adapter also ends the application, so its removal is checked first.
+            tokio::select! {
+                biased;
+                _ = AdapterManager::wait_for_presence(&mut presence, AdapterPresence::Lost) => {
+                    println!("Bluetooth adapter lost, waiting for it to serve again.");
+                }
+                application_handler = self.blt_application.serve(application_handler) => {
+                    ApplicationServer::teardown(application_handler?).await;
+                    return Ok(());
+                }
+            }
+
+            tokio::select! {
+                _ = AdapterManager::wait_for_presence(&mut presence, AdapterPresence::Restored) => {
+                    println!("Bluetooth adapter restored, registering service and advertisement.");
+                }
+                _ = self.shutdown.requested() => return Ok(()),
+            }
+        }
     }
 
     pub async fn teardown(application_handler: ApplicationHandler) {
@@ -1,23 +1,36 @@
+use crate::backend::local::CharacteristicControlEvent;
+use crate::backend::{BltCharacteristic, CharacteristicReader, CharacteristicWriter};
+use crate::capture::CaptureSender;
 use crate::{
     blt_application, ApplicationDescriptor, ApplicationHandler, BltApplication, GattApplication,
 };
 use anyhow::Result;
 use async_trait::async_trait;
-use bluer::gatt::remote::Characteristic;
-use bluer::gatt::{local::CharacteristicControlEvent, CharacteristicReader, CharacteristicWriter};
-use bluer::Uuid;
 use futures::{future, pin_mut, StreamExt};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
 
 include!("../../../resources/services/adder.inc");
 
-pub struct Adder;
+pub struct Adder {
+    seed: u64,
+}
+
+impl Adder {
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
 
 impl Default for Adder {
     fn default() -> Self {
-        Self
+        Self {
+            seed: rand::random(),
+        }
     }
 }
 
@@ -35,6 +48,10 @@ impl BltApplication for Adder {
         GattApplication::from(self.application_descriptor())
     }
 
+    fn seed(&self) -> Option<u64> {
+        Some(self.seed)
+    }
+
     async fn serve(
         &self,
         mut application_handler: ApplicationHandler,
@@ -105,24 +122,27 @@ impl BltApplication for Adder {
 
     async fn exercise_characteristics(
         &self,
-        characteristics: &HashMap<Uuid, Characteristic>,
+        characteristics: &HashMap<Uuid, Box<dyn BltCharacteristic>>,
+        _capture_sender: &CaptureSender,
     ) -> Result<()> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
         for uuid in characteristics.keys() {
             let (mut write_io, mut notify_io) =
                 blt_application::characteristic_io(uuid, characteristics).await?;
 
-            for message in generate_random_entries() {
+            for message in generate_random_entries(&mut rng) {
                 let data: Vec<u8> = message.as_bytes().to_vec();
 
                 println!("\n>> Command:  {:?}.", message);
-                write_io.write_all(&data).await.expect("Write failed.");
+                write_io.write_all(&data).await?;
 
                 let (aux_notify_io, result) =
                     blt_application::read_from_characteristic(notify_io).await;
 
                 notify_io = aux_notify_io;
 
-                let buffer = result.expect("Read failed.");
+                let buffer = result?;
                 println!(
                     "<< Response: {:?}.",
                     String::from_utf8_lossy(&buffer).trim()
@@ -148,9 +168,8 @@ fn sum(str: &str) -> String {
     result.to_string()
 }
 
-fn generate_random_entries() -> Vec<String> {
+fn generate_random_entries(rng: &mut StdRng) -> Vec<String> {
     let mut entries: Vec<String> = Vec::new();
-    let mut rng = rand::thread_rng();
     for _ in 0..rng.gen_range(1..10) {
         // entries
         entries.push(
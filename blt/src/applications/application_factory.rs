@@ -1,35 +1,59 @@
 use crate::adder::Adder;
 use crate::cts::CTS;
 use crate::heart_rate::HeartRate;
+use crate::heart_rate_scenario::HeartRateScenario;
+use crate::mock_capture::MockCapture;
 use crate::ping_pong::PingPong;
 
-use crate::{ApplicationClient, ApplicationServer, BltApplication};
+use crate::capture::CaptureSender;
+use crate::config::Config;
+use crate::shutdown::Shutdown;
+use crate::time_sync_daemon::TimeSyncConfig;
+use crate::{ApplicationClient, ApplicationServer, BltApplication, TimeSyncDaemon};
 
 use anyhow::Result;
-use std::env;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::str::FromStr;
+use std::time::Duration;
 
-const APP: &str = "APP";
-const APP_MODE: &str = "APP_MODE";
+/// Client application that generates captures without a band.
+pub const MOCK_APP: &str = "mock";
 
-#[derive(Debug, PartialEq)]
+/// Applications available.
+pub const APPLICATIONS: [&str; 5] = ["ping_pong", "adder", "cts", "heart_rate", MOCK_APP];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ApplicationMode {
     Client,
     Server,
+    TimeSync,
 }
 
 impl FromStr for ApplicationMode {
-    type Err = ();
+    type Err = anyhow::Error;
 
-    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+    fn from_str(input: &str) -> Result<Self> {
         let value = input.to_lowercase();
         match value.as_str() {
             "client" => Ok(ApplicationMode::Client),
             "server" => Ok(ApplicationMode::Server),
-            _ => {
-                println!("Unknown application mode '{}'", input);
-                Err(())
-            }
+            "time_sync" => Ok(ApplicationMode::TimeSync),
+            _ => Err(anyhow::Error::msg(format!(
+                "Unknown application mode '{}' (available: client, server, time_sync).",
+                input
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ApplicationMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplicationMode::Client => write!(f, "client"),
+            ApplicationMode::Server => write!(f, "server"),
+            ApplicationMode::TimeSync => write!(f, "time_sync"),
         }
     }
 }
@@ -37,57 +61,141 @@ impl FromStr for ApplicationMode {
 pub struct ApplicationFactory;
 
 impl ApplicationFactory {
-    pub async fn launch_application() -> Result<()> {
-        if let Some(application) = ApplicationFactory::discover_application() {
-            if let Some(application_mode) = ApplicationFactory::discover_mode() {
-                match application_mode {
-                    ApplicationMode::Client => ApplicationClient::start(application).await?,
-                    ApplicationMode::Server => ApplicationServer::start(application).await?,
-                };
+    /// Runs the application and mode of `config` until it stops or `shutdown` is requested.
+    pub async fn launch_application(
+        config: &Config,
+        capture_sender: CaptureSender,
+        shutdown: Shutdown,
+    ) -> Result<()> {
+        let mode = config.mode.ok_or_else(|| {
+            anyhow::Error::msg("Application mode is not defined (client, server or time_sync).")
+        })?;
+        if mode == ApplicationMode::TimeSync {
+            return TimeSyncDaemon::start(
+                &config.adapter,
+                ApplicationFactory::time_sync_config(config),
+                shutdown,
+            )
+            .await;
+        }
+
+        if ApplicationFactory::is_mock_application(config) {
+            let mock_capture = ApplicationFactory::mock_capture(config)?;
+            return shutdown.run_until(mock_capture.start(capture_sender)).await;
+        }
+
+        let application = ApplicationFactory::application(config)?;
+        match mode {
+            ApplicationMode::Client => {
+                ApplicationClient::start(application, config, capture_sender, shutdown).await
+            }
+            ApplicationMode::Server => {
+                ApplicationServer::start(application, config, shutdown).await
             }
+            ApplicationMode::TimeSync => Ok(()),
         }
+    }
 
-        Ok(())
+    /// Modes `app` runs in.
+    pub fn modes(app: &str) -> Vec<ApplicationMode> {
+        match app.to_lowercase().as_str() {
+            MOCK_APP => vec![ApplicationMode::Client],
+            _ => vec![ApplicationMode::Client, ApplicationMode::Server],
+        }
     }
 
-    pub fn discover_mode() -> Option<ApplicationMode> {
-        if let Some(application_mode) = ApplicationFactory::get_env_var(APP_MODE) {
-            if let Ok(application_mode) = ApplicationMode::from_str(&application_mode) {
-                return Some(application_mode);
-            }
+    pub fn time_sync_config(config: &Config) -> TimeSyncConfig {
+        let settings = &config.time_sync;
+        TimeSyncConfig {
+            interval: Duration::from_secs(settings.interval),
+            threshold: chrono::Duration::seconds(settings.threshold),
+            discovery_timeout: Duration::from_secs(settings.discovery_timeout),
+            history_length: settings.history_length,
+            max_drift_rate: settings.max_drift_rate,
+            history_path: settings.history.clone(),
         }
+    }
 
-        None
+    /// Whether the mock application has been requested.
+    fn is_mock_application(config: &Config) -> bool {
+        config
+            .app
+            .as_ref()
+            .map(|app| app.to_lowercase() == MOCK_APP)
+            .unwrap_or_default()
     }
 
-    pub fn discover_application() -> Option<Box<dyn BltApplication>> {
-        if let Some(app_name) = ApplicationFactory::get_env_var(APP) {
-            ApplicationFactory::get_blt_application(&app_name)
-        } else {
-            None
+    pub fn mock_capture(config: &Config) -> Result<MockCapture> {
+        let settings = &config.apps.mock;
+        let mut mock_capture = MockCapture::default()
+            .with_seed(ApplicationFactory::seed(config))
+            .with_interval(Duration::from_secs_f64(settings.interval))
+            .with_payload(settings.payload.parse()?);
+        if let Some(scenario) = ApplicationFactory::heart_rate_scenario(config)? {
+            mock_capture = mock_capture.with_scenario(scenario);
         }
+
+        Ok(mock_capture)
     }
 
-    fn get_env_var(var: &str) -> Option<String> {
-        if let Ok(result) = env::var(var) {
-            Some(result)
-        } else {
-            println!("Environment var '{}' is not defined.", var);
-            None
+    pub fn application(config: &Config) -> Result<Box<dyn BltApplication>> {
+        match &config.app {
+            Some(app_name) => ApplicationFactory::get_blt_application(app_name, config),
+            None => Err(anyhow::Error::msg(format!(
+                "Application is not defined (available: {}).",
+                APPLICATIONS.join(", ")
+            ))),
         }
     }
 
-    fn get_blt_application(name: &str) -> Option<Box<dyn BltApplication>> {
+    fn seed(config: &Config) -> u64 {
+        config.seed.unwrap_or_else(rand::random)
+    }
+
+    fn heart_rate_scenario(config: &Config) -> Result<Option<HeartRateScenario>> {
+        match &config.apps.heart_rate.scenario {
+            Some(scenario) => Ok(Some(HeartRateScenario::load(scenario)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_blt_application(name: &str, config: &Config) -> Result<Box<dyn BltApplication>> {
+        let seed = ApplicationFactory::seed(config);
+
         let value = name.to_lowercase();
         match value.as_str() {
-            "ping_pong" => Some(Box::new(PingPong::default())),
-            "adder" => Some(Box::new(Adder::default())),
-            "cts" => Some(Box::new(CTS::default())),
-            "heart_rate" => Some(Box::new(HeartRate::default())),
-            _ => {
-                println!("Unknown application '{}'", name);
-                None
+            "ping_pong" => Ok(Box::new(PingPong)),
+            "adder" => Ok(Box::new(Adder::default().with_seed(seed))),
+            "cts" => {
+                let settings = &config.apps.cts;
+                let mut cts = CTS::default()
+                    .with_offset(chrono::Duration::seconds(settings.offset))
+                    .with_drift_rate(settings.drift_rate);
+                if let Some(threshold) = settings.sync_threshold {
+                    cts = cts.with_sync_threshold(chrono::Duration::seconds(threshold));
+                }
+                Ok(Box::new(cts))
+            }
+            "heart_rate" => {
+                let heart_rate = HeartRate::default()
+                    .with_seed(seed)
+                    .with_notification_interval(Duration::from_secs_f64(
+                        config.apps.heart_rate.notification_interval,
+                    ));
+                match ApplicationFactory::heart_rate_scenario(config)? {
+                    Some(scenario) => Ok(Box::new(heart_rate.with_scenario(scenario))),
+                    None => Ok(Box::new(heart_rate)),
+                }
             }
+            MOCK_APP => Err(anyhow::Error::msg(format!(
+                "Application '{}' only runs as a client.",
+                name
+            ))),
+            _ => Err(anyhow::Error::msg(format!(
+                "Unknown application '{}' (available: {}).",
+                name,
+                APPLICATIONS.join(", ")
+            ))),
         }
     }
 }
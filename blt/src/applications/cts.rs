@@ -1,26 +1,82 @@
+use crate::backend::local::{
+    CharacteristicControlEvent, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod,
+    ReqError,
+};
+use crate::backend::{BltCharacteristic, BltDevice, CharacteristicWriter};
+use crate::capture::CaptureSender;
+use crate::current_time::{
+    AdjustReason, CurrentTime, DstOffset, LocalTimeInformation, ReferenceTimeInformation,
+    TimeSource, ACCURACY_UNKNOWN, DAYS_SINCE_UPDATE_MAX, HOURS_SINCE_UPDATE_MAX,
+};
 use crate::{
     blt_application, ApplicationDescriptor, ApplicationHandler, BltApplication, GattApplication,
 };
 use anyhow::Result;
 use async_trait::async_trait;
-use bluer::gatt::local::{CharacteristicRead, CharacteristicWrite};
-use bluer::gatt::remote::Characteristic;
-use bluer::Uuid;
-use chrono::{DateTime, Datelike, NaiveDateTime, ParseResult, Timelike};
-use futures::FutureExt;
+use chrono::NaiveDateTime;
+use futures::{pin_mut, FutureExt, StreamExt};
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::{Arc, OnceLock};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, Notify};
+use tokio::time::Instant;
+use uuid::Uuid;
 
 include!("../../../resources/services/cts.inc");
 
 const DIFF_IN_MINUTES_TO_FORCE_SYNC: i64 = 10;
 
-pub struct CTS;
+pub struct CTS {
+    /// Clock offset above which the client resynchronizes the server.
+    sync_threshold: chrono::Duration,
+    /// Emulated clock offset from the host clock, applied when the clock is built.
+    offset: chrono::Duration,
+    /// Emulated clock drift, applied when the clock is built.
+    drift_rate: f64,
+    /// Built on first use.
+    clock: OnceLock<Arc<Mutex<EmulatedClock>>>,
+    /// Signaled whenever the clock is set, so that subscribers get notified.
+    clock_changed: Arc<Notify>,
+}
+
+impl CTS {
+    pub fn with_sync_threshold(mut self, sync_threshold: chrono::Duration) -> Self {
+        self.sync_threshold = sync_threshold;
+        self
+    }
+
+    /// Emulated clock offset from the host clock.
+    pub fn with_offset(mut self, offset: chrono::Duration) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Emulated clock drift, in parts per million (positive values run fast).
+    pub fn with_drift_rate(mut self, drift_rate: f64) -> Self {
+        self.drift_rate = drift_rate;
+        self
+    }
+
+    /// Emulated clock, starting at the host clock plus the offset.
+    fn clock(&self) -> &Arc<Mutex<EmulatedClock>> {
+        self.clock.get_or_init(|| {
+            Arc::new(Mutex::new(
+                EmulatedClock::new(chrono::Utc::now().naive_utc() + self.offset)
+                    .with_drift_rate(self.drift_rate),
+            ))
+        })
+    }
+}
 
 impl Default for CTS {
     fn default() -> Self {
-        Self
+        Self {
+            sync_threshold: chrono::Duration::minutes(DIFF_IN_MINUTES_TO_FORCE_SYNC),
+            offset: chrono::Duration::zero(),
+            drift_rate: 0.0,
+            clock: OnceLock::new(),
+            clock_changed: Arc::new(Notify::new()),
+        }
     }
 }
 
@@ -28,40 +84,113 @@ impl Default for CTS {
 impl BltApplication for CTS {
     fn application_descriptor(&self) -> ApplicationDescriptor {
         ApplicationDescriptor::new(
-            uuid::Uuid::try_from(SERVICE).unwrap(),
+            Uuid::from(SERVICE),
             SERVICE_NAME,
-            vec![uuid::Uuid::try_from(CURRENT_TIME_CHARACTERISTIC).unwrap()],
-            vec![Some(CharacteristicRead {
-                read: true,
-                fun: Box::new(|_| {
-                    let current_local_time = chrono::Utc::now();
-                    let current_local_time = date_time_to_vector(&current_local_time);
-                    let value = Arc::new(Mutex::new(current_local_time));
-                    async move {
-                        let value = value.lock().await.clone();
-                        Ok(value)
-                    }
-                    .boxed()
+            vec![
+                Uuid::from(CURRENT_TIME_CHARACTERISTIC),
+                Uuid::from(LOCAL_TIME_INFORMATION_CHARACTERISTIC),
+                Uuid::from(REFERENCE_TIME_INFORMATION_CHARACTERISTIC),
+            ],
+            vec![
+                Some(CharacteristicRead {
+                    read: true,
+                    fun: Box::new({
+                        let clock = self.clock().clone();
+                        move |_| {
+                            let clock = clock.clone();
+                            async move { Ok(clock.lock().await.current_time().to_vector()) }.boxed()
+                        }
+                    }),
+                }),
+                Some(CharacteristicRead {
+                    read: true,
+                    fun: Box::new(|_| {
+                        async move { Ok(local_time_information().to_vector()) }.boxed()
+                    }),
+                }),
+                Some(CharacteristicRead {
+                    read: true,
+                    fun: Box::new(|_| {
+                        async move { Ok(reference_time_information().to_vector()) }.boxed()
+                    }),
+                }),
+            ],
+            vec![
+                Some(CharacteristicWrite {
+                    write: true,
+                    method: CharacteristicWriteMethod::Fun(Box::new({
+                        let clock = self.clock().clone();
+                        let clock_changed = self.clock_changed.clone();
+                        move |value, _| {
+                            let clock = clock.clone();
+                            let clock_changed = clock_changed.clone();
+                            async move {
+                                write_current_time(&value, &clock).await?;
+                                clock_changed.notify_one();
+                                Ok(())
+                            }
+                            .boxed()
+                        }
+                    })),
+                    ..Default::default()
                 }),
-                ..Default::default()
-            })],
-            vec![Some(CharacteristicWrite {
-                write: false,
-                ..Default::default()
-            })],
-            vec![ApplicationDescriptor::default_notify()],
+                None,
+                None,
+            ],
+            vec![ApplicationDescriptor::default_notify(), None, None],
         )
+        .with_optional_characteristics(vec![
+            Uuid::from(LOCAL_TIME_INFORMATION_CHARACTERISTIC),
+            Uuid::from(REFERENCE_TIME_INFORMATION_CHARACTERISTIC),
+        ])
     }
 
     fn gatt_application(&self) -> GattApplication {
         GattApplication::from(self.application_descriptor())
     }
 
-    async fn serve(&self, application_handler: ApplicationHandler) -> Result<ApplicationHandler> {
+    async fn serve(
+        &self,
+        mut application_handler: ApplicationHandler,
+    ) -> Result<ApplicationHandler> {
         let mut receiver = blt_application::server_control_c_handler(&application_handler);
+
+        let mut characteristic_writer: Option<CharacteristicWriter> = None;
+        let characteristic_control = application_handler
+            .take_characteristic_control(&Uuid::from(CURRENT_TIME_CHARACTERISTIC))
+            .unwrap();
+        pin_mut!(characteristic_control);
+
+        {
+            let clock = self.clock().lock().await;
+            println!(
+                "Emulated clock: '{}' (drift {} ppm).",
+                clock.now(),
+                clock.drift_rate
+            );
+        }
+
         'main_loop: loop {
             tokio::select! {
                 _ = receiver.recv() => break 'main_loop,
+                evt = characteristic_control.next() => {
+                    match evt {
+                        Some(CharacteristicControlEvent::Notify(notifier)) => {
+                            characteristic_writer = Some(notifier);
+                        },
+                        _ => break,
+                    }
+                },
+                _ = self.clock_changed.notified() => {
+                    let current_time = self.clock().lock().await.current_time();
+                    println!("Emulated clock set to '{}'.", current_time);
+                    if let Some(writer) = characteristic_writer.as_mut() {
+                        if let Err(err) = writer.write_all(&current_time.to_vector()).await {
+                            println!("Notification stream error: {}.", &err);
+                            characteristic_writer = None;
+                        }
+                    }
+                }
             }
         }
 
@@ -70,22 +199,56 @@ impl BltApplication for CTS {
 
     async fn exercise_characteristics(
         &self,
-        characteristics: &HashMap<Uuid, Characteristic>,
+        characteristics: &HashMap<Uuid, Box<dyn BltCharacteristic>>,
+        _capture_sender: &CaptureSender,
     ) -> Result<()> {
         let characteristic = characteristics
-            .get(&uuid::Uuid::try_from(CURRENT_TIME_CHARACTERISTIC).unwrap())
-            .unwrap();
+            .get(&Uuid::from(CURRENT_TIME_CHARACTERISTIC))
+            .unwrap()
+            .as_ref();
 
-        let current_service_time = read_service_value(characteristic).await?;
-        let current_local_time = chrono::Utc::now();
-        println!("Current service time [UTC]: '{}'", current_service_time);
-        println!("Current local time [UTC]: '{}'", current_local_time);
-        let diff = (current_service_time.timestamp() - current_local_time.timestamp()).abs();
-        if diff > 60 * DIFF_IN_MINUTES_TO_FORCE_SYNC {
+        if let Some(local_time_information) =
+            characteristics.get(&Uuid::from(LOCAL_TIME_INFORMATION_CHARACTERISTIC))
+        {
+            let value = local_time_information.read().await?;
+            println!(
+                "Local time information: {}",
+                LocalTimeInformation::from_vector(&value)?
+            );
+        }
+        if let Some(reference_time_information) =
+            characteristics.get(&Uuid::from(REFERENCE_TIME_INFORMATION_CHARACTERISTIC))
+        {
+            let value = reference_time_information.read().await?;
             println!(
-                "Difference is greater than {} minutes.",
-                DIFF_IN_MINUTES_TO_FORCE_SYNC
+                "Reference time information: {}",
+                ReferenceTimeInformation::from_vector(&value)?
             );
+        }
+
+        let current_time = read_service_value(characteristic).await?;
+        let current_local_time = chrono::Utc::now().naive_utc();
+        println!("Current service time [UTC]: '{}'", current_time);
+        println!("Current local time [UTC]: '{}'", current_local_time);
+        let out_of_sync = match current_time.date_time() {
+            Some(current_service_time) => {
+                let diff = current_service_time - current_local_time;
+                let out_of_sync =
+                    diff.num_milliseconds().abs() > self.sync_threshold.num_milliseconds();
+                if out_of_sync {
+                    println!(
+                        "Difference is greater than {} seconds.",
+                        self.sync_threshold.num_seconds()
+                    );
+                }
+                out_of_sync
+            }
+            None => {
+                println!("Service date not known.");
+                true
+            }
+        };
+        if out_of_sync {
             println!("Changing the remote service time.");
 
             write_service_value(&current_local_time, characteristic).await?;
@@ -99,45 +262,129 @@ impl BltApplication for CTS {
     }
 }
 
-async fn read_service_value(characteristic: &Characteristic) -> Result<NaiveDateTime> {
+pub fn service_uuid() -> Uuid {
+    Uuid::from(SERVICE)
+}
+
+/// Returns the Current Time characteristic of `device`, connecting to it if needed, or None if the
+/// device doesn't provide the Current Time Service.
+pub async fn find_current_time_characteristic(
+    device: &dyn BltDevice,
+) -> Result<Option<Box<dyn BltCharacteristic>>> {
+    let uuids = device.uuids().await?.unwrap_or_default();
+    if !uuids.contains(&Uuid::from(SERVICE)) {
+        return Ok(None);
+    }
+
+    if !device.is_connected().await? {
+        device.connect().await?;
+    }
+    for service in device.services().await? {
+        if service.uuid().await? == Uuid::from(SERVICE) {
+            for characteristic in service.characteristics().await? {
+                if characteristic.uuid().await? == Uuid::from(CURRENT_TIME_CHARACTERISTIC) {
+                    return Ok(Some(characteristic));
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+pub async fn read_service_value(characteristic: &dyn BltCharacteristic) -> Result<CurrentTime> {
     let current_service_time = characteristic.read().await?;
-    Ok(vector_to_naive_date_time(&current_service_time).unwrap())
+    CurrentTime::from_vector(&current_service_time)
 }
 
-async fn write_service_value(
-    time: &DateTime<chrono::Utc>,
-    characteristic: &Characteristic,
+pub async fn write_service_value(
+    time: &NaiveDateTime,
+    characteristic: &dyn BltCharacteristic,
 ) -> Result<()> {
-    characteristic.write(&date_time_to_vector(time)).await?;
+    let current_time = CurrentTime::new(*time).with_adjust_reason(AdjustReason {
+        manual_time_update: true,
+        ..Default::default()
+    });
+    characteristic.write(&current_time.to_vector()).await?;
     Ok(())
 }
 
-fn date_time_to_vector(date_time: &DateTime<chrono::Utc>) -> Vec<u8> {
-    vec![
-        date_time.year() as u8,
-        (date_time.year() >> 8) as u8,
-        date_time.month() as u8,
-        date_time.day() as u8,
-        date_time.hour() as u8,
-        date_time.minute() as u8,
-        date_time.second() as u8,
-        (date_time.weekday().num_days_from_monday() + 1) as u8,
-        0x00,
-    ]
+async fn write_current_time(value: &[u8], clock: &Mutex<EmulatedClock>) -> Result<(), ReqError> {
+    match CurrentTime::from_vector(value) {
+        Ok(current_time) => {
+            clock.lock().await.set(&current_time);
+            Ok(())
+        }
+        Err(error) => {
+            println!("Rejected current time write: {}", error);
+            Err(ReqError::Failed)
+        }
+    }
+}
+
+/// Clock that starts at a given time and then runs at `1 + drift_rate / 10^6` the speed of the host
+/// clock.
+pub struct EmulatedClock {
+    /// Emulated time at `since`.
+    base: NaiveDateTime,
+    since: Instant,
+    /// Drift, in parts per million.
+    pub drift_rate: f64,
+    /// Adjust Reason of the last time the clock was set.
+    adjust_reason: AdjustReason,
 }
 
-fn vector_to_naive_date_time(time: &[u8]) -> ParseResult<NaiveDateTime> {
-    let year = time[0] as u16 + ((time[1] as u16) << 8);
-    let month = time[2];
-    let day = time[3];
-    let hour = time[4];
-    let minute = time[5];
-    let second = time[6];
+impl EmulatedClock {
+    pub fn new(now: NaiveDateTime) -> Self {
+        Self {
+            base: now,
+            since: Instant::now(),
+            drift_rate: 0.0,
+            adjust_reason: AdjustReason::default(),
+        }
+    }
 
-    let date = format!(
-        "{:#04}-{:#02}-{:#02} {:#02}:{:#02}:{:#02}",
-        year, month, day, hour, minute, second
-    );
+    pub fn with_drift_rate(mut self, drift_rate: f64) -> Self {
+        self.drift_rate = drift_rate;
+        self
+    }
+
+    pub fn now(&self) -> NaiveDateTime {
+        let elapsed = self.since.elapsed().as_secs_f64() * (1.0 + self.drift_rate / 1_000_000.0);
+        self.base + chrono::Duration::nanoseconds((elapsed * 1_000_000_000.0) as i64)
+    }
+
+    pub fn current_time(&self) -> CurrentTime {
+        CurrentTime::new(self.now()).with_adjust_reason(self.adjust_reason)
+    }
 
-    NaiveDateTime::parse_from_str(date.as_str(), "%Y-%m-%d %H:%M:%S")
+    /// Moves the clock by `offset` without changing its drift.
+    pub fn offset(&mut self, offset: chrono::Duration) {
+        self.base = self.now() + offset;
+        self.since = Instant::now();
+    }
+
+    /// Sets the clock to `current_time`, keeping its date if the new one is not known.
+    pub fn set(&mut self, current_time: &CurrentTime) {
+        self.base = current_time
+            .date_time()
+            .unwrap_or_else(|| self.now().date().and_time(current_time.time));
+        self.since = Instant::now();
+        self.adjust_reason = current_time.adjust_reason;
+    }
+}
+
+/// The emulated clock runs in UTC.
+fn local_time_information() -> LocalTimeInformation {
+    LocalTimeInformation::new(Some(0), DstOffset::StandardTime)
+}
+
+/// The emulated clock follows the host clock, whose reference is not known.
+fn reference_time_information() -> ReferenceTimeInformation {
+    ReferenceTimeInformation {
+        time_source: TimeSource::Unknown,
+        accuracy: ACCURACY_UNKNOWN,
+        days_since_update: DAYS_SINCE_UPDATE_MAX,
+        hours_since_update: HOURS_SINCE_UPDATE_MAX,
+    }
 }
@@ -0,0 +1,482 @@
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday};
+use std::fmt;
+
+/// Current Time characteristic length: Exact Time 256 (9 bytes) plus Adjust Reason.
+pub const CURRENT_TIME_LENGTH: usize = 10;
+
+/// Local Time Information characteristic length.
+pub const LOCAL_TIME_INFORMATION_LENGTH: usize = 2;
+
+/// Reference Time Information characteristic length.
+pub const REFERENCE_TIME_INFORMATION_LENGTH: usize = 4;
+
+/// Adjust Reason bits.
+const ADJUST_REASON_MANUAL_TIME_UPDATE: u8 = 0x01;
+const ADJUST_REASON_EXTERNAL_REFERENCE_TIME_UPDATE: u8 = 0x02;
+const ADJUST_REASON_CHANGE_OF_TIME_ZONE: u8 = 0x04;
+const ADJUST_REASON_CHANGE_OF_DST: u8 = 0x08;
+
+/// Time Zone value used when the time zone is not known.
+const TIME_ZONE_UNKNOWN: i8 = -128;
+
+/// Time Zone increments: 15 minutes.
+const TIME_ZONE_RESOLUTION_SECONDS: i32 = 15 * 60;
+
+/// Accuracy values with a special meaning (otherwise in 1/8 seconds).
+pub const ACCURACY_OUT_OF_RANGE: u8 = 254;
+pub const ACCURACY_UNKNOWN: u8 = 255;
+
+/// Days Since Update value meaning "255 days or more".
+pub const DAYS_SINCE_UPDATE_MAX: u8 = 255;
+
+/// Hours Since Update value the spec requires when Days Since Update is [DAYS_SINCE_UPDATE_MAX].
+pub const HOURS_SINCE_UPDATE_MAX: u8 = 255;
+
+/// Adjust Reason field of the Current Time characteristic (CTS v1.1, section 3.1.2.1).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AdjustReason {
+    pub manual_time_update: bool,
+    pub external_reference_time_update: bool,
+    pub change_of_time_zone: bool,
+    pub change_of_dst: bool,
+}
+
+impl From<u8> for AdjustReason {
+    fn from(value: u8) -> Self {
+        Self {
+            manual_time_update: value & ADJUST_REASON_MANUAL_TIME_UPDATE != 0,
+            external_reference_time_update: value & ADJUST_REASON_EXTERNAL_REFERENCE_TIME_UPDATE
+                != 0,
+            change_of_time_zone: value & ADJUST_REASON_CHANGE_OF_TIME_ZONE != 0,
+            change_of_dst: value & ADJUST_REASON_CHANGE_OF_DST != 0,
+        }
+    }
+}
+
+impl From<AdjustReason> for u8 {
+    fn from(adjust_reason: AdjustReason) -> Self {
+        let mut value = 0;
+        if adjust_reason.manual_time_update {
+            value |= ADJUST_REASON_MANUAL_TIME_UPDATE;
+        }
+        if adjust_reason.external_reference_time_update {
+            value |= ADJUST_REASON_EXTERNAL_REFERENCE_TIME_UPDATE;
+        }
+        if adjust_reason.change_of_time_zone {
+            value |= ADJUST_REASON_CHANGE_OF_TIME_ZONE;
+        }
+        if adjust_reason.change_of_dst {
+            value |= ADJUST_REASON_CHANGE_OF_DST;
+        }
+        value
+    }
+}
+
+impl fmt::Display for AdjustReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reasons: Vec<&str> = [
+            (self.manual_time_update, "manual time update"),
+            (
+                self.external_reference_time_update,
+                "external reference time update",
+            ),
+            (self.change_of_time_zone, "change of time zone"),
+            (self.change_of_dst, "change of DST"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, reason)| *reason)
+        .collect();
+
+        if reasons.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", reasons.join(", "))
+        }
+    }
+}
+
+/// Current Time characteristic value (CTS v1.1, section 3.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurrentTime {
+    /// Year, month and day, each None if the device doesn't know it (0 once encoded).
+    pub year: Option<u16>,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+    /// Time of day, with a 1/256 seconds resolution once encoded.
+    pub time: NaiveTime,
+    pub adjust_reason: AdjustReason,
+}
+
+impl CurrentTime {
+    pub fn new(date_time: NaiveDateTime) -> Self {
+        Self {
+            year: Some(date_time.year() as u16),
+            month: Some(date_time.month() as u8),
+            day: Some(date_time.day() as u8),
+            time: date_time.time(),
+            adjust_reason: AdjustReason::default(),
+        }
+    }
+
+    pub fn with_adjust_reason(mut self, adjust_reason: AdjustReason) -> Self {
+        self.adjust_reason = adjust_reason;
+        self
+    }
+
+    /// Date, if the year, month and day are known.
+    pub fn date(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year? as i32, self.month? as u32, self.day? as u32)
+    }
+
+    /// Date and time, if the date is known.
+    pub fn date_time(&self) -> Option<NaiveDateTime> {
+        Some(self.date()?.and_time(self.time))
+    }
+
+    /// Fractions256 field: the sub-second part in 1/256 seconds.
+    pub fn fractions256(&self) -> u8 {
+        (self.time.nanosecond().min(999_999_999) as u64 * 256 / 1_000_000_000) as u8
+    }
+
+    pub fn to_vector(&self) -> Vec<u8> {
+        let year = self.year.unwrap_or(0);
+        vec![
+            year as u8,
+            (year >> 8) as u8,
+            self.month.unwrap_or(0),
+            self.day.unwrap_or(0),
+            self.time.hour() as u8,
+            self.time.minute() as u8,
+            self.time.second() as u8,
+            self.day_of_week()
+                .map_or(0, |weekday| weekday.number_from_monday() as u8),
+            self.fractions256(),
+            self.adjust_reason.into(),
+        ]
+    }
+
+    pub fn from_vector(vector: &[u8]) -> Result<Self> {
+        if vector.len() < CURRENT_TIME_LENGTH {
+            return Err(anyhow::Error::msg(format!(
+                "Truncated current time ({} of {} bytes).",
+                vector.len(),
+                CURRENT_TIME_LENGTH
+            )));
+        }
+
+        let year = u16::from_le_bytes([vector[0], vector[1]]);
+        let (month, day) = (vector[2], vector[3]);
+        let (hour, minute, second) = (vector[4], vector[5], vector[6]);
+        let nanosecond = (vector[8] as u64 * 1_000_000_000 / 256) as u32;
+        let invalid = || {
+            anyhow::Error::msg(format!(
+                "Invalid current time {:04}-{:02}-{:02} {:02}:{:02}:{:02}.",
+                year, month, day, hour, minute, second
+            ))
+        };
+
+        // Year, month and day 0 mean not known.
+        let known = |value: u8| (value != 0).then_some(value);
+        let current_time = Self {
+            year: (year != 0).then_some(year),
+            month: known(month),
+            day: known(day),
+            time: NaiveTime::from_hms_nano_opt(
+                hour as u32,
+                minute as u32,
+                second as u32,
+                nanosecond,
+            )
+            .ok_or_else(invalid)?,
+            adjust_reason: AdjustReason::from(vector[9]),
+        };
+        if month > 12 || day > 31 {
+            return Err(invalid());
+        }
+
+        // Day of week 0 means unknown, otherwise it must match the date if known.
+        let day_of_week = vector[7];
+        let date_known = current_time.year.is_some()
+            && current_time.month.is_some()
+            && current_time.day.is_some();
+        if date_known {
+            let date = current_time.date().ok_or_else(invalid)?;
+            if day_of_week != 0 && day_of_week as u32 != date.weekday().number_from_monday() {
+                return Err(anyhow::Error::msg(format!(
+                    "Day of week {} does not match {} ({}).",
+                    day_of_week,
+                    date,
+                    date.weekday()
+                )));
+            }
+        } else if day_of_week > 7 {
+            return Err(anyhow::Error::msg(format!(
+                "Invalid day of week {}.",
+                day_of_week
+            )));
+        }
+
+        Ok(current_time)
+    }
+
+    /// Day of week, if the date is known.
+    pub fn day_of_week(&self) -> Option<Weekday> {
+        Some(self.date()?.weekday())
+    }
+}
+
+impl fmt::Display for CurrentTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.date() {
+            Some(date) => write!(f, "{} {}", date.weekday(), date.format("%F"))?,
+            None => {
+                let unknown = |value: Option<u16>, width: usize| match value {
+                    Some(value) => format!("{:0width$}", value, width = width),
+                    None => "?".repeat(width),
+                };
+                write!(
+                    f,
+                    "{}-{}-{}",
+                    unknown(self.year, 4),
+                    unknown(self.month.map(u16::from), 2),
+                    unknown(self.day.map(u16::from), 2)
+                )?
+            }
+        }
+        write!(
+            f,
+            " {} + {}/256 s (adjust reason: {})",
+            self.time.format("%T"),
+            self.fractions256(),
+            self.adjust_reason
+        )
+    }
+}
+
+/// DST Offset field of the Local Time Information characteristic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DstOffset {
+    StandardTime,
+    HalfAnHourDaylightTime,
+    DaylightTime,
+    DoubleDaylightTime,
+    Unknown,
+    Reserved(u8),
+}
+
+impl DstOffset {
+    /// Offset added to the standard time, if known.
+    pub fn minutes(&self) -> Option<i32> {
+        match self {
+            DstOffset::StandardTime => Some(0),
+            DstOffset::HalfAnHourDaylightTime => Some(30),
+            DstOffset::DaylightTime => Some(60),
+            DstOffset::DoubleDaylightTime => Some(120),
+            DstOffset::Unknown | DstOffset::Reserved(_) => None,
+        }
+    }
+}
+
+impl From<u8> for DstOffset {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => DstOffset::StandardTime,
+            2 => DstOffset::HalfAnHourDaylightTime,
+            4 => DstOffset::DaylightTime,
+            8 => DstOffset::DoubleDaylightTime,
+            255 => DstOffset::Unknown,
+            value => DstOffset::Reserved(value),
+        }
+    }
+}
+
+impl From<DstOffset> for u8 {
+    fn from(dst_offset: DstOffset) -> Self {
+        match dst_offset {
+            DstOffset::StandardTime => 0,
+            DstOffset::HalfAnHourDaylightTime => 2,
+            DstOffset::DaylightTime => 4,
+            DstOffset::DoubleDaylightTime => 8,
+            DstOffset::Unknown => 255,
+            DstOffset::Reserved(value) => value,
+        }
+    }
+}
+
+/// Local Time Information characteristic value (CTS v1.1, section 3.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTimeInformation {
+    /// Offset from UTC in 15 minutes increments, without DST. None if unknown.
+    pub time_zone: Option<i8>,
+    pub dst_offset: DstOffset,
+}
+
+impl LocalTimeInformation {
+    pub fn new(time_zone: Option<i8>, dst_offset: DstOffset) -> Self {
+        Self {
+            time_zone,
+            dst_offset,
+        }
+    }
+
+    /// Local time offset from UTC (time zone plus DST), in seconds, if known.
+    pub fn utc_offset_seconds(&self) -> Option<i32> {
+        Some(
+            self.time_zone? as i32 * TIME_ZONE_RESOLUTION_SECONDS + self.dst_offset.minutes()? * 60,
+        )
+    }
+
+    pub fn to_vector(&self) -> Vec<u8> {
+        vec![
+            self.time_zone.unwrap_or(TIME_ZONE_UNKNOWN) as u8,
+            self.dst_offset.into(),
+        ]
+    }
+
+    pub fn from_vector(vector: &[u8]) -> Result<Self> {
+        if vector.len() < LOCAL_TIME_INFORMATION_LENGTH {
+            return Err(anyhow::Error::msg(format!(
+                "Truncated local time information ({} of {} bytes).",
+                vector.len(),
+                LOCAL_TIME_INFORMATION_LENGTH
+            )));
+        }
+
+        let time_zone = match vector[0] as i8 {
+            TIME_ZONE_UNKNOWN => None,
+            time_zone => Some(time_zone),
+        };
+
+        Ok(Self::new(time_zone, DstOffset::from(vector[1])))
+    }
+}
+
+impl fmt::Display for LocalTimeInformation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.time_zone {
+            Some(time_zone) => {
+                let minutes = time_zone as i32 * TIME_ZONE_RESOLUTION_SECONDS / 60;
+                let sign = if minutes < 0 { '-' } else { '+' };
+                write!(
+                    f,
+                    "UTC{}{:02}:{:02}",
+                    sign,
+                    minutes.abs() / 60,
+                    minutes.abs() % 60
+                )?
+            }
+            None => write!(f, "unknown time zone")?,
+        }
+        match self.dst_offset.minutes() {
+            Some(0) => write!(f, ", standard time"),
+            Some(minutes) => write!(f, ", DST +{} min", minutes),
+            None => write!(f, ", unknown DST"),
+        }
+    }
+}
+
+/// Time Source field of the Reference Time Information characteristic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSource {
+    Unknown,
+    NetworkTimeProtocol,
+    Gps,
+    RadioTimeSignal,
+    Manual,
+    AtomicClock,
+    CellularNetwork,
+    Reserved(u8),
+}
+
+impl From<u8> for TimeSource {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => TimeSource::Unknown,
+            1 => TimeSource::NetworkTimeProtocol,
+            2 => TimeSource::Gps,
+            3 => TimeSource::RadioTimeSignal,
+            4 => TimeSource::Manual,
+            5 => TimeSource::AtomicClock,
+            6 => TimeSource::CellularNetwork,
+            value => TimeSource::Reserved(value),
+        }
+    }
+}
+
+impl From<TimeSource> for u8 {
+    fn from(time_source: TimeSource) -> Self {
+        match time_source {
+            TimeSource::Unknown => 0,
+            TimeSource::NetworkTimeProtocol => 1,
+            TimeSource::Gps => 2,
+            TimeSource::RadioTimeSignal => 3,
+            TimeSource::Manual => 4,
+            TimeSource::AtomicClock => 5,
+            TimeSource::CellularNetwork => 6,
+            TimeSource::Reserved(value) => value,
+        }
+    }
+}
+
+/// Reference Time Information characteristic value (CTS v1.1, section 3.3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReferenceTimeInformation {
+    pub time_source: TimeSource,
+    /// Drift since the last update, in 1/8 seconds; see [ACCURACY_OUT_OF_RANGE] and
+    /// [ACCURACY_UNKNOWN].
+    pub accuracy: u8,
+    /// See [DAYS_SINCE_UPDATE_MAX].
+    pub days_since_update: u8,
+    /// See [HOURS_SINCE_UPDATE_MAX].
+    pub hours_since_update: u8,
+}
+
+impl ReferenceTimeInformation {
+    pub fn to_vector(&self) -> Vec<u8> {
+        vec![
+            self.time_source.into(),
+            self.accuracy,
+            self.days_since_update,
+            self.hours_since_update,
+        ]
+    }
+
+    pub fn from_vector(vector: &[u8]) -> Result<Self> {
+        if vector.len() < REFERENCE_TIME_INFORMATION_LENGTH {
+            return Err(anyhow::Error::msg(format!(
+                "Truncated reference time information ({} of {} bytes).",
+                vector.len(),
+                REFERENCE_TIME_INFORMATION_LENGTH
+            )));
+        }
+
+        Ok(Self {
+            time_source: TimeSource::from(vector[0]),
+            accuracy: vector[1],
+            days_since_update: vector[2],
+            hours_since_update: vector[3],
+        })
+    }
+}
+
+impl fmt::Display for ReferenceTimeInformation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "source {:?}, ", self.time_source)?;
+        match self.accuracy {
+            ACCURACY_OUT_OF_RANGE => write!(f, "accuracy out of range, ")?,
+            ACCURACY_UNKNOWN => write!(f, "unknown accuracy, ")?,
+            accuracy => write!(f, "accuracy {} ms, ", accuracy as u32 * 125)?,
+        }
+        if self.days_since_update == DAYS_SINCE_UPDATE_MAX {
+            write!(f, "updated {} days ago or more", DAYS_SINCE_UPDATE_MAX)
+        } else {
+            write!(
+                f,
+                "updated {} days and {} hours ago",
+                self.days_since_update, self.hours_since_update
+            )
+        }
+    }
+}
@@ -1,84 +1,163 @@
+use crate::backend::local::{
+    CharacteristicControlEvent, CharacteristicRead, CharacteristicWrite, CharacteristicWriteMethod,
+    ReqError,
+};
+use crate::backend::{BltCharacteristic, CharacteristicWriter};
 use crate::blt_application::flush_notify_buffer;
+use crate::capture::{to_hex, Capture, CaptureSender, Reading};
+use crate::heart_rate_measurement::{
+    BodySensorLocation, HeartRateMeasurement, SensorContact, RR_INTERVAL_RESOLUTION,
+};
+use crate::heart_rate_scenario::HeartRateScenario;
 use crate::{
     blt_application, ApplicationDescriptor, ApplicationHandler, BltApplication, GattApplication,
 };
 use anyhow::Result;
 use async_trait::async_trait;
-use bluer::gatt::local::{CharacteristicControlEvent, CharacteristicRead, CharacteristicWrite};
-use bluer::gatt::remote::Characteristic;
-use bluer::gatt::CharacteristicWriter;
-use bluer::Uuid;
 use futures::{pin_mut, FutureExt, StreamExt};
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
-use std::mem::MaybeUninit;
-use std::sync::Once;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
 use tokio::time::interval;
+use uuid::Uuid;
 
 include!("../../../resources/services/heart_rate.inc");
 
 const INITIAL_HEART_RATE_MEASURE: u16 = 80;
-const NOTIFICATION_INTERVAL: u64 = 7;
+const DEFAULT_NOTIFICATION_INTERVAL: Duration = Duration::from_secs(7);
 const MAX_HEART_RATE: u16 = 250;
 const MIN_HEART_RATE: u16 = 60;
+/// Maximum beat-to-beat variation of the emulated RR-Intervals.
+const RR_INTERVAL_JITTER: f64 = 0.03;
+/// Probability of losing (or recovering) sensor contact on each notification.
+const SENSOR_CONTACT_TOGGLE_PROBABILITY: f64 = 0.05;
+/// Lower bound of the emulated energy expenditure, in kJ per minute.
+const MIN_ENERGY_EXPENDITURE: f64 = 4.2;
+const BODY_SENSOR_LOCATION: BodySensorLocation = BodySensorLocation::Wrist;
+
+/// Heart Rate Control Point command.
+const RESET_ENERGY_EXPENDED: u8 = 0x01;
 
-#[derive(Default)]
+/// "Control Point value not supported" (ATT application error 0x80). BlueZ reports `Failed` to
+/// remote clients as application error 0x80.
+pub const CONTROL_POINT_NOT_SUPPORTED: ReqError = ReqError::Failed;
+
+/// State of a heart rate server, shared with its characteristic callbacks.
 struct ApplicationState {
-    pub state: Mutex<Vec<u8>>,
+    /// Last Heart Rate Measurement value.
+    state: Mutex<Vec<u8>>,
+    emulator: Mutex<HeartRateEmulator>,
 }
 
-fn application_state() -> &'static ApplicationState {
-    static mut APPLICATION_STATE: MaybeUninit<ApplicationState> = MaybeUninit::uninit();
-    static ONCE: Once = Once::new();
-
-    unsafe {
-        ONCE.call_once(|| {
-            let application_state = ApplicationState {
-                state: Mutex::new(Vec::new()),
-            };
-            APPLICATION_STATE.write(application_state);
-        });
-        APPLICATION_STATE.assume_init_ref()
+impl ApplicationState {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(Vec::new()),
+            emulator: Mutex::new(HeartRateEmulator::new(INITIAL_HEART_RATE_MEASURE)),
+        }
     }
 }
 
-pub struct HeartRate;
+pub struct HeartRate {
+    scenario: Option<HeartRateScenario>,
+    seed: u64,
+    notification_interval: Duration,
+    /// Each instance emulates its own sensor.
+    application_state: Arc<ApplicationState>,
+}
+
+impl HeartRate {
+    /// Plays `scenario` instead of the random walk.
+    pub fn with_scenario(mut self, scenario: HeartRateScenario) -> Self {
+        self.scenario = Some(scenario);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Time between measurement notifications of the server.
+    pub fn with_notification_interval(mut self, notification_interval: Duration) -> Self {
+        self.notification_interval = notification_interval;
+        self
+    }
+}
 
 impl Default for HeartRate {
     fn default() -> Self {
-        Self
+        Self {
+            scenario: None,
+            seed: rand::random(),
+            notification_interval: DEFAULT_NOTIFICATION_INTERVAL,
+            application_state: Arc::new(ApplicationState::new()),
+        }
     }
 }
 
 #[async_trait]
 impl BltApplication for HeartRate {
     fn application_descriptor(&self) -> ApplicationDescriptor {
+        let read_state = self.application_state.clone();
+        let write_state = self.application_state.clone();
         ApplicationDescriptor::new(
-            uuid::Uuid::try_from(SERVICE).unwrap(),
+            Uuid::from(SERVICE),
             SERVICE_NAME,
-            vec![uuid::Uuid::try_from(HEART_RATE_MEASUREMENT_CHARACTERISTIC).unwrap()],
-            vec![Some(CharacteristicRead {
-                read: true,
-                fun: Box::new(|_| {
-                    async move { Ok(application_state().state.lock().await.clone()) }.boxed()
+            vec![
+                Uuid::from(HEART_RATE_MEASUREMENT_CHARACTERISTIC),
+                Uuid::from(BODY_SENSOR_LOCATION_CHARACTERISTIC),
+                Uuid::from(HEART_RATE_CONTROL_POINT_CHARACTERISTIC),
+            ],
+            vec![
+                Some(CharacteristicRead {
+                    read: true,
+                    fun: Box::new(move |_| {
+                        let application_state = read_state.clone();
+                        async move { Ok(application_state.state.lock().await.clone()) }.boxed()
+                    }),
+                }),
+                Some(CharacteristicRead {
+                    read: true,
+                    fun: Box::new(|_| async move { Ok(vec![BODY_SENSOR_LOCATION.into()]) }.boxed()),
+                }),
+                None,
+            ],
+            vec![
+                Some(CharacteristicWrite {
+                    write: false,
+                    ..Default::default()
+                }),
+                None,
+                Some(CharacteristicWrite {
+                    write: true,
+                    method: CharacteristicWriteMethod::Fun(Box::new(move |value, _| {
+                        let application_state = write_state.clone();
+                        async move { write_control_point(&application_state, &value).await }.boxed()
+                    })),
+                    ..Default::default()
                 }),
-                ..Default::default()
-            })],
-            vec![Some(CharacteristicWrite {
-                write: false,
-                ..Default::default()
-            })],
-            vec![ApplicationDescriptor::default_notify()],
+            ],
+            vec![ApplicationDescriptor::default_notify(), None, None],
         )
+        .with_optional_characteristics(vec![
+            Uuid::from(BODY_SENSOR_LOCATION_CHARACTERISTIC),
+            Uuid::from(HEART_RATE_CONTROL_POINT_CHARACTERISTIC),
+        ])
     }
 
     fn gatt_application(&self) -> GattApplication {
         GattApplication::from(self.application_descriptor())
     }
 
+    fn seed(&self) -> Option<u64> {
+        Some(self.seed)
+    }
+
     async fn serve(
         &self,
         mut application_handler: ApplicationHandler,
@@ -86,14 +165,25 @@ impl BltApplication for HeartRate {
         let mut receiver = blt_application::server_control_c_handler(&application_handler);
 
         let mut characteristic_writer: Option<CharacteristicWriter> = None;
-        let characteristic_control = application_handler.pop_characteristic_control().unwrap();
-        let mut interval = interval(Duration::from_secs(NOTIFICATION_INTERVAL));
+        let characteristic_control = application_handler
+            .take_characteristic_control(&Uuid::from(HEART_RATE_MEASUREMENT_CHARACTERISTIC))
+            .unwrap();
+        let mut interval = interval(self.notification_interval);
 
         pin_mut!(characteristic_control);
 
         {
-            let mut state = application_state().state.lock().await;
-            *state = heart_rate_to_vector(&INITIAL_HEART_RATE_MEASURE);
+            let mut emulator = self.application_state.emulator.lock().await;
+            *emulator = match &self.scenario {
+                Some(scenario) => {
+                    println!("Playing heart rate scenario '{}'.", scenario.name);
+                    HeartRateEmulator::with_scenario(scenario.clone())
+                }
+                None => HeartRateEmulator::new(INITIAL_HEART_RATE_MEASURE),
+            }
+            .with_seed(self.seed);
+            let mut state = self.application_state.state.lock().await;
+            *state = emulator.measurement().to_vector();
         }
 
         'main_loop: loop {
@@ -108,14 +198,22 @@ impl BltApplication for HeartRate {
                     }
                 },
                 _ = interval.tick() => {
-                    let mut state = application_state().state.lock().await;
-                    let heart_rate = generate_random_heart_rate_measure(&vector_to_heart_rate(&state));
-                    *state = heart_rate_to_vector(&heart_rate);
-                    println!("Generated new random value: {:#3}.", heart_rate);
-                    if let Some(writer) = characteristic_writer.as_mut() {
-                        if let Err(err) = writer.write(&state).await {
-                            println!("Notification stream error: {}.", &err);
-                            characteristic_writer = None;
+                    let measurement = self
+                        .application_state
+                        .emulator
+                        .lock()
+                        .await
+                        .advance(self.notification_interval);
+                    println!("Generated new random value: {}.", measurement);
+                    // Beats that don't fit in one notification are sent in the next ones.
+                    for measurement in measurement.split_rr_intervals() {
+                        let mut state = self.application_state.state.lock().await;
+                        *state = measurement.to_vector();
+                        if let Some(writer) = characteristic_writer.as_mut() {
+                            if let Err(err) = writer.write(&state).await {
+                                println!("Notification stream error: {}.", &err);
+                                characteristic_writer = None;
+                            }
                         }
                     }
                 }
@@ -127,12 +225,29 @@ impl BltApplication for HeartRate {
 
     async fn exercise_characteristics(
         &self,
-        characteristics: &HashMap<Uuid, Characteristic>,
+        characteristics: &HashMap<Uuid, Box<dyn BltCharacteristic>>,
+        capture_sender: &CaptureSender,
     ) -> Result<()> {
         let characteristic = characteristics
-            .get(&uuid::Uuid::try_from(HEART_RATE_MEASUREMENT_CHARACTERISTIC).unwrap())
+            .get(&Uuid::from(HEART_RATE_MEASUREMENT_CHARACTERISTIC))
             .unwrap();
 
+        if let Some(body_sensor_location) =
+            characteristics.get(&Uuid::from(BODY_SENSOR_LOCATION_CHARACTERISTIC))
+        {
+            match body_sensor_location.read().await {
+                Ok(value) if !value.is_empty() => println!(
+                    "Body sensor location: {:?}.",
+                    BodySensorLocation::from(value[0])
+                ),
+                Ok(_) => println!("Body sensor location: empty value."),
+                Err(error) => println!("Body sensor location read failed: {}.", error),
+            }
+        }
+        let control_point = characteristics
+            .get(&Uuid::from(HEART_RATE_CONTROL_POINT_CHARACTERISTIC))
+            .map(|characteristic| characteristic.as_ref());
+
         let mut notify_io = characteristic.notify_io().await?;
         flush_notify_buffer(&mut notify_io).await?;
         println!("Flushed previous heart rate measurement notifications.\n");
@@ -143,12 +258,25 @@ impl BltApplication for HeartRate {
                 _ = receiver.recv() => break 'main_loop,
                 (aux_notify_io, result) = blt_application::read_from_characteristic(notify_io) => {
                     notify_io = aux_notify_io;
-                    let buffer = result.expect("Read failed.");
-                    println!(
-                        "[{}] {:#3}.",
-                        chrono::Utc::now().format("%F %T%.3f"),
-                        vector_to_heart_rate(&buffer)
-                    );
+                    let buffer = result?;
+                    if buffer.is_empty() {
+                        println!("Notification stream closed.");
+                        break 'main_loop;
+                    }
+                    let now = chrono::Utc::now().format("%F %T%.3f");
+                    match HeartRateMeasurement::from_vector(&buffer) {
+                        Ok(measurement) => {
+                            println!("[{}] {}.", now, measurement);
+                            capture_sender.send(measurement_capture(&buffer, &measurement));
+                            // The Energy Expended field stays at its maximum until the client resets it.
+                            if measurement.energy_expended == Some(u16::MAX) {
+                                if let Some(control_point) = control_point {
+                                    reset_energy_expended(control_point).await;
+                                }
+                            }
+                        }
+                        Err(error) => println!("[{}] {}", now, error),
+                    }
                 },
             }
         }
@@ -157,19 +285,167 @@ impl BltApplication for HeartRate {
     }
 }
 
-fn vector_to_heart_rate(vector: &[u8]) -> u16 {
-    ((vector[0] as u16) << 8) + vector[1] as u16
+async fn write_control_point(
+    application_state: &ApplicationState,
+    value: &[u8],
+) -> Result<(), ReqError> {
+    match value {
+        [RESET_ENERGY_EXPENDED] => {
+            let mut emulator = application_state.emulator.lock().await;
+            emulator.reset_energy_expended();
+            *application_state.state.lock().await = emulator.measurement().to_vector();
+            println!("Energy expended reset.");
+            Ok(())
+        }
+        _ => {
+            println!("Unsupported heart rate control point value: {:?}.", value);
+            Err(CONTROL_POINT_NOT_SUPPORTED)
+        }
+    }
+}
+
+/// Capture of a Heart Rate Measurement characteristic value.
+pub fn measurement_capture(value: &[u8], measurement: &HeartRateMeasurement) -> Capture {
+    Capture::new(to_hex(value))
+        .with_source(
+            Uuid::from(SERVICE),
+            Uuid::from(HEART_RATE_MEASUREMENT_CHARACTERISTIC),
+        )
+        .with_reading(Reading::new(measurement.heart_rate as f64, "bpm"))
+}
+
+async fn reset_energy_expended(control_point: &dyn BltCharacteristic) {
+    match control_point.write(&[RESET_ENERGY_EXPENDED]).await {
+        Ok(()) => println!("Energy expended reset."),
+        Err(error) => println!("Energy expended reset failed: {}.", error),
+    }
+}
+
+/// Emulates a heart rate sensor: a random walk of the heart rate (or a scripted scenario) with
+/// matching RR-Intervals, an accumulated energy expenditure and a sensor contact that is lost and
+/// recovered now and then.
+pub struct HeartRateEmulator {
+    heart_rate: u16,
+    sensor_contact: SensorContact,
+    /// Accumulated energy expended, in kJ.
+    energy_expended: f64,
+    /// Time elapsed since the last emulated beat.
+    since_last_beat: Duration,
+    scenario: Option<HeartRateScenario>,
+    /// Time elapsed since the emulation started.
+    elapsed: Duration,
+    rng: StdRng,
+}
+
+impl HeartRateEmulator {
+    pub fn new(heart_rate: u16) -> Self {
+        Self {
+            heart_rate,
+            sensor_contact: SensorContact::Detected,
+            energy_expended: 0.0,
+            since_last_beat: Duration::ZERO,
+            scenario: None,
+            elapsed: Duration::ZERO,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Makes the emulation reproducible: the same seed yields the same measurements.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn with_scenario(scenario: HeartRateScenario) -> Self {
+        let (heart_rate, sensor_contact) = scenario.at(Duration::ZERO);
+        Self {
+            sensor_contact,
+            scenario: Some(scenario),
+            ..Self::new(heart_rate)
+        }
+    }
+
+    pub fn reset_energy_expended(&mut self) {
+        self.energy_expended = 0.0;
+    }
+
+    pub fn measurement(&self) -> HeartRateMeasurement {
+        HeartRateMeasurement {
+            sensor_contact: self.sensor_contact,
+            energy_expended: Some(self.energy_expended as u16),
+            ..HeartRateMeasurement::new(self.heart_rate)
+        }
+    }
+
+    /// Moves the emulation `elapsed` forward and returns the measurement to be notified, including
+    /// the RR-Intervals of the beats that happened meanwhile.
+    pub fn advance(&mut self, elapsed: Duration) -> HeartRateMeasurement {
+        let rnd = &mut self.rng;
+        self.elapsed += elapsed;
+
+        let rr_interval_jitter = match &self.scenario {
+            Some(scenario) => {
+                (self.heart_rate, self.sensor_contact) = scenario.at(self.elapsed);
+                scenario.rr_interval_jitter
+            }
+            None => {
+                if rnd.gen_bool(SENSOR_CONTACT_TOGGLE_PROBABILITY) {
+                    self.sensor_contact = match self.sensor_contact {
+                        SensorContact::Detected => SensorContact::NotDetected,
+                        _ => SensorContact::Detected,
+                    };
+                }
+                if self.sensor_contact == SensorContact::Detected {
+                    self.heart_rate = generate_random_heart_rate_measure(rnd, &self.heart_rate);
+                }
+                RR_INTERVAL_JITTER
+            }
+        };
+
+        self.energy_expended = (self.energy_expended
+            + energy_expenditure(self.heart_rate) * elapsed.as_secs_f64() / 60.0)
+            .min(u16::MAX as f64);
+
+        let mut rr_intervals = Vec::new();
+        if self.sensor_contact == SensorContact::Detected {
+            let mean_rr_interval = 60.0 / self.heart_rate as f64;
+            let mut remaining = self.since_last_beat + elapsed;
+            loop {
+                let rr_interval = Duration::from_secs_f64(
+                    mean_rr_interval
+                        * (1.0 + rnd.gen_range(-rr_interval_jitter..=rr_interval_jitter)),
+                );
+                if rr_interval > remaining {
+                    break;
+                }
+                remaining -= rr_interval;
+                rr_intervals.push(
+                    (rr_interval.as_secs_f64() * RR_INTERVAL_RESOLUTION as f64).round() as u16,
+                );
+            }
+            self.since_last_beat = remaining;
+        } else {
+            self.since_last_beat = Duration::ZERO;
+        }
+
+        HeartRateMeasurement {
+            rr_intervals,
+            ..self.measurement()
+        }
+    }
 }
 
-fn heart_rate_to_vector(heart_rate: &u16) -> Vec<u8> {
-    vec![(heart_rate >> 8) as u8, *heart_rate as u8]
+/// Energy expenditure estimation (Keytel et al., 2005) for a 30 years old, 70 kg adult, in kJ per
+/// minute.
+fn energy_expenditure(heart_rate: u16) -> f64 {
+    (-55.0969 + 0.6309 * heart_rate as f64 + 0.1988 * 70.0 + 0.2017 * 30.0)
+        .max(MIN_ENERGY_EXPENDITURE)
 }
 
-fn generate_random_heart_rate_measure(previous_value: &u16) -> u16 {
-    let mut rnd = rand::thread_rng();
+fn generate_random_heart_rate_measure(rnd: &mut StdRng, previous_value: &u16) -> u16 {
     let factor: f32 = *previous_value as f32 * rnd.gen_range(0.0..0.05);
     let direction = rnd.gen_range(-1..2);
     let change = (factor * direction as f32) as i16;
     let value = (*previous_value as i16 + change) as u16;
-    value.min(MAX_HEART_RATE).max(MIN_HEART_RATE) as u16
+    value.clamp(MIN_HEART_RATE, MAX_HEART_RATE)
 }
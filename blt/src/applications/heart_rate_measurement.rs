@@ -0,0 +1,277 @@
+use anyhow::Result;
+use std::fmt;
+
+/// Heart Rate Value Format bit (0: UINT8, 1: UINT16).
+const FLAG_VALUE_FORMAT_UINT16: u8 = 0x01;
+
+/// Sensor Contact Status bits.
+const FLAG_SENSOR_CONTACT_MASK: u8 = 0x06;
+const FLAG_SENSOR_CONTACT_NOT_DETECTED: u8 = 0x04;
+const FLAG_SENSOR_CONTACT_DETECTED: u8 = 0x06;
+
+/// Energy Expended Status bit.
+const FLAG_ENERGY_EXPENDED: u8 = 0x08;
+
+/// RR-Interval bit.
+const FLAG_RR_INTERVAL: u8 = 0x10;
+
+/// Largest notification payload with the default ATT MTU (23 bytes minus 3 bytes of header).
+pub const MAX_PAYLOAD_LENGTH: usize = 20;
+
+/// RR-Interval resolution: 1/1024 seconds.
+pub const RR_INTERVAL_RESOLUTION: u16 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueFormat {
+    Uint8,
+    Uint16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorContact {
+    NotSupported,
+    NotDetected,
+    Detected,
+}
+
+/// Body Sensor Location characteristic value (HRS v1.0, section 3.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodySensorLocation {
+    Other,
+    Chest,
+    Wrist,
+    Finger,
+    Hand,
+    EarLobe,
+    Foot,
+    Reserved(u8),
+}
+
+impl From<u8> for BodySensorLocation {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => BodySensorLocation::Other,
+            1 => BodySensorLocation::Chest,
+            2 => BodySensorLocation::Wrist,
+            3 => BodySensorLocation::Finger,
+            4 => BodySensorLocation::Hand,
+            5 => BodySensorLocation::EarLobe,
+            6 => BodySensorLocation::Foot,
+            value => BodySensorLocation::Reserved(value),
+        }
+    }
+}
+
+impl From<BodySensorLocation> for u8 {
+    fn from(location: BodySensorLocation) -> Self {
+        match location {
+            BodySensorLocation::Other => 0,
+            BodySensorLocation::Chest => 1,
+            BodySensorLocation::Wrist => 2,
+            BodySensorLocation::Finger => 3,
+            BodySensorLocation::Hand => 4,
+            BodySensorLocation::EarLobe => 5,
+            BodySensorLocation::Foot => 6,
+            BodySensorLocation::Reserved(value) => value,
+        }
+    }
+}
+
+/// Heart Rate Measurement characteristic value (HRS v1.0, section 3.1).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeartRateMeasurement {
+    pub heart_rate: u16,
+    pub value_format: ValueFormat,
+    pub sensor_contact: SensorContact,
+    /// Accumulated energy expended, in kilo Joules.
+    pub energy_expended: Option<u16>,
+    /// RR-Intervals, oldest first, in 1/1024 seconds.
+    pub rr_intervals: Vec<u16>,
+}
+
+impl HeartRateMeasurement {
+    pub fn new(heart_rate: u16) -> Self {
+        Self {
+            heart_rate,
+            value_format: if heart_rate > u8::MAX as u16 {
+                ValueFormat::Uint16
+            } else {
+                ValueFormat::Uint8
+            },
+            sensor_contact: SensorContact::NotSupported,
+            energy_expended: None,
+            rr_intervals: Vec::new(),
+        }
+    }
+
+    /// RR-Intervals that fit in [MAX_PAYLOAD_LENGTH] along with the other fields.
+    pub fn rr_interval_room(&self) -> usize {
+        let mut length = 1;
+        length += match self.encoded_value_format() {
+            ValueFormat::Uint8 => 1,
+            ValueFormat::Uint16 => 2,
+        };
+        if self.energy_expended.is_some() {
+            length += 2;
+        }
+        (MAX_PAYLOAD_LENGTH - length) / 2
+    }
+
+    /// Splits the measurement into as many as needed for every RR-Interval to fit in its
+    /// notification, oldest RR-Intervals first.
+    pub fn split_rr_intervals(self) -> Vec<HeartRateMeasurement> {
+        if self.rr_intervals.is_empty() {
+            return vec![self];
+        }
+        self.rr_intervals
+            .chunks(self.rr_interval_room())
+            .map(|rr_intervals| HeartRateMeasurement {
+                rr_intervals: rr_intervals.to_vec(),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Encodes the measurement. Values above 255 are always sent as UINT16, and only the most recent
+    /// RR-Intervals that fit in [MAX_PAYLOAD_LENGTH] are included, see
+    /// [HeartRateMeasurement::split_rr_intervals] to send all of them.
+    pub fn to_vector(&self) -> Vec<u8> {
+        let value_format = self.encoded_value_format();
+
+        let mut flags = match self.sensor_contact {
+            SensorContact::NotSupported => 0,
+            SensorContact::NotDetected => FLAG_SENSOR_CONTACT_NOT_DETECTED,
+            SensorContact::Detected => FLAG_SENSOR_CONTACT_DETECTED,
+        };
+
+        let mut vector = vec![0];
+        match value_format {
+            ValueFormat::Uint8 => vector.push(self.heart_rate as u8),
+            ValueFormat::Uint16 => {
+                flags |= FLAG_VALUE_FORMAT_UINT16;
+                vector.extend_from_slice(&self.heart_rate.to_le_bytes());
+            }
+        }
+
+        if let Some(energy_expended) = self.energy_expended {
+            flags |= FLAG_ENERGY_EXPENDED;
+            vector.extend_from_slice(&energy_expended.to_le_bytes());
+        }
+
+        if !self.rr_intervals.is_empty() {
+            flags |= FLAG_RR_INTERVAL;
+            let skip = self
+                .rr_intervals
+                .len()
+                .saturating_sub(self.rr_interval_room());
+            for rr_interval in &self.rr_intervals[skip..] {
+                vector.extend_from_slice(&rr_interval.to_le_bytes());
+            }
+        }
+
+        vector[0] = flags;
+        vector
+    }
+
+    fn encoded_value_format(&self) -> ValueFormat {
+        if self.heart_rate > u8::MAX as u16 {
+            ValueFormat::Uint16
+        } else {
+            self.value_format
+        }
+    }
+
+    pub fn from_vector(vector: &[u8]) -> Result<Self> {
+        let (flags, mut data) = match vector.split_first() {
+            Some((flags, data)) => (*flags, data),
+            None => return Err(anyhow::Error::msg("Empty heart rate measurement.")),
+        };
+
+        let (heart_rate, value_format) = if flags & FLAG_VALUE_FORMAT_UINT16 != 0 {
+            (
+                take_u16(&mut data, "heart rate value")?,
+                ValueFormat::Uint16,
+            )
+        } else {
+            match data.split_first() {
+                Some((value, rest)) => {
+                    data = rest;
+                    (*value as u16, ValueFormat::Uint8)
+                }
+                None => {
+                    return Err(anyhow::Error::msg(
+                        "Truncated heart rate measurement (heart rate value).",
+                    ))
+                }
+            }
+        };
+
+        let sensor_contact = match flags & FLAG_SENSOR_CONTACT_MASK {
+            FLAG_SENSOR_CONTACT_NOT_DETECTED => SensorContact::NotDetected,
+            FLAG_SENSOR_CONTACT_DETECTED => SensorContact::Detected,
+            _ => SensorContact::NotSupported,
+        };
+
+        let energy_expended = if flags & FLAG_ENERGY_EXPENDED != 0 {
+            Some(take_u16(&mut data, "energy expended")?)
+        } else {
+            None
+        };
+
+        let mut rr_intervals = Vec::new();
+        if flags & FLAG_RR_INTERVAL != 0 {
+            while !data.is_empty() {
+                rr_intervals.push(take_u16(&mut data, "RR-Interval")?);
+            }
+        }
+
+        Ok(Self {
+            heart_rate,
+            value_format,
+            sensor_contact,
+            energy_expended,
+            rr_intervals,
+        })
+    }
+
+    /// RR-Intervals converted to milliseconds.
+    pub fn rr_intervals_ms(&self) -> Vec<u32> {
+        self.rr_intervals
+            .iter()
+            .map(|rr_interval| {
+                (*rr_interval as u32 * 1000 + RR_INTERVAL_RESOLUTION as u32 / 2)
+                    / RR_INTERVAL_RESOLUTION as u32
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for HeartRateMeasurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#3} bpm", self.heart_rate)?;
+        match self.sensor_contact {
+            SensorContact::NotSupported => (),
+            SensorContact::NotDetected => write!(f, ", no contact")?,
+            SensorContact::Detected => write!(f, ", contact")?,
+        }
+        if let Some(energy_expended) = self.energy_expended {
+            write!(f, ", {} kJ", energy_expended)?;
+        }
+        if !self.rr_intervals.is_empty() {
+            write!(f, ", RR {:?} ms", self.rr_intervals_ms())?;
+        }
+        Ok(())
+    }
+}
+
+fn take_u16(data: &mut &[u8], field: &str) -> Result<u16> {
+    if data.len() < 2 {
+        return Err(anyhow::Error::msg(format!(
+            "Truncated heart rate measurement ({}).",
+            field
+        )));
+    }
+    let value = u16::from_le_bytes([data[0], data[1]]);
+    *data = &data[2..];
+    Ok(value)
+}
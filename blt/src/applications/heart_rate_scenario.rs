@@ -0,0 +1,256 @@
+use crate::heart_rate_measurement::SensorContact;
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Beat-to-beat variation used when a scenario does not define its own.
+const DEFAULT_RR_INTERVAL_JITTER: f64 = 0.03;
+
+/// Named scenarios, see [HeartRateScenario::from_name].
+pub const SCENARIO_NAMES: [&str; 7] = [
+    "rest",
+    "exercise_ramp",
+    "recovery",
+    "tachycardia_burst",
+    "bradycardia",
+    "signal_loss",
+    "arrhythmic_rr",
+];
+
+/// Heart rate and sensor contact from `at` onwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    pub at: Duration,
+    pub heart_rate: u16,
+    pub sensor_contact: SensorContact,
+}
+
+/// Reproducible heart rate pattern: the heart rate is linearly interpolated between keyframes, the
+/// sensor contact is the one of the latest keyframe, and the last keyframe holds once the scenario
+/// is over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeartRateScenario {
+    pub name: String,
+    pub keyframes: Vec<Keyframe>,
+    /// Maximum beat-to-beat variation of the emulated RR-Intervals.
+    pub rr_interval_jitter: f64,
+}
+
+impl HeartRateScenario {
+    pub fn new(name: &str, keyframes: Vec<Keyframe>) -> Result<Self> {
+        if keyframes.is_empty() {
+            return Err(anyhow::Error::msg(format!(
+                "Scenario '{}' has no keyframes.",
+                name
+            )));
+        }
+        if keyframes.windows(2).any(|pair| pair[0].at > pair[1].at) {
+            return Err(anyhow::Error::msg(format!(
+                "Scenario '{}' keyframes are not sorted by time.",
+                name
+            )));
+        }
+        if keyframes.iter().any(|keyframe| keyframe.heart_rate == 0) {
+            return Err(anyhow::Error::msg(format!(
+                "Scenario '{}' has a heart rate of 0 bpm.",
+                name
+            )));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            keyframes,
+            rr_interval_jitter: DEFAULT_RR_INTERVAL_JITTER,
+        })
+    }
+
+    pub fn with_rr_interval_jitter(mut self, rr_interval_jitter: f64) -> Self {
+        self.rr_interval_jitter = rr_interval_jitter;
+        self
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let scenario = match name.to_lowercase().as_str() {
+            "rest" => Self::from_table(name, &[(0, 62, true), (120, 66, true), (240, 60, true)]),
+            "exercise_ramp" => Self::from_table(
+                name,
+                &[
+                    (0, 70, true),
+                    (60, 75, true),
+                    (600, 165, true),
+                    (900, 170, true),
+                ],
+            ),
+            "recovery" => Self::from_table(
+                name,
+                &[
+                    (0, 170, true),
+                    (60, 140, true),
+                    (180, 110, true),
+                    (420, 85, true),
+                    (600, 75, true),
+                ],
+            ),
+            "tachycardia_burst" => Self::from_table(
+                name,
+                &[
+                    (0, 80, true),
+                    (60, 82, true),
+                    (65, 185, true),
+                    (125, 180, true),
+                    (130, 85, true),
+                    (240, 80, true),
+                ],
+            ),
+            "bradycardia" => {
+                Self::from_table(name, &[(0, 48, true), (180, 44, true), (360, 46, true)])
+            }
+            "signal_loss" => Self::from_table(
+                name,
+                &[
+                    (0, 75, true),
+                    (60, 76, false),
+                    (90, 76, true),
+                    (180, 78, false),
+                    (185, 78, true),
+                    (300, 75, true),
+                ],
+            ),
+            "arrhythmic_rr" => Self::from_table(name, &[(0, 78, true), (600, 82, true)])
+                .with_rr_interval_jitter(0.35),
+            _ => return None,
+        };
+
+        Some(scenario)
+    }
+
+    /// Parses a scenario file. Each line is `<seconds> <bpm> [contact|no_contact]` (contact by
+    /// default), and `rr_jitter <fraction>` overrides the RR-Interval jitter. `#` starts a comment.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|error| {
+            anyhow::Error::msg(format!(
+                "Unable to read scenario file '{}': {}",
+                path.display(),
+                error
+            ))
+        })?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.display().to_string());
+
+        Self::parse(&name, &content)
+    }
+
+    pub fn parse(name: &str, content: &str) -> Result<Self> {
+        let mut keyframes = Vec::new();
+        let mut rr_interval_jitter = DEFAULT_RR_INTERVAL_JITTER;
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| {
+                anyhow::Error::msg(format!(
+                    "Scenario '{}', line {}: {} ('{}').",
+                    name,
+                    index + 1,
+                    message,
+                    line
+                ))
+            };
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["rr_jitter", value] => {
+                    rr_interval_jitter = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|value| (0.0..1.0).contains(value))
+                        .ok_or_else(|| error("RR-Interval jitter must be in [0, 1)"))?;
+                }
+                [at, heart_rate, sensor_contact @ ..] if sensor_contact.len() <= 1 => {
+                    let at = at
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(|at| Duration::try_from_secs_f64(at).ok())
+                        .ok_or_else(|| error("invalid time"))?;
+                    let heart_rate = heart_rate
+                        .parse::<u16>()
+                        .map_err(|_| error("invalid heart rate"))?;
+                    let sensor_contact = match sensor_contact.first() {
+                        None | Some(&"contact") => SensorContact::Detected,
+                        Some(&"no_contact") => SensorContact::NotDetected,
+                        Some(_) => {
+                            return Err(error("sensor contact must be contact or no_contact"))
+                        }
+                    };
+                    keyframes.push(Keyframe {
+                        at,
+                        heart_rate,
+                        sensor_contact,
+                    });
+                }
+                _ => return Err(error("expected '<seconds> <bpm> [contact|no_contact]'")),
+            }
+        }
+
+        Ok(Self::new(name, keyframes)?.with_rr_interval_jitter(rr_interval_jitter))
+    }
+
+    /// Heart rate and sensor contact `elapsed` after the scenario started.
+    pub fn at(&self, elapsed: Duration) -> (u16, SensorContact) {
+        let next = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.at > elapsed);
+        match next {
+            None => {
+                let last = self.keyframes.last().unwrap();
+                (last.heart_rate, last.sensor_contact)
+            }
+            Some(0) => {
+                let first = &self.keyframes[0];
+                (first.heart_rate, first.sensor_contact)
+            }
+            Some(next) => {
+                let (from, to) = (&self.keyframes[next - 1], &self.keyframes[next]);
+                let progress = (elapsed - from.at).as_secs_f64() / (to.at - from.at).as_secs_f64();
+                let heart_rate = from.heart_rate as f64
+                    + (to.heart_rate as f64 - from.heart_rate as f64) * progress;
+                (heart_rate.round() as u16, from.sensor_contact)
+            }
+        }
+    }
+
+    fn from_table(name: &str, table: &[(u64, u16, bool)]) -> Self {
+        let keyframes = table
+            .iter()
+            .map(|(at, heart_rate, contact)| Keyframe {
+                at: Duration::from_secs(*at),
+                heart_rate: *heart_rate,
+                sensor_contact: if *contact {
+                    SensorContact::Detected
+                } else {
+                    SensorContact::NotDetected
+                },
+            })
+            .collect();
+        Self::new(name, keyframes).unwrap()
+    }
+
+    /// Resolves a scenario given either its name or the path of a scenario file.
+    pub fn load(name_or_path: &str) -> Result<Self> {
+        match Self::from_name(name_or_path) {
+            Some(scenario) => Ok(scenario),
+            None if Path::new(name_or_path).is_file() => Self::from_file(Path::new(name_or_path)),
+            None => Err(anyhow::Error::msg(format!(
+                "Unknown heart rate scenario '{}' (available: {}).",
+                name_or_path,
+                SCENARIO_NAMES.join(", ")
+            ))),
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::blt_application::control_c_handler;
+use crate::capture::{Capture, CaptureSender};
+use crate::heart_rate::{measurement_capture, HeartRateEmulator};
+use crate::heart_rate_scenario::HeartRateScenario;
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::interval;
+use uuid::{Builder, Variant, Version};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+const INITIAL_HEART_RATE_MEASURE: u16 = 80;
+
+/// Value of the generated captures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockPayload {
+    /// A random (version 4) UUID.
+    Uuid,
+    /// An emulated Heart Rate Measurement, hex encoded as the heart rate client sends them.
+    HeartRate,
+}
+
+impl FromStr for MockPayload {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.to_lowercase().as_str() {
+            "uuid" => Ok(MockPayload::Uuid),
+            "heart_rate" => Ok(MockPayload::HeartRate),
+            _ => Err(anyhow::Error::msg(format!(
+                "Unknown mock payload '{}' (available: uuid, heart_rate).",
+                input
+            ))),
+        }
+    }
+}
+
+/// Generates captures without a band, so phonendo_manager can be developed and tested on its own.
+pub struct MockCapture {
+    interval: Duration,
+    payload: MockPayload,
+    scenario: Option<HeartRateScenario>,
+    seed: u64,
+}
+
+impl MockCapture {
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_payload(mut self, payload: MockPayload) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Scenario played by the [MockPayload::HeartRate] payload.
+    pub fn with_scenario(mut self, scenario: HeartRateScenario) -> Self {
+        self.scenario = Some(scenario);
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn generator(&self) -> MockCaptureGenerator {
+        let heart_rate_emulator = match (&self.payload, &self.scenario) {
+            (MockPayload::Uuid, _) => None,
+            (MockPayload::HeartRate, Some(scenario)) => {
+                Some(HeartRateEmulator::with_scenario(scenario.clone()))
+            }
+            (MockPayload::HeartRate, None) => {
+                Some(HeartRateEmulator::new(INITIAL_HEART_RATE_MEASURE))
+            }
+        };
+
+        MockCaptureGenerator {
+            interval: self.interval,
+            rng: StdRng::seed_from_u64(self.seed),
+            heart_rate_emulator: heart_rate_emulator.map(|emulator| emulator.with_seed(self.seed)),
+        }
+    }
+
+    /// Sends a capture every interval until Ctrl+C is pressed or the captures are not accepted.
+    pub async fn start(&self, capture_sender: CaptureSender) -> Result<()> {
+        println!(
+            "Generating {:?} captures every {} ms. Random seed: {}. Press Ctrl+C to quit.",
+            self.payload,
+            self.interval.as_millis(),
+            self.seed
+        );
+
+        let mut generator = self.generator();
+        let mut interval = interval(self.interval);
+        let mut receiver = control_c_handler();
+        loop {
+            tokio::select! {
+                _ = receiver.recv() => break,
+                _ = interval.tick() => {
+                    let capture = generator.next_capture();
+                    println!("Simulate capture {:?}.", capture);
+                    if !capture_sender.send(capture) {
+                        println!("Capture channel closed.");
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for MockCapture {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_INTERVAL,
+            payload: MockPayload::Uuid,
+            scenario: None,
+            seed: rand::random(),
+        }
+    }
+}
+
+pub struct MockCaptureGenerator {
+    interval: Duration,
+    rng: StdRng,
+    heart_rate_emulator: Option<HeartRateEmulator>,
+}
+
+impl MockCaptureGenerator {
+    pub fn next_capture(&mut self) -> Capture {
+        match self.heart_rate_emulator.as_mut() {
+            Some(emulator) => {
+                let measurement = emulator.advance(self.interval);
+                measurement_capture(&measurement.to_vector(), &measurement)
+            }
+            None => Capture::new(
+                Builder::from_bytes(self.rng.gen())
+                    .set_variant(Variant::RFC4122)
+                    .set_version(Version::Random)
+                    .build()
+                    .to_string(),
+            ),
+        }
+    }
+}
@@ -1,5 +1,9 @@
 pub mod adder;
 pub mod application_factory;
 pub mod cts;
+pub mod current_time;
 pub mod heart_rate;
+pub mod heart_rate_measurement;
+pub mod heart_rate_scenario;
+pub mod mock_capture;
 pub mod ping_pong;
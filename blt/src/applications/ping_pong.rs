@@ -1,14 +1,15 @@
+use crate::backend::local::CharacteristicControlEvent;
+use crate::backend::{BltCharacteristic, CharacteristicReader, CharacteristicWriter};
+use crate::capture::CaptureSender;
 use crate::{
     blt_application, ApplicationDescriptor, ApplicationHandler, BltApplication, GattApplication,
 };
 use anyhow::Result;
 use async_trait::async_trait;
-use bluer::gatt::remote::Characteristic;
-use bluer::gatt::{local::CharacteristicControlEvent, CharacteristicReader, CharacteristicWriter};
-use bluer::Uuid;
 use futures::{future, pin_mut, StreamExt};
 use std::collections::HashMap;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use uuid::Uuid;
 
 include!("../../../resources/services/ping_pong.inc");
 
@@ -106,7 +107,8 @@ impl BltApplication for PingPong {
 
     async fn exercise_characteristics(
         &self,
-        characteristics: &HashMap<Uuid, Characteristic>,
+        characteristics: &HashMap<Uuid, Box<dyn BltCharacteristic>>,
+        _capture_sender: &CaptureSender,
     ) -> Result<()> {
         for uuid in characteristics.keys() {
             let (mut write_io, mut notify_io) =
@@ -116,13 +118,13 @@ impl BltApplication for PingPong {
                 let data: Vec<u8> = message.as_bytes().to_vec();
 
                 println!("\n>> Command:  {:?}.", message);
-                write_io.write_all(&data).await.expect("Write failed.");
+                write_io.write_all(&data).await?;
                 let (aux_notify_io, result) =
                     blt_application::read_from_characteristic(notify_io).await;
 
                 notify_io = aux_notify_io;
 
-                let buffer = result.expect("Read failed.");
+                let buffer = result?;
                 println!(
                     "<< Response: {:?}.",
                     String::from_utf8_lossy(&buffer).trim()
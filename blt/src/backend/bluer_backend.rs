@@ -0,0 +1,721 @@
+use crate::adapter_manager::{AdapterCapability, AdapterInfo, AdapterSelector};
+use crate::backend::local::{
+    Application, Characteristic, CharacteristicControlEvent, CharacteristicControlHandle,
+    CharacteristicRead, CharacteristicReadRequest, CharacteristicWrite,
+    CharacteristicWriteIoRequest, CharacteristicWriteMethod, CharacteristicWriteRequest,
+};
+use crate::backend::{
+    presence_stream, AdapterEvent, AdapterEvents, AdapterPresence, AdvertisementHandle,
+    AgentHandle, ApplicationHandle, BltAdapter, BltCharacteristic, BltDevice, BltService,
+    CharacteristicReader, CharacteristicWriter, PresenceEvents,
+};
+use crate::config::AdapterSettings;
+use crate::pairing_agent::{PairingAgent, PairingAnswer, PairingRequest, PairingRequestKind};
+use anyhow::Result;
+use async_trait::async_trait;
+use bluer::adv::{Advertisement, SecondaryChannel};
+use bluer::agent::{Agent, ReqError, ReqResult};
+use bluer::gatt::local as bluer_local;
+use bluer::gatt::remote;
+use bluer::{Adapter, Address, Device, SessionEvent};
+use futures::{future, Future, StreamExt};
+use std::collections::{BTreeSet, HashSet};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+pub struct BluerAdapter {
+    shared: Arc<SharedAdapter>,
+    /// Taken once the state is restored.
+    previous_state: Mutex<Option<AdapterState>>,
+    presence: broadcast::Sender<AdapterPresence>,
+    watcher: JoinHandle<()>,
+}
+
+/// Adapter in use, replaced when the adapter comes back after being removed.
+struct SharedAdapter {
+    session: bluer::Session,
+    selector: AdapterSelector,
+    pairable_timeout: u32,
+    adapter: RwLock<Adapter>,
+    /// Pairing agent, registered again when BlueZ restarts.
+    agent: Mutex<Option<RegisteredAgent>>,
+}
+
+struct RegisteredAgent {
+    id: u64,
+    agent: PairingAgent,
+    _handle: bluer::agent::AgentHandle,
+}
+
+impl SharedAdapter {
+    fn adapter(&self) -> Adapter {
+        self.adapter.read().unwrap().clone()
+    }
+
+    /// Uses the adapter named `name` if it matches the selector, powering it on.
+    async fn acquire(&self, name: &str) -> Result<bool> {
+        let adapter = self.session.adapter(name)?;
+        if !self
+            .selector
+            .matches(&BluerAdapter::adapter_info(&adapter).await?)
+        {
+            return Ok(false);
+        }
+        adapter.set_powered(true).await?;
+        adapter.set_pairable_timeout(self.pairable_timeout).await?;
+        *self.adapter.write().unwrap() = adapter;
+        Ok(true)
+    }
+
+    async fn register_agent(self: &Arc<Self>, id: u64, agent: PairingAgent) -> Result<()> {
+        let handle = self
+            .session
+            .register_agent(bluer_agent(AgentHandler {
+                agent: agent.clone(),
+                shared: self.clone(),
+            }))
+            .await?;
+        *self.agent.lock().unwrap() = Some(RegisteredAgent {
+            id,
+            agent,
+            _handle: handle,
+        });
+        Ok(())
+    }
+
+    /// Follows the adapters added and removed, reporting when the adapter in use goes away and
+    /// when it, or another one matching the selector, is back. The adapter being added again
+    /// while in use means that BlueZ restarted.
+    async fn watch(self: Arc<Self>, presence: broadcast::Sender<AdapterPresence>) {
+        let mut events = match self.session.events().await {
+            Ok(events) => Box::pin(events),
+            Err(error) => {
+                println!("Unable to watch Bluetooth adapters: {}.", error);
+                return;
+            }
+        };
+
+        let mut present = true;
+        while let Some(event) = events.next().await {
+            match event {
+                SessionEvent::AdapterRemoved(name) if present && name == self.adapter().name() => {
+                    println!("Bluetooth adapter {} removed.", name);
+                    present = false;
+                    let _ = presence.send(AdapterPresence::Lost);
+                }
+                SessionEvent::AdapterAdded(name) => {
+                    let restarted = present && name == self.adapter().name();
+                    if present && !restarted {
+                        continue;
+                    }
+                    match self.acquire(&name).await {
+                        Ok(true) => {
+                            if restarted {
+                                println!("Bluetooth adapter {} reset by BlueZ.", name);
+                                let _ = presence.send(AdapterPresence::Lost);
+                            }
+                            self.restore_agent().await;
+                            println!("Bluetooth adapter {} available again.", name);
+                            present = true;
+                            let _ = presence.send(AdapterPresence::Restored);
+                        }
+                        Ok(false) => (),
+                        Err(error) => {
+                            println!("Unable to use Bluetooth adapter {}: {}.", name, error)
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    async fn restore_agent(self: &Arc<Self>) {
+        let agent = self
+            .agent
+            .lock()
+            .unwrap()
+            .take()
+            .map(|registered| (registered.id, registered.agent));
+        if let Some((id, agent)) = agent {
+            if let Err(error) = self.register_agent(id, agent).await {
+                println!("Unable to register the pairing agent again: {}.", error);
+            }
+        }
+    }
+}
+
+/// Unregisters the agent when dropped, unless it has been replaced.
+struct AgentRegistration {
+    shared: Arc<SharedAdapter>,
+    id: u64,
+}
+
+impl Drop for AgentRegistration {
+    fn drop(&mut self) {
+        let mut agent = self.shared.agent.lock().unwrap();
+        if matches!(&*agent, Some(registered) if registered.id == self.id) {
+            *agent = None;
+        }
+    }
+}
+
+/// Adapter properties changed by the reader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AdapterState {
+    /// Adapter the state was read from, hot-plug may replace it with another one.
+    address: Address,
+    powered: bool,
+    pairable: bool,
+    pairable_timeout: u32,
+}
+
+impl AdapterState {
+    async fn read(adapter: &Adapter) -> Result<Self> {
+        Ok(Self {
+            address: adapter.address().await?,
+            powered: adapter.is_powered().await?,
+            pairable: adapter.is_pairable().await?,
+            pairable_timeout: adapter.pairable_timeout().await?,
+        })
+    }
+
+    async fn restore(&self, adapter: &Adapter) -> Result<()> {
+        if AdapterState::read(adapter).await? != *self {
+            adapter.set_pairable_timeout(self.pairable_timeout).await?;
+            adapter.set_pairable(self.pairable).await?;
+            adapter.set_powered(self.powered).await?;
+        }
+        Ok(())
+    }
+}
+
+impl BluerAdapter {
+    /// Uses the adapter selected by `settings`, powering it on.
+    pub async fn new(settings: &AdapterSettings) -> Result<Self> {
+        let session = bluer::Session::new().await?;
+        let adapters = BluerAdapter::adapter_infos(&session).await?;
+        let selector = AdapterSelector::from_settings(settings)?;
+        let adapter = session.adapter(&selector.select(&adapters)?.name)?;
+
+        let previous_state = if settings.restore_state {
+            Some(AdapterState::read(&adapter).await?)
+        } else {
+            None
+        };
+        adapter.set_powered(true).await?;
+        adapter
+            .set_pairable_timeout(settings.pairable_timeout)
+            .await?;
+
+        let shared = Arc::new(SharedAdapter {
+            session,
+            selector,
+            pairable_timeout: settings.pairable_timeout,
+            adapter: RwLock::new(adapter),
+            agent: Mutex::new(None),
+        });
+        let (presence, _) = broadcast::channel(16);
+        let watcher = tokio::spawn(shared.clone().watch(presence.clone()));
+
+        Ok(Self {
+            shared,
+            previous_state: Mutex::new(previous_state),
+            presence,
+            watcher,
+        })
+    }
+
+    /// Adapter in use, it changes if the adapter is removed and another one is used.
+    pub fn adapter(&self) -> Adapter {
+        self.shared.adapter()
+    }
+
+    /// Adapters present, to be selected with an [AdapterSelector].
+    pub async fn available() -> Result<Vec<AdapterInfo>> {
+        BluerAdapter::adapter_infos(&bluer::Session::new().await?).await
+    }
+
+    async fn adapter_infos(session: &bluer::Session) -> Result<Vec<AdapterInfo>> {
+        let mut adapters = Vec::new();
+        for name in session.adapter_names().await? {
+            adapters.push(BluerAdapter::adapter_info(&session.adapter(&name)?).await?);
+        }
+        Ok(adapters)
+    }
+
+    async fn adapter_info(adapter: &Adapter) -> Result<AdapterInfo> {
+        let mut capabilities = BTreeSet::new();
+        // Adapters without LE advertising support don't provide these properties.
+        if adapter
+            .supported_advertising_instances()
+            .await
+            .is_ok_and(|instances| instances > 0)
+        {
+            capabilities.insert(AdapterCapability::Advertising);
+        }
+        let channels = adapter
+            .supported_advertising_secondary_channels()
+            .await
+            .unwrap_or_default();
+        if channels.contains(&SecondaryChannel::TwoM) {
+            capabilities.insert(AdapterCapability::Le2M);
+        }
+        if channels.contains(&SecondaryChannel::Coded) {
+            capabilities.insert(AdapterCapability::LeCoded);
+        }
+
+        Ok(AdapterInfo {
+            name: adapter.name().to_string(),
+            address: adapter.address().await?,
+            capabilities,
+        })
+    }
+
+    /// Restores the powered and pairable state the adapter had before being used, if
+    /// `restore_state` is set. It is done once, when the adapter is shut down.
+    pub async fn restore_state(&self) -> Result<()> {
+        let previous_state = self.previous_state.lock().unwrap().take();
+        if let Some(state) = previous_state {
+            let adapter = self.adapter();
+            let address = adapter.address().await?;
+            if address != state.address {
+                println!(
+                    "Bluetooth adapter {} not restored, its state was read from {}.",
+                    address, state.address
+                );
+                return Ok(());
+            }
+            state.restore(&adapter).await?;
+            println!(
+                "Bluetooth adapter {} restored (powered: {}, pairable: {}).",
+                adapter.name(),
+                state.powered,
+                state.pairable
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BluerAdapter {
+    fn drop(&mut self) {
+        self.watcher.abort();
+        // Restoring needs to await, which can't be done here.
+        if self.previous_state.get_mut().unwrap().is_some() {
+            println!(
+                "Bluetooth adapter {} not restored, it was dropped before being shut down.",
+                self.adapter().name()
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl BltAdapter for BluerAdapter {
+    fn name(&self) -> String {
+        self.adapter().name().to_string()
+    }
+
+    async fn address(&self) -> Result<Address> {
+        Ok(self.adapter().address().await?)
+    }
+
+    fn presence_events(&self) -> PresenceEvents {
+        presence_stream(self.presence.subscribe())
+    }
+
+    async fn serve_gatt_application(&self, application: Application) -> Result<ApplicationHandle> {
+        let application = bluer_local::Application {
+            services: application
+                .services
+                .into_iter()
+                .map(|service| bluer_local::Service {
+                    uuid: service.uuid,
+                    primary: service.primary,
+                    characteristics: service
+                        .characteristics
+                        .into_iter()
+                        .map(bluer_characteristic)
+                        .collect(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+
+        Ok(ApplicationHandle::new(
+            self.adapter().serve_gatt_application(application).await?,
+        ))
+    }
+
+    async fn advertise_gatt_service(
+        &self,
+        service_uuid: Uuid,
+        local_name: &str,
+    ) -> Result<AdvertisementHandle> {
+        Ok(AdvertisementHandle::new(
+            self.adapter()
+                .advertise(Advertisement {
+                    service_uuids: vec![service_uuid].into_iter().collect(),
+                    discoverable: Some(true),
+                    local_name: Some(local_name.to_string()),
+                    ..Default::default()
+                })
+                .await?,
+        ))
+    }
+
+    async fn discover_devices(&self) -> Result<AdapterEvents> {
+        let events = self.adapter().discover_devices().await?;
+        Ok(Box::pin(events.filter_map(|event| {
+            future::ready(match event {
+                bluer::AdapterEvent::DeviceAdded(address) => {
+                    Some(AdapterEvent::DeviceAdded(address))
+                }
+                bluer::AdapterEvent::DeviceRemoved(address) => {
+                    Some(AdapterEvent::DeviceRemoved(address))
+                }
+                _ => None,
+            })
+        })))
+    }
+
+    fn device(&self, address: Address) -> Result<Box<dyn BltDevice>> {
+        Ok(Box::new(BluerDevice {
+            device: self.adapter().device(address)?,
+        }))
+    }
+
+    async fn remove_device(&self, address: Address) -> Result<()> {
+        Ok(self.adapter().remove_device(address).await?)
+    }
+
+    async fn register_pairing_agent(&self, agent: PairingAgent) -> Result<AgentHandle> {
+        let id = rand::random();
+        self.shared.register_agent(id, agent).await?;
+        Ok(AgentHandle::new(AgentRegistration {
+            shared: self.shared.clone(),
+            id,
+        }))
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.restore_state().await
+    }
+}
+
+fn bluer_agent(handler: AgentHandler) -> Agent {
+    Agent {
+        request_default: true,
+        request_pin_code: Some(handler.request(
+            |request| (request.device, PairingRequestKind::PinCode),
+            |answer| match answer {
+                PairingAnswer::PinCode(pin_code) => Ok(pin_code),
+                _ => Err(ReqError::Rejected),
+            },
+        )),
+        display_pin_code: Some(handler.request(
+            |request| {
+                (
+                    request.device,
+                    PairingRequestKind::DisplayPinCode(request.pincode.clone()),
+                )
+            },
+            accepted,
+        )),
+        request_passkey: Some(handler.request(
+            |request| (request.device, PairingRequestKind::Passkey),
+            |answer| match answer {
+                PairingAnswer::Passkey(passkey) => Ok(passkey),
+                _ => Err(ReqError::Rejected),
+            },
+        )),
+        display_passkey: Some(handler.request(
+            |request| {
+                (
+                    request.device,
+                    PairingRequestKind::DisplayPasskey(request.passkey),
+                )
+            },
+            accepted,
+        )),
+        request_confirmation: Some(handler.request(
+            |request| {
+                (
+                    request.device,
+                    PairingRequestKind::Confirmation(request.passkey),
+                )
+            },
+            accepted,
+        )),
+        request_authorization: Some(handler.request(
+            |request| (request.device, PairingRequestKind::Authorization),
+            accepted,
+        )),
+        ..Default::default()
+    }
+}
+
+type AgentFn<R, T> =
+    Box<dyn Fn(R) -> Pin<Box<dyn Future<Output = ReqResult<T>> + Send>> + Send + Sync>;
+
+/// Turns BlueZ agent requests into [PairingRequest]s for a [PairingAgent].
+#[derive(Clone)]
+struct AgentHandler {
+    agent: PairingAgent,
+    shared: Arc<SharedAdapter>,
+}
+
+impl AgentHandler {
+    fn request<R, T>(
+        &self,
+        kind: fn(&R) -> (Address, PairingRequestKind),
+        answer: fn(PairingAnswer) -> ReqResult<T>,
+    ) -> AgentFn<R, T>
+    where
+        R: Send + 'static,
+        T: 'static,
+    {
+        let handler = self.clone();
+        Box::new(move |request| {
+            let handler = handler.clone();
+            let (address, kind) = kind(&request);
+            Box::pin(async move {
+                let request = handler.pairing_request(address, kind).await;
+                answer(handler.agent.answer(&request).await)
+            })
+        })
+    }
+
+    async fn pairing_request(&self, address: Address, kind: PairingRequestKind) -> PairingRequest {
+        let device = self.shared.adapter().device(address).ok();
+        let (name, alias) = match &device {
+            Some(device) => (
+                device.name().await.ok().flatten(),
+                device.alias().await.ok(),
+            ),
+            None => (None, None),
+        };
+        PairingRequest {
+            address,
+            name,
+            alias,
+            kind,
+        }
+    }
+}
+
+fn accepted(answer: PairingAnswer) -> ReqResult<()> {
+    match answer {
+        PairingAnswer::Accept => Ok(()),
+        _ => Err(ReqError::Rejected),
+    }
+}
+
+fn bluer_characteristic(characteristic: Characteristic) -> bluer_local::Characteristic {
+    let io_write = matches!(
+        &characteristic.write,
+        Some(CharacteristicWrite {
+            method: CharacteristicWriteMethod::Io,
+            ..
+        })
+    );
+    let control_handle = if io_write || characteristic.notify.is_some() {
+        let (control, control_handle) = bluer_local::characteristic_control();
+        tokio::spawn(forward_control_events(
+            control,
+            characteristic.control_handle,
+        ));
+        control_handle
+    } else {
+        bluer_local::CharacteristicControlHandle::default()
+    };
+
+    bluer_local::Characteristic {
+        uuid: characteristic.uuid,
+        read: characteristic.read.map(bluer_read),
+        write: characteristic.write.map(bluer_write),
+        notify: characteristic
+            .notify
+            .map(|notify| bluer_local::CharacteristicNotify {
+                notify: notify.notify,
+                indicate: notify.indicate,
+                method: bluer_local::CharacteristicNotifyMethod::Io,
+                ..Default::default()
+            }),
+        control_handle,
+        ..Default::default()
+    }
+}
+
+fn bluer_read(read: CharacteristicRead) -> bluer_local::CharacteristicRead {
+    let fun = Arc::new(read.fun);
+    bluer_local::CharacteristicRead {
+        read: read.read,
+        fun: Box::new(move |req| {
+            fun(CharacteristicReadRequest {
+                offset: req.offset,
+                mtu: req.mtu,
+            })
+        }),
+        ..Default::default()
+    }
+}
+
+fn bluer_write(write: CharacteristicWrite) -> bluer_local::CharacteristicWrite {
+    bluer_local::CharacteristicWrite {
+        write: write.write,
+        write_without_response: write.write_without_response,
+        method: match write.method {
+            CharacteristicWriteMethod::Fun(fun) => {
+                let fun = Arc::new(fun);
+                bluer_local::CharacteristicWriteMethod::Fun(Box::new(move |value, req| {
+                    fun(
+                        value,
+                        CharacteristicWriteRequest {
+                            offset: req.offset,
+                            mtu: req.mtu,
+                        },
+                    )
+                }))
+            }
+            CharacteristicWriteMethod::Io => bluer_local::CharacteristicWriteMethod::Io,
+        },
+        ..Default::default()
+    }
+}
+
+async fn forward_control_events(
+    control: bluer_local::CharacteristicControl,
+    control_handle: CharacteristicControlHandle,
+) {
+    let mut control = Box::pin(control);
+    while let Some(event) = control.next().await {
+        let event = match event {
+            bluer_local::CharacteristicControlEvent::Write(req) => {
+                CharacteristicControlEvent::Write(CharacteristicWriteIoRequest::new(
+                    req.mtu(),
+                    move || {
+                        let reader = req.accept()?;
+                        Ok(CharacteristicReader::new(reader.mtu(), reader))
+                    },
+                ))
+            }
+            bluer_local::CharacteristicControlEvent::Notify(writer) => {
+                CharacteristicControlEvent::Notify(CharacteristicWriter::new(writer.mtu(), writer))
+            }
+        };
+        if !control_handle.send(event) {
+            break;
+        }
+    }
+}
+
+pub struct BluerDevice {
+    device: Device,
+}
+
+#[async_trait]
+impl BltDevice for BluerDevice {
+    fn address(&self) -> Address {
+        self.device.address()
+    }
+
+    async fn name(&self) -> Result<Option<String>> {
+        Ok(self.device.name().await?)
+    }
+
+    async fn alias(&self) -> Result<String> {
+        Ok(self.device.alias().await?)
+    }
+
+    async fn uuids(&self) -> Result<Option<HashSet<Uuid>>> {
+        Ok(self.device.uuids().await?)
+    }
+
+    async fn is_paired(&self) -> Result<bool> {
+        Ok(self.device.is_paired().await?)
+    }
+
+    async fn pair(&self) -> Result<()> {
+        Ok(self.device.pair().await?)
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        Ok(self.device.is_connected().await?)
+    }
+
+    async fn connect(&self) -> Result<()> {
+        Ok(self.device.connect().await?)
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        Ok(self.device.disconnect().await?)
+    }
+
+    async fn services(&self) -> Result<Vec<Box<dyn BltService>>> {
+        Ok(self
+            .device
+            .services()
+            .await?
+            .into_iter()
+            .map(|service| Box::new(BluerService { service }) as Box<dyn BltService>)
+            .collect())
+    }
+}
+
+pub struct BluerService {
+    service: remote::Service,
+}
+
+#[async_trait]
+impl BltService for BluerService {
+    async fn uuid(&self) -> Result<Uuid> {
+        Ok(self.service.uuid().await?)
+    }
+
+    async fn characteristics(&self) -> Result<Vec<Box<dyn BltCharacteristic>>> {
+        Ok(self
+            .service
+            .characteristics()
+            .await?
+            .into_iter()
+            .map(|characteristic| {
+                Box::new(BluerCharacteristic { characteristic }) as Box<dyn BltCharacteristic>
+            })
+            .collect())
+    }
+}
+
+pub struct BluerCharacteristic {
+    characteristic: remote::Characteristic,
+}
+
+#[async_trait]
+impl BltCharacteristic for BluerCharacteristic {
+    async fn uuid(&self) -> Result<Uuid> {
+        Ok(self.characteristic.uuid().await?)
+    }
+
+    async fn read(&self) -> Result<Vec<u8>> {
+        Ok(self.characteristic.read().await?)
+    }
+
+    async fn write(&self, value: &[u8]) -> Result<()> {
+        Ok(self.characteristic.write(value).await?)
+    }
+
+    async fn write_io(&self) -> Result<CharacteristicWriter> {
+        let writer = self.characteristic.write_io().await?;
+        Ok(CharacteristicWriter::new(writer.mtu(), writer))
+    }
+
+    async fn notify_io(&self) -> Result<CharacteristicReader> {
+        let reader = self.characteristic.notify_io().await?;
+        Ok(CharacteristicReader::new(reader.mtu(), reader))
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::Result;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+pub struct CharacteristicReader {
+    mtu: usize,
+    stream: Box<dyn AsyncRead + Send + Sync + Unpin>,
+}
+
+impl CharacteristicReader {
+    pub fn new(mtu: usize, stream: impl AsyncRead + Send + Sync + Unpin + 'static) -> Self {
+        Self {
+            mtu,
+            stream: Box::new(stream),
+        }
+    }
+
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+}
+
+impl AsyncRead for CharacteristicReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+pub struct CharacteristicWriter {
+    mtu: usize,
+    stream: Box<dyn AsyncWrite + Send + Sync + Unpin>,
+}
+
+impl CharacteristicWriter {
+    pub fn new(mtu: usize, stream: impl AsyncWrite + Send + Sync + Unpin + 'static) -> Self {
+        Self {
+            mtu,
+            stream: Box::new(stream),
+        }
+    }
+
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+}
+
+impl AsyncWrite for CharacteristicWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
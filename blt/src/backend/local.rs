@@ -0,0 +1,170 @@
+use crate::backend::{CharacteristicReader, CharacteristicWriter};
+use anyhow::Result;
+use futures::{FutureExt, Stream};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+pub use bluer::gatt::local::ReqError;
+
+pub type ReqResult<T> = std::result::Result<T, ReqError>;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CharacteristicReadRequest {
+    pub offset: u16,
+    pub mtu: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CharacteristicWriteRequest {
+    pub offset: u16,
+    pub mtu: u16,
+}
+
+pub type CharacteristicReadFun = Box<
+    dyn Fn(CharacteristicReadRequest) -> Pin<Box<dyn Future<Output = ReqResult<Vec<u8>>> + Send>>
+        + Send
+        + Sync,
+>;
+
+pub type CharacteristicWriteFun = Box<
+    dyn Fn(
+            Vec<u8>,
+            CharacteristicWriteRequest,
+        ) -> Pin<Box<dyn Future<Output = ReqResult<()>> + Send>>
+        + Send
+        + Sync,
+>;
+
+pub struct CharacteristicRead {
+    pub read: bool,
+    pub fun: CharacteristicReadFun,
+}
+
+impl Default for CharacteristicRead {
+    fn default() -> Self {
+        Self {
+            read: false,
+            fun: Box::new(|_| async move { Err(ReqError::NotSupported) }.boxed()),
+        }
+    }
+}
+
+pub enum CharacteristicWriteMethod {
+    Fun(CharacteristicWriteFun),
+    Io,
+}
+
+impl Default for CharacteristicWriteMethod {
+    fn default() -> Self {
+        Self::Fun(Box::new(|_, _| {
+            async move { Err(ReqError::NotSupported) }.boxed()
+        }))
+    }
+}
+
+#[derive(Default)]
+pub struct CharacteristicWrite {
+    pub write: bool,
+    pub write_without_response: bool,
+    pub method: CharacteristicWriteMethod,
+}
+
+/// Notifications are always delivered through a [CharacteristicWriter] handed
+/// to the application by its [CharacteristicControl].
+#[derive(Default)]
+pub struct CharacteristicNotify {
+    pub notify: bool,
+    pub indicate: bool,
+}
+
+#[derive(Default)]
+pub struct Characteristic {
+    pub uuid: Uuid,
+    pub read: Option<CharacteristicRead>,
+    pub write: Option<CharacteristicWrite>,
+    pub notify: Option<CharacteristicNotify>,
+    pub control_handle: CharacteristicControlHandle,
+}
+
+#[derive(Default)]
+pub struct Service {
+    pub uuid: Uuid,
+    pub primary: bool,
+    pub characteristics: Vec<Characteristic>,
+}
+
+#[derive(Default)]
+pub struct Application {
+    pub services: Vec<Service>,
+}
+
+/// A remote request to start writing via IO.
+pub struct CharacteristicWriteIoRequest {
+    mtu: usize,
+    accept: Box<dyn FnOnce() -> Result<CharacteristicReader> + Send>,
+}
+
+impl CharacteristicWriteIoRequest {
+    pub fn new(
+        mtu: usize,
+        accept: impl FnOnce() -> Result<CharacteristicReader> + Send + 'static,
+    ) -> Self {
+        Self {
+            mtu,
+            accept: Box::new(accept),
+        }
+    }
+
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    pub fn accept(self) -> Result<CharacteristicReader> {
+        (self.accept)()
+    }
+}
+
+pub enum CharacteristicControlEvent {
+    Write(CharacteristicWriteIoRequest),
+    Notify(CharacteristicWriter),
+}
+
+pub struct CharacteristicControl {
+    receiver: mpsc::UnboundedReceiver<CharacteristicControlEvent>,
+}
+
+impl Stream for CharacteristicControl {
+    type Item = CharacteristicControlEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+#[derive(Default)]
+pub struct CharacteristicControlHandle {
+    sender: Option<mpsc::UnboundedSender<CharacteristicControlEvent>>,
+}
+
+impl CharacteristicControlHandle {
+    /// Delivers an event to the application. Returns `false` if nobody is listening.
+    pub fn send(&self, event: CharacteristicControlEvent) -> bool {
+        match &self.sender {
+            Some(sender) => sender.send(event).is_ok(),
+            None => false,
+        }
+    }
+}
+
+pub fn characteristic_control() -> (CharacteristicControl, CharacteristicControlHandle) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    (
+        CharacteristicControl { receiver },
+        CharacteristicControlHandle {
+            sender: Some(sender),
+        },
+    )
+}
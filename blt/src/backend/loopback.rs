@@ -0,0 +1,713 @@
+use crate::backend::local::{
+    Application, CharacteristicControlEvent, CharacteristicControlHandle, CharacteristicNotify,
+    CharacteristicRead, CharacteristicReadRequest, CharacteristicWrite,
+    CharacteristicWriteIoRequest, CharacteristicWriteMethod, CharacteristicWriteRequest, ReqError,
+};
+use crate::backend::{
+    AdapterEvent, AdapterEvents, AdapterPresence, AdvertisementHandle, AgentHandle,
+    ApplicationHandle, BltAdapter, BltCharacteristic, BltDevice, BltService, CharacteristicReader,
+    CharacteristicWriter, PresenceEvents,
+};
+use crate::pairing_agent::{PairingAgent, PairingAnswer, PairingRequest, PairingRequestKind};
+use anyhow::Result;
+use async_trait::async_trait;
+use bluer::Address;
+use futures::stream;
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{broadcast, mpsc};
+use uuid::Uuid;
+
+const LOOPBACK_MTU: usize = 512;
+
+/// In-process radio shared by every [LoopbackAdapter] created on it.
+///
+/// GATT applications served and advertised by one adapter can be discovered and
+/// used by any other adapter on the same bus, without touching BlueZ.
+pub struct LoopbackBus {
+    state: Mutex<BusState>,
+    events: broadcast::Sender<AdapterEvent>,
+    presence: broadcast::Sender<(Address, AdapterPresence)>,
+}
+
+#[derive(Default)]
+struct BusState {
+    next_id: u64,
+    peers: HashMap<Address, Peer>,
+}
+
+#[derive(Default)]
+struct Peer {
+    local_name: Option<String>,
+    advertised_uuids: HashSet<Uuid>,
+    applications: HashMap<u64, Vec<Arc<LoopbackServiceEntry>>>,
+    advertisements: HashMap<u64, (Uuid, String)>,
+    paired: bool,
+    connected: bool,
+    pairing: LoopbackPairing,
+    agent: Option<(u64, PairingAgent)>,
+    /// Adapter unplugged, see [LoopbackAdapter::set_present].
+    removed: bool,
+}
+
+/// How a loopback device authenticates the adapters that pair with it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LoopbackPairing {
+    /// No passkey, the pairing adapter needs no agent.
+    #[default]
+    JustWorks,
+    /// Legacy pairing, the agent must send this PIN code.
+    PinCode(String),
+    /// The device shows this passkey and the agent must send it back.
+    Passkey(u32),
+    /// The agent must confirm that the device shows this passkey.
+    Confirmation(u32),
+}
+
+impl Peer {
+    fn refresh_advertisement(&mut self) {
+        self.advertised_uuids = self
+            .advertisements
+            .values()
+            .map(|(uuid, _)| *uuid)
+            .collect();
+        self.local_name = self
+            .advertisements
+            .values()
+            .next()
+            .map(|(_, name)| name.clone());
+    }
+}
+
+struct LoopbackServiceEntry {
+    uuid: Uuid,
+    characteristics: Vec<Arc<LoopbackCharacteristicEntry>>,
+}
+
+struct LoopbackCharacteristicEntry {
+    uuid: Uuid,
+    read: Option<CharacteristicRead>,
+    write: Option<CharacteristicWrite>,
+    notify: Option<CharacteristicNotify>,
+    control_handle: CharacteristicControlHandle,
+}
+
+impl LoopbackBus {
+    pub fn new() -> Arc<Self> {
+        let (events, _) = broadcast::channel(64);
+        let (presence, _) = broadcast::channel(16);
+        Arc::new(Self {
+            state: Mutex::new(BusState::default()),
+            events,
+            presence,
+        })
+    }
+
+    /// Simulates unplugging the adapter with `address`, or plugging it back. Removing it drops
+    /// its GATT applications, advertisements and connections, as BlueZ does.
+    pub fn set_present(&self, address: Address, present: bool) {
+        let changed = self
+            .with_peer(address, |peer| {
+                if peer.removed != present {
+                    return false;
+                }
+                peer.removed = !present;
+                if !present {
+                    peer.applications.clear();
+                    peer.advertisements.clear();
+                    peer.refresh_advertisement();
+                    peer.connected = false;
+                }
+                true
+            })
+            .unwrap_or(false);
+        if !changed {
+            return;
+        }
+
+        if present {
+            let _ = self.presence.send((address, AdapterPresence::Restored));
+        } else {
+            let _ = self.events.send(AdapterEvent::DeviceRemoved(address));
+            let _ = self.presence.send((address, AdapterPresence::Lost));
+        }
+    }
+
+    fn register_peer(&self) -> Address {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id.to_be_bytes();
+        let address = Address::new([0x02, 0x00, id[4], id[5], id[6], id[7]]);
+        state.peers.insert(address, Peer::default());
+        address
+    }
+
+    fn next_id(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        state.next_id
+    }
+
+    fn with_peer<T>(&self, address: Address, f: impl FnOnce(&mut Peer) -> T) -> Result<T> {
+        let mut state = self.state.lock().unwrap();
+        match state.peers.get_mut(&address) {
+            Some(peer) => Ok(f(peer)),
+            None => Err(anyhow::Error::msg(format!("Device {} not found.", address))),
+        }
+    }
+}
+
+pub struct LoopbackAdapter {
+    bus: Arc<LoopbackBus>,
+    name: String,
+    address: Address,
+}
+
+impl LoopbackAdapter {
+    pub fn new(bus: &Arc<LoopbackBus>, name: &str) -> Self {
+        Self {
+            bus: bus.clone(),
+            name: name.to_string(),
+            address: bus.register_peer(),
+        }
+    }
+
+    /// Simulates unplugging the adapter, or plugging it back, see [LoopbackBus::set_present].
+    pub fn set_present(&self, present: bool) {
+        self.bus.set_present(self.address, present);
+    }
+
+    fn ensure_present(&self) -> Result<()> {
+        if self.bus.with_peer(self.address, |peer| peer.removed)? {
+            Err(anyhow::Error::msg(format!(
+                "Bluetooth adapter {} not available.",
+                self.name
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl LoopbackAdapter {
+    /// Sets how adapters pairing with this one are authenticated.
+    pub fn set_pairing(&self, pairing: LoopbackPairing) {
+        let _ = self
+            .bus
+            .with_peer(self.address, |peer| peer.pairing = pairing);
+    }
+}
+
+impl Drop for LoopbackAdapter {
+    fn drop(&mut self) {
+        self.bus.state.lock().unwrap().peers.remove(&self.address);
+    }
+}
+
+struct ApplicationRegistration {
+    bus: Arc<LoopbackBus>,
+    address: Address,
+    id: u64,
+}
+
+impl Drop for ApplicationRegistration {
+    fn drop(&mut self) {
+        let _ = self.bus.with_peer(self.address, |peer| {
+            peer.applications.remove(&self.id);
+        });
+    }
+}
+
+struct AgentRegistration {
+    bus: Arc<LoopbackBus>,
+    address: Address,
+    id: u64,
+}
+
+impl Drop for AgentRegistration {
+    fn drop(&mut self) {
+        let _ = self.bus.with_peer(self.address, |peer| {
+            if matches!(peer.agent, Some((id, _)) if id == self.id) {
+                peer.agent = None;
+            }
+        });
+    }
+}
+
+struct AdvertisementRegistration {
+    bus: Arc<LoopbackBus>,
+    address: Address,
+    id: u64,
+}
+
+impl Drop for AdvertisementRegistration {
+    fn drop(&mut self) {
+        let _ = self.bus.with_peer(self.address, |peer| {
+            peer.advertisements.remove(&self.id);
+            peer.refresh_advertisement();
+        });
+        let _ = self
+            .bus
+            .events
+            .send(AdapterEvent::DeviceRemoved(self.address));
+    }
+}
+
+#[async_trait]
+impl BltAdapter for LoopbackAdapter {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn address(&self) -> Result<Address> {
+        Ok(self.address)
+    }
+
+    fn presence_events(&self) -> PresenceEvents {
+        let own_address = self.address;
+        Box::pin(stream::unfold(
+            self.bus.presence.subscribe(),
+            move |mut receiver| async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok((address, presence)) if address == own_address => {
+                            return Some((presence, receiver))
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            },
+        ))
+    }
+
+    async fn serve_gatt_application(&self, application: Application) -> Result<ApplicationHandle> {
+        self.ensure_present()?;
+        let services = application
+            .services
+            .into_iter()
+            .map(|service| {
+                Arc::new(LoopbackServiceEntry {
+                    uuid: service.uuid,
+                    characteristics: service
+                        .characteristics
+                        .into_iter()
+                        .map(|characteristic| {
+                            Arc::new(LoopbackCharacteristicEntry {
+                                uuid: characteristic.uuid,
+                                read: characteristic.read,
+                                write: characteristic.write,
+                                notify: characteristic.notify,
+                                control_handle: characteristic.control_handle,
+                            })
+                        })
+                        .collect(),
+                })
+            })
+            .collect();
+
+        let id = self.bus.next_id();
+        self.bus.with_peer(self.address, |peer| {
+            peer.applications.insert(id, services);
+        })?;
+
+        Ok(ApplicationHandle::new(ApplicationRegistration {
+            bus: self.bus.clone(),
+            address: self.address,
+            id,
+        }))
+    }
+
+    async fn advertise_gatt_service(
+        &self,
+        service_uuid: Uuid,
+        local_name: &str,
+    ) -> Result<AdvertisementHandle> {
+        self.ensure_present()?;
+        let id = self.bus.next_id();
+        self.bus.with_peer(self.address, |peer| {
+            peer.advertisements
+                .insert(id, (service_uuid, local_name.to_string()));
+            peer.refresh_advertisement();
+        })?;
+        let _ = self
+            .bus
+            .events
+            .send(AdapterEvent::DeviceAdded(self.address));
+
+        Ok(AdvertisementHandle::new(AdvertisementRegistration {
+            bus: self.bus.clone(),
+            address: self.address,
+            id,
+        }))
+    }
+
+    async fn discover_devices(&self) -> Result<AdapterEvents> {
+        self.ensure_present()?;
+        let receiver = self.bus.events.subscribe();
+        let advertising: Vec<AdapterEvent> = {
+            let state = self.bus.state.lock().unwrap();
+            state
+                .peers
+                .iter()
+                .filter(|(address, peer)| {
+                    **address != self.address && !peer.advertisements.is_empty()
+                })
+                .map(|(address, _)| AdapterEvent::DeviceAdded(*address))
+                .collect()
+        };
+
+        let own_address = self.address;
+        let live = stream::unfold(receiver, move |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(
+                        AdapterEvent::DeviceAdded(address) | AdapterEvent::DeviceRemoved(address),
+                    ) if address == own_address => {}
+                    Ok(event) => return Some((event, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+
+        Ok(Box::pin(futures::StreamExt::chain(
+            stream::iter(advertising),
+            live,
+        )))
+    }
+
+    fn device(&self, address: Address) -> Result<Box<dyn BltDevice>> {
+        self.ensure_present()?;
+        self.bus.with_peer(address, |_| ())?;
+        Ok(Box::new(LoopbackDevice {
+            bus: self.bus.clone(),
+            address,
+            adapter_address: self.address,
+        }))
+    }
+
+    async fn remove_device(&self, address: Address) -> Result<()> {
+        self.bus.with_peer(address, |peer| {
+            peer.paired = false;
+            peer.connected = false;
+        })
+    }
+
+    async fn register_pairing_agent(&self, agent: PairingAgent) -> Result<AgentHandle> {
+        let id = self.bus.next_id();
+        self.bus
+            .with_peer(self.address, |peer| peer.agent = Some((id, agent)))?;
+
+        Ok(AgentHandle::new(AgentRegistration {
+            bus: self.bus.clone(),
+            address: self.address,
+            id,
+        }))
+    }
+}
+
+pub struct LoopbackDevice {
+    bus: Arc<LoopbackBus>,
+    address: Address,
+    /// Adapter the device is seen from, whose agent answers its pairing requests.
+    adapter_address: Address,
+}
+
+#[async_trait]
+impl BltDevice for LoopbackDevice {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn name(&self) -> Result<Option<String>> {
+        self.bus
+            .with_peer(self.address, |peer| peer.local_name.clone())
+    }
+
+    async fn alias(&self) -> Result<String> {
+        let address = self.address;
+        self.bus.with_peer(address, |peer| {
+            peer.local_name
+                .clone()
+                .unwrap_or_else(|| address.to_string().replace(':', "-"))
+        })
+    }
+
+    async fn uuids(&self) -> Result<Option<HashSet<Uuid>>> {
+        self.bus
+            .with_peer(self.address, |peer| Some(peer.advertised_uuids.clone()))
+    }
+
+    async fn is_paired(&self) -> Result<bool> {
+        self.bus.with_peer(self.address, |peer| peer.paired)
+    }
+
+    async fn pair(&self) -> Result<()> {
+        let pairing = self
+            .bus
+            .with_peer(self.address, |peer| peer.pairing.clone())?;
+        let kind = match &pairing {
+            LoopbackPairing::JustWorks => None,
+            LoopbackPairing::PinCode(_) => Some(PairingRequestKind::PinCode),
+            LoopbackPairing::Passkey(_) => Some(PairingRequestKind::Passkey),
+            LoopbackPairing::Confirmation(passkey) => {
+                Some(PairingRequestKind::Confirmation(*passkey))
+            }
+        };
+
+        if let Some(kind) = kind {
+            let agent = self
+                .bus
+                .with_peer(self.adapter_address, |peer| {
+                    peer.agent.as_ref().map(|(_, agent)| agent.clone())
+                })?
+                .ok_or_else(|| {
+                    anyhow::Error::msg(format!("Device {} requires a pairing agent.", self.address))
+                })?;
+            let request = PairingRequest {
+                address: self.address,
+                name: self.name().await?,
+                alias: Some(self.alias().await?),
+                kind,
+            };
+            let authenticated = match (pairing, agent.answer(&request).await) {
+                (LoopbackPairing::PinCode(expected), PairingAnswer::PinCode(pin_code)) => {
+                    expected == pin_code
+                }
+                (LoopbackPairing::Passkey(expected), PairingAnswer::Passkey(passkey)) => {
+                    expected == passkey
+                }
+                (LoopbackPairing::Confirmation(_), PairingAnswer::Accept) => true,
+                _ => false,
+            };
+            if !authenticated {
+                return Err(anyhow::Error::msg(format!(
+                    "Authentication of device {} failed.",
+                    self.address
+                )));
+            }
+        }
+
+        self.bus.with_peer(self.address, |peer| peer.paired = true)
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        self.bus.with_peer(self.address, |peer| peer.connected)
+    }
+
+    async fn connect(&self) -> Result<()> {
+        self.bus
+            .with_peer(self.address, |peer| peer.connected = true)
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        self.bus
+            .with_peer(self.address, |peer| peer.connected = false)
+    }
+
+    async fn services(&self) -> Result<Vec<Box<dyn BltService>>> {
+        let services = self.bus.with_peer(self.address, |peer| {
+            if peer.connected {
+                Some(
+                    peer.applications
+                        .values()
+                        .flatten()
+                        .cloned()
+                        .collect::<Vec<_>>(),
+                )
+            } else {
+                None
+            }
+        })?;
+
+        match services {
+            Some(services) => Ok(services
+                .into_iter()
+                .map(|entry| Box::new(LoopbackService { entry }) as Box<dyn BltService>)
+                .collect()),
+            None => Err(anyhow::Error::msg("Not connected")),
+        }
+    }
+}
+
+pub struct LoopbackService {
+    entry: Arc<LoopbackServiceEntry>,
+}
+
+#[async_trait]
+impl BltService for LoopbackService {
+    async fn uuid(&self) -> Result<Uuid> {
+        Ok(self.entry.uuid)
+    }
+
+    async fn characteristics(&self) -> Result<Vec<Box<dyn BltCharacteristic>>> {
+        Ok(self
+            .entry
+            .characteristics
+            .iter()
+            .map(|entry| {
+                Box::new(LoopbackCharacteristic {
+                    entry: entry.clone(),
+                }) as Box<dyn BltCharacteristic>
+            })
+            .collect())
+    }
+}
+
+pub struct LoopbackCharacteristic {
+    entry: Arc<LoopbackCharacteristicEntry>,
+}
+
+#[async_trait]
+impl BltCharacteristic for LoopbackCharacteristic {
+    async fn uuid(&self) -> Result<Uuid> {
+        Ok(self.entry.uuid)
+    }
+
+    async fn read(&self) -> Result<Vec<u8>> {
+        match &self.entry.read {
+            Some(read) if read.read => Ok((read.fun)(CharacteristicReadRequest {
+                offset: 0,
+                mtu: LOOPBACK_MTU as u16,
+            })
+            .await?),
+            _ => Err(ReqError::NotPermitted.into()),
+        }
+    }
+
+    async fn write(&self, value: &[u8]) -> Result<()> {
+        match &self.entry.write {
+            Some(CharacteristicWrite {
+                write: true,
+                method: CharacteristicWriteMethod::Fun(fun),
+                ..
+            }) => Ok(fun(
+                value.to_vec(),
+                CharacteristicWriteRequest {
+                    offset: 0,
+                    mtu: LOOPBACK_MTU as u16,
+                },
+            )
+            .await?),
+            Some(CharacteristicWrite {
+                method: CharacteristicWriteMethod::Io,
+                ..
+            }) => Err(ReqError::NotSupported.into()),
+            _ => Err(ReqError::NotPermitted.into()),
+        }
+    }
+
+    async fn write_io(&self) -> Result<CharacteristicWriter> {
+        match &self.entry.write {
+            Some(CharacteristicWrite {
+                method: CharacteristicWriteMethod::Io,
+                ..
+            }) => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                let request = CharacteristicWriteIoRequest::new(LOOPBACK_MTU, move || {
+                    Ok(CharacteristicReader::new(
+                        LOOPBACK_MTU,
+                        PacketReader { receiver },
+                    ))
+                });
+                if self
+                    .entry
+                    .control_handle
+                    .send(CharacteristicControlEvent::Write(request))
+                {
+                    Ok(CharacteristicWriter::new(
+                        LOOPBACK_MTU,
+                        PacketWriter {
+                            sender: Some(sender),
+                        },
+                    ))
+                } else {
+                    Err(ReqError::Failed.into())
+                }
+            }
+            _ => Err(ReqError::NotSupported.into()),
+        }
+    }
+
+    async fn notify_io(&self) -> Result<CharacteristicReader> {
+        match &self.entry.notify {
+            Some(notify) if notify.notify || notify.indicate => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                let writer = CharacteristicWriter::new(
+                    LOOPBACK_MTU,
+                    PacketWriter {
+                        sender: Some(sender),
+                    },
+                );
+                if self
+                    .entry
+                    .control_handle
+                    .send(CharacteristicControlEvent::Notify(writer))
+                {
+                    Ok(CharacteristicReader::new(
+                        LOOPBACK_MTU,
+                        PacketReader { receiver },
+                    ))
+                } else {
+                    Err(ReqError::Failed.into())
+                }
+            }
+            _ => Err(ReqError::NotSupported.into()),
+        }
+    }
+}
+
+/// Reading half of a loopback characteristic stream. Each read returns one
+/// written packet, as with BlueZ sequential packet sockets.
+struct PacketReader {
+    receiver: mpsc::UnboundedReceiver<Vec<u8>>,
+}
+
+impl AsyncRead for PacketReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.receiver.poll_recv(cx) {
+            Poll::Ready(Some(packet)) => {
+                let n = packet.len().min(buf.remaining());
+                buf.put_slice(&packet[..n]);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(None) => Poll::Ready(Ok(())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+struct PacketWriter {
+    sender: Option<mpsc::UnboundedSender<Vec<u8>>>,
+}
+
+impl AsyncWrite for PacketWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let n = buf.len().min(LOOPBACK_MTU);
+        match &self.sender {
+            Some(sender) if sender.send(buf[..n].to_vec()).is_ok() => Poll::Ready(Ok(n)),
+            _ => Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.sender = None;
+        Poll::Ready(Ok(()))
+    }
+}
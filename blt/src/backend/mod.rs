@@ -0,0 +1,147 @@
+pub mod bluer_backend;
+pub mod io;
+pub mod local;
+pub mod loopback;
+
+pub use bluer_backend::BluerAdapter;
+pub use io::{CharacteristicReader, CharacteristicWriter};
+pub use loopback::{LoopbackAdapter, LoopbackBus, LoopbackPairing};
+
+use crate::pairing_agent::PairingAgent;
+use anyhow::Result;
+use async_trait::async_trait;
+use bluer::Address;
+use futures::{stream, Stream};
+use local::Application;
+use std::collections::HashSet;
+use std::pin::Pin;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Device discovery events emitted by a [BltAdapter].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdapterEvent {
+    DeviceAdded(Address),
+    DeviceRemoved(Address),
+}
+
+pub type AdapterEvents = Pin<Box<dyn Stream<Item = AdapterEvent> + Send>>;
+
+/// Adapter being removed (unplugged, BlueZ stopped) and available again, emitted by a
+/// [BltAdapter]. Applications, advertisements and discovery have to be started again once the
+/// adapter is restored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdapterPresence {
+    Lost,
+    Restored,
+}
+
+pub type PresenceEvents = Pin<Box<dyn Stream<Item = AdapterPresence> + Send>>;
+
+/// Stream of the presence events sent after subscribing.
+pub(crate) fn presence_stream(receiver: broadcast::Receiver<AdapterPresence>) -> PresenceEvents {
+    Box::pin(stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(presence) => return Some((presence, receiver)),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }))
+}
+
+/// Keeps a GATT application registered while alive.
+pub struct ApplicationHandle {
+    _inner: Box<dyn Send + Sync>,
+}
+
+impl ApplicationHandle {
+    pub fn new(inner: impl Send + Sync + 'static) -> Self {
+        Self {
+            _inner: Box::new(inner),
+        }
+    }
+}
+
+/// Keeps an advertisement active while alive.
+pub struct AdvertisementHandle {
+    _inner: Box<dyn Send + Sync>,
+}
+
+impl AdvertisementHandle {
+    pub fn new(inner: impl Send + Sync + 'static) -> Self {
+        Self {
+            _inner: Box::new(inner),
+        }
+    }
+}
+
+/// Keeps a pairing agent registered while alive.
+pub struct AgentHandle {
+    _inner: Box<dyn Send + Sync>,
+}
+
+impl AgentHandle {
+    pub fn new(inner: impl Send + Sync + 'static) -> Self {
+        Self {
+            _inner: Box::new(inner),
+        }
+    }
+}
+
+/// Bluetooth adapter operations used by servers and clients.
+#[async_trait]
+pub trait BltAdapter: Send + Sync {
+    fn name(&self) -> String;
+    async fn address(&self) -> Result<Address>;
+    /// Events about the adapter being removed and available again.
+    fn presence_events(&self) -> PresenceEvents;
+    async fn serve_gatt_application(&self, application: Application) -> Result<ApplicationHandle>;
+    async fn advertise_gatt_service(
+        &self,
+        service_uuid: Uuid,
+        local_name: &str,
+    ) -> Result<AdvertisementHandle>;
+    async fn discover_devices(&self) -> Result<AdapterEvents>;
+    fn device(&self, address: Address) -> Result<Box<dyn BltDevice>>;
+    async fn remove_device(&self, address: Address) -> Result<()>;
+    /// Registers the agent that answers the pairing requests of devices.
+    async fn register_pairing_agent(&self, agent: PairingAgent) -> Result<AgentHandle>;
+    /// Leaves the adapter as it was before being used, once it is no longer needed.
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Remote device as seen from an adapter.
+#[async_trait]
+pub trait BltDevice: Send + Sync {
+    fn address(&self) -> Address;
+    async fn name(&self) -> Result<Option<String>>;
+    async fn alias(&self) -> Result<String>;
+    async fn uuids(&self) -> Result<Option<HashSet<Uuid>>>;
+    async fn is_paired(&self) -> Result<bool>;
+    async fn pair(&self) -> Result<()>;
+    async fn is_connected(&self) -> Result<bool>;
+    async fn connect(&self) -> Result<()>;
+    async fn disconnect(&self) -> Result<()>;
+    async fn services(&self) -> Result<Vec<Box<dyn BltService>>>;
+}
+
+/// Remote GATT service.
+#[async_trait]
+pub trait BltService: Send + Sync {
+    async fn uuid(&self) -> Result<Uuid>;
+    async fn characteristics(&self) -> Result<Vec<Box<dyn BltCharacteristic>>>;
+}
+
+/// Remote GATT characteristic.
+#[async_trait]
+pub trait BltCharacteristic: Send + Sync {
+    async fn uuid(&self) -> Result<Uuid>;
+    async fn read(&self) -> Result<Vec<u8>>;
+    async fn write(&self, value: &[u8]) -> Result<()>;
+    async fn write_io(&self) -> Result<CharacteristicWriter>;
+    async fn notify_io(&self) -> Result<CharacteristicReader>;
+}
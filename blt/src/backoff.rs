@@ -0,0 +1,45 @@
+use crate::config::ClientSettings;
+use rand::Rng;
+use std::time::Duration;
+
+/// Delays between the attempts to reconnect a device: `initial`, doubled on every attempt up to
+/// `max`. Each delay is cut by a random jitter of up to a half, so that readers losing several
+/// bands at once don't retry in lockstep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    /// Attempts before giving up, none disables reconnection.
+    pub attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration, attempts: u32) -> Self {
+        Self {
+            initial,
+            max,
+            attempts,
+        }
+    }
+
+    /// Backoff of the `client.reconnect_*` settings.
+    pub fn from_settings(settings: &ClientSettings) -> Self {
+        Backoff::new(
+            Duration::from_secs_f64(settings.reconnect_delay),
+            Duration::from_secs_f64(settings.reconnect_max_delay),
+            settings.reconnect_attempts,
+        )
+    }
+
+    /// Delay before `attempt` (from 1), without jitter.
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+
+    /// Delay before `attempt`, between half and the whole of its base delay.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.base_delay(attempt)
+            .mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
@@ -1,10 +1,8 @@
+use crate::backend::{BltCharacteristic, CharacteristicReader, CharacteristicWriter};
+use crate::capture::CaptureSender;
 use crate::{ApplicationDescriptor, ApplicationHandler, GattApplication};
 use anyhow::Result;
 use async_trait::async_trait;
-use bluer::gatt::{
-    remote::Characteristic,
-    {CharacteristicReader, CharacteristicWriter},
-};
 use std::{collections::HashMap, io::Error, time::Duration};
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Receiver;
@@ -12,19 +10,24 @@ use tokio::{io::AsyncReadExt, time::timeout};
 use uuid::Uuid;
 
 #[async_trait]
-pub trait BltApplication {
+pub trait BltApplication: Send + Sync {
     fn application_descriptor(&self) -> ApplicationDescriptor;
     fn gatt_application(&self) -> GattApplication;
+    /// Seed of the random values generated by the application, if it generates any.
+    fn seed(&self) -> Option<u64> {
+        None
+    }
     async fn serve(&self, application_handler: ApplicationHandler) -> Result<ApplicationHandler>;
     async fn exercise_characteristics(
         &self,
-        characteristics: &HashMap<Uuid, Characteristic>,
+        characteristics: &HashMap<Uuid, Box<dyn BltCharacteristic>>,
+        capture_sender: &CaptureSender,
     ) -> Result<()>;
 }
 
 pub async fn characteristic_io(
     uuid: &Uuid,
-    characteristics: &HashMap<Uuid, Characteristic>,
+    characteristics: &HashMap<Uuid, Box<dyn BltCharacteristic>>,
 ) -> Result<(CharacteristicWriter, CharacteristicReader)> {
     if let Some(characteristic) = characteristics.get(uuid) {
         let write_io = characteristic.write_io().await?;
@@ -79,19 +82,18 @@ pub fn client_control_c_handler() -> Receiver<()> {
     control_c_handler()
 }
 
-fn control_c_handler() -> Receiver<()> {
+pub(crate) fn control_c_handler() -> Receiver<()> {
     let (sender, receiver) = mpsc::channel(1);
-    ctrlc::set_handler(move || {
-        tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .unwrap()
-            .block_on(async {
+    tokio::spawn(async move {
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result.expect("Ctrl+C handler fails");
                 println!(" Ctrl+C pressed.");
-                sender.send(()).await.unwrap();
-            });
-    })
-    .expect("Ctrl+C handler fails");
+                let _ = sender.send(()).await;
+            }
+            _ = sender.closed() => (),
+        }
+    });
 
     receiver
 }
@@ -0,0 +1,263 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+/// Version of the capture schema written by this reader. Version 1 is the original
+/// `{"value": ..., "timestamp": ...}` object, still decoded.
+pub const CAPTURE_SCHEMA_VERSION: u8 = 2;
+
+/// Value of the captures recording a signal loss, see [Capture::signal_loss].
+pub const SIGNAL_LOSS: &str = "signal_loss";
+
+/// Device a capture was read from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureDevice {
+    pub address: String,
+    pub name: Option<String>,
+}
+
+/// Decoded value of a capture.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reading {
+    pub value: f64,
+    pub unit: String,
+}
+
+impl Reading {
+    pub fn new(value: f64, unit: &str) -> Self {
+        Self {
+            value,
+            unit: unit.to_string(),
+        }
+    }
+}
+
+/// Reading taken by a client application, to be forwarded to phonendo_manager.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capture {
+    #[serde(default = "legacy_version")]
+    pub version: u8,
+    /// Nil in version 1 captures.
+    #[serde(default)]
+    pub id: Uuid,
+    #[serde(default)]
+    pub reader_id: Option<String>,
+    #[serde(default)]
+    pub device: Option<CaptureDevice>,
+    #[serde(default)]
+    pub service: Option<Uuid>,
+    #[serde(default)]
+    pub characteristic: Option<Uuid>,
+    /// Raw value, hex encoded for characteristic values.
+    pub value: String,
+    #[serde(default)]
+    pub reading: Option<Reading>,
+    /// Milliseconds since the Unix epoch, as reported by the device.
+    #[serde(default)]
+    pub device_timestamp: Option<i64>,
+    /// Milliseconds since the Unix epoch when the reader received the value.
+    pub timestamp: i64,
+    /// Hex encoded signature of the reader, see [Capture::signing_bytes].
+    #[serde(default)]
+    pub signature: Option<String>,
+}
+
+fn legacy_version() -> u8 {
+    1
+}
+
+impl Capture {
+    pub fn new(value: String) -> Self {
+        Self {
+            version: CAPTURE_SCHEMA_VERSION,
+            id: Uuid::new_v4(),
+            reader_id: None,
+            device: None,
+            service: None,
+            characteristic: None,
+            value,
+            reading: None,
+            device_timestamp: None,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            signature: None,
+        }
+    }
+
+    /// Gap in the readings of a device, from losing its connection until it was back or the
+    /// reader gave up. Its reading is the length of the gap in seconds, and it ended at the capture
+    /// timestamp.
+    pub fn signal_loss(duration: Duration) -> Self {
+        Capture::new(SIGNAL_LOSS.to_string())
+            .with_reading(Reading::new(duration.as_secs_f64(), "s"))
+    }
+
+    pub fn is_signal_loss(&self) -> bool {
+        self.value == SIGNAL_LOSS && self.characteristic.is_none()
+    }
+
+    pub fn with_source(mut self, service: Uuid, characteristic: Uuid) -> Self {
+        self.service = Some(service);
+        self.characteristic = Some(characteristic);
+        self
+    }
+
+    pub fn with_reading(mut self, reading: Reading) -> Self {
+        self.reading = Some(reading);
+        self
+    }
+
+    pub fn with_device_timestamp(mut self, device_timestamp: i64) -> Self {
+        self.device_timestamp = Some(device_timestamp);
+        self
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap()
+    }
+
+    /// Decodes both the current and the version 1 JSON objects.
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        let capture: Capture = serde_json::from_slice(json)?;
+        Capture::check_version(capture.version)?;
+        Ok(capture)
+    }
+
+    /// Compact binary form: the schema version followed by the capture, bincode encoded.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![CAPTURE_SCHEMA_VERSION];
+        bytes.extend(bincode::serialize(self).unwrap());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        match bytes.split_first() {
+            Some((&CAPTURE_SCHEMA_VERSION, capture)) => Ok(bincode::deserialize(capture)?),
+            Some((version, _)) => Err(anyhow::Error::msg(format!(
+                "Unsupported binary capture version {}.",
+                version
+            ))),
+            None => Err(anyhow::Error::msg("Empty binary capture.")),
+        }
+    }
+
+    /// Bytes covered by the signature, in the canonical encoding of the capture version, so that
+    /// captures signed by older readers can still be verified after the schema changes.
+    pub fn signing_bytes(&self) -> Result<Vec<u8>> {
+        match self.version {
+            1 | 2 => Ok(SignedFieldsV2::new(self).to_bytes()),
+            version => Err(anyhow::Error::msg(format!(
+                "Unsupported capture version {}.",
+                version
+            ))),
+        }
+    }
+
+    fn check_version(version: u8) -> Result<()> {
+        if version == 0 || version > CAPTURE_SCHEMA_VERSION {
+            return Err(anyhow::Error::msg(format!(
+                "Unsupported capture version {}.",
+                version
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Fields signed in version 1 and 2 captures, in this order, bincode encoded after the version.
+/// Nested values are flattened so that the encoding does not follow changes of their types.
+#[derive(Serialize)]
+struct SignedFieldsV2<'a> {
+    version: u8,
+    id: [u8; 16],
+    reader_id: Option<&'a str>,
+    device: Option<(&'a str, Option<&'a str>)>,
+    service: Option<[u8; 16]>,
+    characteristic: Option<[u8; 16]>,
+    value: &'a str,
+    reading: Option<(f64, &'a str)>,
+    device_timestamp: Option<i64>,
+    timestamp: i64,
+}
+
+impl<'a> SignedFieldsV2<'a> {
+    fn new(capture: &'a Capture) -> Self {
+        Self {
+            version: capture.version,
+            id: *capture.id.as_bytes(),
+            reader_id: capture.reader_id.as_deref(),
+            device: capture
+                .device
+                .as_ref()
+                .map(|device| (device.address.as_str(), device.name.as_deref())),
+            service: capture.service.map(|service| *service.as_bytes()),
+            characteristic: capture
+                .characteristic
+                .map(|characteristic| *characteristic.as_bytes()),
+            value: &capture.value,
+            reading: capture
+                .reading
+                .as_ref()
+                .map(|reading| (reading.value, reading.unit.as_str())),
+            device_timestamp: capture.device_timestamp,
+            timestamp: capture.timestamp,
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+}
+
+/// Lowercase hexadecimal representation of a characteristic value, used as capture value.
+pub fn to_hex(value: &[u8]) -> String {
+    value.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Where client applications send their captures. The default one drops them.
+#[derive(Clone, Default)]
+pub struct CaptureSender {
+    sender: Option<UnboundedSender<Capture>>,
+    reader_id: Option<String>,
+    device: Option<CaptureDevice>,
+}
+
+impl CaptureSender {
+    pub fn channel() -> (Self, UnboundedReceiver<Capture>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (
+            Self {
+                sender: Some(sender),
+                ..Default::default()
+            },
+            receiver,
+        )
+    }
+
+    /// Reader id set on the captures sent.
+    pub fn with_reader_id(mut self, reader_id: String) -> Self {
+        self.reader_id = Some(reader_id);
+        self
+    }
+
+    /// Device set on the captures sent, unless they define their own.
+    pub fn with_device(mut self, device: CaptureDevice) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Returns false if the capture was dropped.
+    pub fn send(&self, mut capture: Capture) -> bool {
+        if capture.reader_id.is_none() {
+            capture.reader_id = self.reader_id.clone();
+        }
+        if capture.device.is_none() {
+            capture.device = self.device.clone();
+        }
+        match &self.sender {
+            Some(sender) => sender.send(capture).is_ok(),
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::backend::{AdapterEvent, AdapterPresence};
+use crate::{AdapterManager, ApplicationClient};
+use anyhow::Result;
+use bluer::Address;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+/// Holds a session with every device serving the application, up to `max_sessions` at the same
+/// time. Discovery keeps running while sessions are open, and devices found at the limit wait
+/// until a session ends. Devices whose session ended are admitted again after
+/// `client.readmit_delay` seconds. The readings of each session are tagged with its device.
+pub struct ClientManager {
+    application_client: ApplicationClient,
+    max_sessions: usize,
+}
+
+impl ClientManager {
+    /// Manager with the `client.max_sessions` of the client settings.
+    pub fn new(application_client: ApplicationClient) -> Self {
+        Self {
+            max_sessions: application_client.settings().max_sessions,
+            application_client,
+        }
+    }
+
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = max_sessions;
+        self
+    }
+
+    /// Runs until discovery stops and every session has ended, or the shutdown of the client is
+    /// requested, then shuts the adapter down. If the adapter is removed, the sessions are dropped and discovery starts over
+    /// once it is back.
+    pub async fn run(self) -> Result<()> {
+        let result = self.manage_sessions().await;
+        result.and(self.application_client.adapter_manager().shutdown().await)
+    }
+
+    async fn manage_sessions(&self) -> Result<()> {
+        let adapter_manager = self.application_client.adapter_manager();
+        let adapter = adapter_manager.adapter();
+        println!(
+            "Discovering on Bluetooth adapter {} with address {}, up to {} sessions.",
+            adapter.name(),
+            adapter.address().await?,
+            self.max_sessions
+        );
+
+        let mut presence = adapter_manager.presence_events();
+        let shutdown = self.application_client.shutdown();
+        loop {
+            tokio::select! {
+                biased;
+                _ = AdapterManager::wait_for_presence(&mut presence, AdapterPresence::Lost) => {
+                    println!("Bluetooth adapter lost, closing sessions.");
+                }
+                result = self.serve_sessions() => return result,
+                _ = shutdown.requested() => return Ok(()),
+            }
+
+            tokio::select! {
+                _ = AdapterManager::wait_for_presence(&mut presence, AdapterPresence::Restored) => {
+                    println!("Bluetooth adapter restored, resuming discovery.");
+                }
+                _ = shutdown.requested() => return Ok(()),
+            }
+        }
+    }
+
+    async fn serve_sessions(&self) -> Result<()> {
+        let mut discover = self
+            .application_client
+            .adapter_manager()
+            .adapter()
+            .discover_devices()
+            .await?;
+        let readmit_delay =
+            Duration::from_secs_f64(self.application_client.settings().readmit_delay);
+        let mut discovering = true;
+        let mut sessions = FuturesUnordered::new();
+        let mut active = HashSet::new();
+        let mut waiting = VecDeque::new();
+        // Discovery only reports a device again once it has been removed, so devices whose
+        // session ended are admitted again after a delay.
+        let mut readmissions = FuturesUnordered::new();
+        let mut ended = HashSet::new();
+
+        loop {
+            let address = tokio::select! {
+                event = discover.next(), if discovering => match event {
+                    Some(AdapterEvent::DeviceAdded(address)) => address,
+                    Some(AdapterEvent::DeviceRemoved(address)) => {
+                        println!("Device removed {}.", address);
+                        waiting.retain(|waiting| *waiting != address);
+                        ended.remove(&address);
+                        continue;
+                    }
+                    None => {
+                        discovering = false;
+                        readmissions.clear();
+                        ended.clear();
+                        continue;
+                    }
+                },
+                Some((address, readmit)) = sessions.next() => {
+                    active.remove(&address);
+                    if readmit && discovering {
+                        ended.insert(address);
+                        readmissions.push(async move {
+                            tokio::time::sleep(readmit_delay).await;
+                            address
+                        });
+                    }
+                    if let Some(address) = waiting.pop_front() {
+                        active.insert(address);
+                        sessions.push(self.session(address));
+                    }
+                    continue;
+                }
+                Some(address) = readmissions.next() => {
+                    // Removed or reported again by discovery meanwhile.
+                    if !ended.contains(&address) {
+                        continue;
+                    }
+                    address
+                }
+                else => break,
+            };
+
+            ended.remove(&address);
+            if active.contains(&address) || waiting.contains(&address) {
+                continue;
+            }
+            if active.len() < self.max_sessions {
+                active.insert(address);
+                sessions.push(self.session(address));
+            } else {
+                println!(
+                    "\nSession limit ({}) reached, device {} waits.",
+                    self.max_sessions, address
+                );
+                waiting.push_back(address);
+            }
+        }
+
+        println!("\nStopping discovery.");
+
+        Ok(())
+    }
+
+    /// Opens and runs a session with the device, returning its address once it ends, and whether
+    /// to admit it again: devices that don't serve the application are not.
+    async fn session(&self, address: Address) -> (Address, bool) {
+        match self.hold_session(address).await {
+            Ok(true) => println!("Session with device {} ended.", address),
+            Ok(false) => return (address, false),
+            Err(error) => println!("Session with device {} failed: {}.", address, error),
+        }
+        (address, true)
+    }
+
+    /// Whether the device served the application, so that a session was held with it.
+    async fn hold_session(&self, address: Address) -> Result<bool> {
+        let device = self
+            .application_client
+            .adapter_manager()
+            .adapter()
+            .device(address)?;
+        match self.application_client.open_session(device).await? {
+            Some(session) => {
+                println!("\tSession with device {} opened.", address);
+                self.application_client.run_session(session).await?;
+                // The session may have reconnected the device, so it is looked up again.
+                if let Ok(device) = self
+                    .application_client
+                    .adapter_manager()
+                    .adapter()
+                    .device(address)
+                {
+                    let _ = device.disconnect().await;
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
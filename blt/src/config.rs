@@ -0,0 +1,672 @@
+use crate::adapter_manager::AdapterSelector;
+use crate::application_factory::{ApplicationMode, APPLICATIONS, MOCK_APP};
+use crate::device_database::MAX_PASSKEY;
+use crate::instance_supervisor::Instance;
+use crate::mock_capture::MockPayload;
+use crate::pairing_agent::{is_valid_pin_code, PairingAgentKind};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Configuration file read when `PHONENDO_CONFIG` isn't defined. It is optional.
+pub const DEFAULT_CONFIG_PATH: &str = "phonendo_reader.toml";
+/// Path of the configuration file.
+pub const CONFIG_PATH_VAR: &str = "PHONENDO_CONFIG";
+/// Prefix of the environment variables that override configuration keys. Nested keys are joined
+/// with `__`, e.g. `PHONENDO_CLIENT__PAIR_RETRIES` overrides `pair_retries` of `[client]`.
+pub const ENV_PREFIX: &str = "PHONENDO_";
+const ENV_SEPARATOR: &str = "__";
+/// Largest emulated clock offset, in seconds: a thousand years, well within the dates chrono
+/// represents.
+const MAX_CLOCK_OFFSET: i64 = 1000 * 366 * 24 * 60 * 60;
+
+/// Reader configuration, see `resources/phonendo_reader.toml` for an example with every key.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Application to run, one of [APPLICATIONS].
+    pub app: Option<String>,
+    pub mode: Option<ApplicationMode>,
+    /// Seed of the random values generated by the applications, random if not defined.
+    pub seed: Option<u64>,
+    pub adapter: AdapterSettings,
+    pub client: ClientSettings,
+    pub pairing: PairingSettings,
+    pub server: ServerSettings,
+    pub time_sync: TimeSyncSettings,
+    pub apps: AppSettings,
+    pub manager: ManagerSettings,
+    /// Applications run at the same time, each on its own adapter. `app`, `mode`, `seed` and
+    /// `[adapter]` are the defaults of their keys.
+    pub instances: Vec<InstanceSettings>,
+}
+
+/// Application run on an adapter of its own, see [Config::instances].
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InstanceSettings {
+    /// Name shown in the logs, `<app>_<mode>` if not defined.
+    pub name: Option<String>,
+    pub app: Option<String>,
+    pub mode: Option<ApplicationMode>,
+    pub seed: Option<u64>,
+    pub adapter: Option<AdapterSettings>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdapterSettings {
+    /// Name of the adapter to use, e.g. hci1.
+    pub name: Option<String>,
+    /// Address of the adapter to use.
+    pub address: Option<String>,
+    /// Capabilities the adapter must have: advertising, le_2m or le_coded.
+    pub capabilities: Vec<String>,
+    /// Seconds the adapter stays pairable.
+    pub pairable_timeout: u32,
+    /// Whether to restore the powered and pairable state of the adapter on shutdown.
+    pub restore_state: bool,
+}
+
+impl Default for AdapterSettings {
+    fn default() -> Self {
+        Self {
+            name: None,
+            address: None,
+            capabilities: Vec::new(),
+            pairable_timeout: 15,
+            restore_state: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientSettings {
+    /// Device database with the devices to pair, and how. `resources/devices.toml` if not
+    /// defined.
+    pub device_database: Option<PathBuf>,
+    pub pair_retries: u32,
+    pub connect_retries: u32,
+    /// Devices the client holds sessions with at the same time. Above 1, discovery keeps running
+    /// and every device serving the application gets a session of its own.
+    pub max_sessions: usize,
+    /// Attempts to reconnect a device whose connection is lost, 0 to end its session instead.
+    pub reconnect_attempts: u32,
+    /// Seconds before the first reconnection attempt, doubled on every attempt.
+    pub reconnect_delay: f64,
+    /// Longest delay between reconnection attempts, in seconds.
+    pub reconnect_max_delay: f64,
+    /// Seconds before a device whose session ended gets a session again, as discovery doesn't
+    /// report devices it already knows.
+    pub readmit_delay: f64,
+}
+
+impl Default for ClientSettings {
+    fn default() -> Self {
+        Self {
+            device_database: None,
+            pair_retries: 5,
+            connect_retries: 2,
+            max_sessions: 1,
+            reconnect_attempts: 5,
+            reconnect_delay: 1.0,
+            reconnect_max_delay: 30.0,
+            readmit_delay: 30.0,
+        }
+    }
+}
+
+/// Answers of the pairing agent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PairingSettings {
+    /// config (device database and this section), prompt (asks on the terminal) or none.
+    pub agent: String,
+    /// PIN code sent to devices that request one.
+    pub pin_code: Option<String>,
+    /// Passkey of the devices whose rule doesn't define one.
+    pub passkey: Option<u32>,
+    /// Whether to accept numeric comparisons of devices without an expected passkey.
+    pub confirm: bool,
+}
+
+impl Default for PairingSettings {
+    fn default() -> Self {
+        Self {
+            agent: "config".to_string(),
+            pin_code: None,
+            passkey: None,
+            confirm: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    /// Name advertised instead of the service name.
+    pub local_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeSyncSettings {
+    /// Seconds between synchronization rounds.
+    pub interval: u64,
+    /// Clock offset, in seconds, above which a band is resynchronized.
+    pub threshold: i64,
+    /// Seconds each round looks for new bands.
+    pub discovery_timeout: u64,
+    /// Samples kept per band.
+    pub history_length: usize,
+    /// Drift, in parts per million, above which a band clock is reported as failing.
+    pub max_drift_rate: f64,
+    /// CSV file where every sample is appended, if any.
+    pub history: Option<PathBuf>,
+}
+
+impl Default for TimeSyncSettings {
+    fn default() -> Self {
+        Self {
+            interval: 15 * 60,
+            threshold: 1,
+            discovery_timeout: 10,
+            history_length: 96,
+            max_drift_rate: 100.0,
+            history: None,
+        }
+    }
+}
+
+/// Settings of each application.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppSettings {
+    pub heart_rate: HeartRateSettings,
+    pub cts: CtsSettings,
+    pub mock: MockSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartRateSettings {
+    /// Seconds between notifications of the server.
+    pub notification_interval: f64,
+    /// Scenario played instead of the random walk, either a scenario name or the path of a
+    /// scenario file. Also used by the mock application with the heart_rate payload.
+    pub scenario: Option<String>,
+}
+
+impl Default for HeartRateSettings {
+    fn default() -> Self {
+        Self {
+            notification_interval: 7.0,
+            scenario: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CtsSettings {
+    /// Emulated clock offset of the server, in seconds.
+    pub offset: i64,
+    /// Emulated clock drift of the server, in parts per million.
+    pub drift_rate: f64,
+    /// Clock offset, in seconds, above which the client resynchronizes the server. Ten minutes if
+    /// not defined.
+    pub sync_threshold: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockSettings {
+    /// Seconds between captures.
+    pub interval: f64,
+    /// uuid or heart_rate.
+    pub payload: String,
+}
+
+impl Default for MockSettings {
+    fn default() -> Self {
+        Self {
+            interval: 5.0,
+            payload: "uuid".to_string(),
+        }
+    }
+}
+
+/// What to do with a new capture when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Removes the oldest capture to make room for the new one.
+    DropOldest,
+    /// Keeps the queue as is and drops the new capture.
+    DropNewest,
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.to_lowercase().as_str() {
+            "drop_oldest" => Ok(EvictionPolicy::DropOldest),
+            "drop_newest" => Ok(EvictionPolicy::DropNewest),
+            _ => Err(anyhow::Error::msg(format!(
+                "Unknown eviction policy '{}' (available: drop_oldest, drop_newest).",
+                input
+            ))),
+        }
+    }
+}
+
+/// Connection with phonendo_manager, used in client mode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManagerSettings {
+    /// Multiaddress the reader listens on.
+    pub listen_address: String,
+    /// Multiaddresses of managers dialed on start, besides the ones found with mDNS.
+    pub peers: Vec<String>,
+    /// Seconds between mDNS queries, 0 disables mDNS discovery.
+    pub mdns_query_interval: u64,
+    /// Seconds to wait for the manager to answer a request.
+    pub request_timeout: u64,
+    /// File with the reader Ed25519 keypair, created on first run.
+    pub identity_path: PathBuf,
+    /// File where captures wait until the manager stores them.
+    pub capture_queue_path: PathBuf,
+    /// Maximum number of pending captures.
+    pub capture_queue_capacity: usize,
+    /// drop_oldest or drop_newest, what to do with new captures when the queue is full.
+    pub capture_queue_eviction: String,
+    /// Seconds between attempts to send pending captures while the manager is unavailable.
+    pub capture_queue_retry_interval: u64,
+    /// Hash chained log of every capture taken.
+    pub audit_log_path: PathBuf,
+}
+
+impl Default for ManagerSettings {
+    fn default() -> Self {
+        Self {
+            listen_address: "/ip4/127.0.0.1/tcp/0".to_string(),
+            peers: Vec::new(),
+            mdns_query_interval: 20,
+            request_timeout: 10,
+            identity_path: PathBuf::from("phonendo_reader.key"),
+            capture_queue_path: PathBuf::from("phonendo_captures.jsonl"),
+            capture_queue_capacity: 100_000,
+            capture_queue_eviction: "drop_oldest".to_string(),
+            capture_queue_retry_interval: 5,
+            audit_log_path: PathBuf::from("phonendo_audit.jsonl"),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the file in `PHONENDO_CONFIG`, or `phonendo_reader.toml` if it exists, and applies the
+    /// environment overrides.
+    pub fn load() -> Result<Self> {
+        match std::env::var(CONFIG_PATH_VAR) {
+            Ok(path) => Config::load_file(Path::new(&path), std::env::vars()),
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Config::load_file(Path::new(DEFAULT_CONFIG_PATH), std::env::vars())
+            }
+            Err(_) => Config::parse("", std::env::vars()),
+        }
+    }
+
+    pub fn load_file<I>(path: &Path, vars: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let content = std::fs::read_to_string(path).map_err(|error| {
+            anyhow::Error::msg(format!(
+                "Unable to read configuration '{}': {}",
+                path.display(),
+                error
+            ))
+        })?;
+        Config::parse(&content, vars).map_err(|error| {
+            anyhow::Error::msg(format!(
+                "Invalid configuration '{}': {}",
+                path.display(),
+                error
+            ))
+        })
+    }
+
+    /// Parses a TOML configuration, overriding its keys with the `PHONENDO_` variables of `vars`,
+    /// and validates it.
+    pub fn parse<I>(content: &str, vars: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut table: toml::value::Table = toml::from_str(content)?;
+        let template = override_template();
+        for (name, value) in vars {
+            if name == CONFIG_PATH_VAR {
+                continue;
+            }
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                override_key(&mut table, &template, &name, key, &value)?;
+            }
+        }
+
+        let config: Config = toml::Value::Table(table).try_into()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(app) = &self.app {
+            if !APPLICATIONS.contains(&app.to_lowercase().as_str()) {
+                return Err(anyhow::Error::msg(format!(
+                    "Unknown application '{}' (available: {}).",
+                    app,
+                    APPLICATIONS.join(", ")
+                )));
+            }
+            if app.to_lowercase() == MOCK_APP && self.mode == Some(ApplicationMode::Server) {
+                return Err(anyhow::Error::msg(format!(
+                    "Application '{}' only runs as a client.",
+                    app
+                )));
+            }
+        }
+
+        positive(
+            "apps.heart_rate.notification_interval",
+            self.apps.heart_rate.notification_interval,
+        )?;
+        positive("apps.mock.interval", self.apps.mock.interval)?;
+        if self.apps.cts.offset.abs() > MAX_CLOCK_OFFSET {
+            return Err(anyhow::Error::msg(format!(
+                "apps.cts.offset must be within {} seconds.",
+                MAX_CLOCK_OFFSET
+            )));
+        }
+        // At -10^6 ppm or below the emulated clock stops or runs backwards.
+        let drift_rate = self.apps.cts.drift_rate;
+        if !drift_rate.is_finite() || drift_rate <= -1_000_000.0 {
+            return Err(anyhow::Error::msg(format!(
+                "apps.cts.drift_rate must be a number above -1000000 ppm, not {}.",
+                drift_rate
+            )));
+        }
+        if let Some(sync_threshold) = self.apps.cts.sync_threshold {
+            threshold("apps.cts.sync_threshold", sync_threshold)?;
+        }
+        self.apps.mock.payload.parse::<MockPayload>()?;
+        AdapterSelector::from_settings(&self.adapter)?;
+        self.pairing.agent.parse::<PairingAgentKind>()?;
+        if let Some(pin_code) = &self.pairing.pin_code {
+            if !is_valid_pin_code(pin_code) {
+                return Err(anyhow::Error::msg(
+                    "pairing.pin_code must have 1 to 16 letters or digits.",
+                ));
+            }
+        }
+        if self
+            .pairing
+            .passkey
+            .is_some_and(|passkey| passkey > MAX_PASSKEY)
+        {
+            return Err(anyhow::Error::msg(
+                "pairing.passkey must have up to six digits.",
+            ));
+        }
+        if self.client.max_sessions == 0 {
+            return Err(anyhow::Error::msg("client.max_sessions must be positive."));
+        }
+        positive("client.reconnect_delay", self.client.reconnect_delay)?;
+        positive(
+            "client.reconnect_max_delay",
+            self.client.reconnect_max_delay,
+        )?;
+        if self.client.reconnect_max_delay < self.client.reconnect_delay {
+            return Err(anyhow::Error::msg(
+                "client.reconnect_max_delay must not be below client.reconnect_delay.",
+            ));
+        }
+        positive("client.readmit_delay", self.client.readmit_delay)?;
+        if self.time_sync.interval == 0 {
+            return Err(anyhow::Error::msg("time_sync.interval must be positive."));
+        }
+        threshold("time_sync.threshold", self.time_sync.threshold)?;
+        if self.manager.request_timeout == 0 {
+            return Err(anyhow::Error::msg(
+                "manager.request_timeout must be positive.",
+            ));
+        }
+        if self.manager.capture_queue_capacity == 0 {
+            return Err(anyhow::Error::msg(
+                "manager.capture_queue_capacity must be positive.",
+            ));
+        }
+        self.manager
+            .capture_queue_eviction
+            .parse::<EvictionPolicy>()?;
+        if self.manager.capture_queue_retry_interval == 0 {
+            return Err(anyhow::Error::msg(
+                "manager.capture_queue_retry_interval must be positive.",
+            ));
+        }
+        self.instances()?;
+        Ok(())
+    }
+
+    /// Configuration of every instance, each one with its application, mode, seed and adapter.
+    /// Instances must have an application and a mode and, if there are several, adapters selected
+    /// by different names or addresses.
+    pub fn instances(&self) -> Result<Vec<Instance>> {
+        let mut instances: Vec<Instance> = Vec::new();
+        for settings in &self.instances {
+            let config = Config {
+                app: settings.app.clone().or_else(|| self.app.clone()),
+                mode: settings.mode.or(self.mode),
+                seed: settings.seed.or(self.seed),
+                adapter: settings
+                    .adapter
+                    .clone()
+                    .unwrap_or_else(|| self.adapter.clone()),
+                instances: Vec::new(),
+                ..self.clone()
+            };
+            let (app, mode) = match (&config.app, config.mode) {
+                (Some(app), Some(mode)) => (app.clone(), mode),
+                _ => {
+                    return Err(anyhow::Error::msg(format!(
+                        "Instance {} needs an application and a mode.",
+                        instances.len() + 1
+                    )))
+                }
+            };
+            let name = settings
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("{}_{}", app, mode));
+            config
+                .validate()
+                .map_err(|error| anyhow::Error::msg(format!("Instance '{}': {}", name, error)))?;
+
+            if instances.iter().any(|instance| instance.name == name) {
+                return Err(anyhow::Error::msg(format!(
+                    "Instance name '{}' is repeated.",
+                    name
+                )));
+            }
+            instances.push(Instance { name, config });
+        }
+
+        if instances.len() > 1 {
+            for (index, instance) in instances.iter().enumerate() {
+                let adapter = &instance.config.adapter;
+                if adapter.name.is_none() && adapter.address.is_none() {
+                    return Err(anyhow::Error::msg(format!(
+                        "Instance '{}' needs an adapter name or address.",
+                        instance.name
+                    )));
+                }
+                if let Some(other) = instances[..index].iter().find(|other| {
+                    other.config.adapter.name == adapter.name
+                        && other.config.adapter.address == adapter.address
+                }) {
+                    return Err(anyhow::Error::msg(format!(
+                        "Instances '{}' and '{}' use the same adapter.",
+                        other.name, instance.name
+                    )));
+                }
+            }
+        }
+        Ok(instances)
+    }
+}
+
+/// Checks that `value` is a positive number of seconds that fits a [Duration].
+fn positive(key: &str, value: f64) -> Result<()> {
+    if value > 0.0 && Duration::try_from_secs_f64(value).is_ok() {
+        Ok(())
+    } else {
+        Err(anyhow::Error::msg(format!(
+            "{} must be a positive number of seconds, not {}.",
+            key, value
+        )))
+    }
+}
+
+/// Checks that `value` is a non-negative number of seconds that fits a [chrono::Duration].
+fn threshold(key: &str, value: i64) -> Result<()> {
+    if (0..=chrono::Duration::max_value().num_seconds()).contains(&value) {
+        Ok(())
+    } else {
+        Err(anyhow::Error::msg(format!(
+            "{} must be a non-negative number of seconds, not {}.",
+            key, value
+        )))
+    }
+}
+
+/// Configuration with every optional key set, whose serialized form gives the type of every key
+/// environment variables can override. Optional keys missing here are ignored as unknown.
+fn override_template() -> toml::value::Table {
+    let config = Config {
+        app: Some(String::new()),
+        mode: Some(ApplicationMode::Client),
+        seed: Some(0),
+        adapter: AdapterSettings {
+            name: Some(String::new()),
+            address: Some(String::new()),
+            ..Default::default()
+        },
+        client: ClientSettings {
+            device_database: Some(PathBuf::new()),
+            ..Default::default()
+        },
+        pairing: PairingSettings {
+            pin_code: Some(String::new()),
+            passkey: Some(0),
+            ..Default::default()
+        },
+        server: ServerSettings {
+            local_name: Some(String::new()),
+        },
+        time_sync: TimeSyncSettings {
+            history: Some(PathBuf::new()),
+            ..Default::default()
+        },
+        apps: AppSettings {
+            heart_rate: HeartRateSettings {
+                scenario: Some(String::new()),
+                ..Default::default()
+            },
+            cts: CtsSettings {
+                sync_threshold: Some(0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    match toml::Value::try_from(config) {
+        Ok(toml::Value::Table(table)) => table,
+        _ => unreachable!("configurations serialize as tables"),
+    }
+}
+
+/// Sets the key of `name`, lowercased and split on `__`, to `value` converted to the type of that
+/// key in `template`: strings are taken as they are, numbers and booleans are parsed, and arrays
+/// are read as TOML. Keys that aren't in the configuration are ignored with a warning.
+fn override_key(
+    table: &mut toml::value::Table,
+    template: &toml::value::Table,
+    name: &str,
+    key: &str,
+    value: &str,
+) -> Result<()> {
+    let path: Vec<String> = key.split(ENV_SEPARATOR).map(str::to_lowercase).collect();
+    let (last, sections) = path.split_last().unwrap();
+
+    let mut kind = template;
+    for section in sections {
+        match kind.get(section) {
+            Some(toml::Value::Table(section)) => kind = section,
+            _ => {
+                println!("Unknown configuration key in {}, ignored.", name);
+                return Ok(());
+            }
+        }
+    }
+    let invalid = |expected: &str| {
+        anyhow::Error::msg(format!(
+            "{}: '{}' is not {} for {}.",
+            name,
+            value,
+            expected,
+            path.join(".")
+        ))
+    };
+    let value = match kind.get(last) {
+        Some(toml::Value::String(_)) => toml::Value::String(value.to_string()),
+        Some(toml::Value::Integer(_)) => {
+            toml::Value::Integer(value.trim().parse().map_err(|_| invalid("an integer"))?)
+        }
+        Some(toml::Value::Float(_)) => {
+            toml::Value::Float(value.trim().parse().map_err(|_| invalid("a number"))?)
+        }
+        Some(toml::Value::Boolean(_)) => {
+            toml::Value::Boolean(value.trim().parse().map_err(|_| invalid("a boolean"))?)
+        }
+        Some(toml::Value::Array(_)) => {
+            toml::from_str::<toml::value::Table>(&format!("value = {}", value))
+                .ok()
+                .and_then(|mut table| table.remove("value"))
+                .filter(toml::Value::is_array)
+                .ok_or_else(|| invalid("an array"))?
+        }
+        Some(_) => {
+            println!("{} names a configuration section, ignored.", name);
+            return Ok(());
+        }
+        None => {
+            println!("Unknown configuration key in {}, ignored.", name);
+            return Ok(());
+        }
+    };
+
+    let mut table = table;
+    for section in sections {
+        table = table
+            .entry(section.clone())
+            .or_insert_with(|| toml::Value::Table(Default::default()))
+            .as_table_mut()
+            .ok_or_else(|| {
+                anyhow::Error::msg(format!("{}: '{}' is not a section.", name, section))
+            })?;
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
@@ -0,0 +1,249 @@
+use crate::backend::BltDevice;
+use anyhow::Result;
+use bluer::Address;
+use serde::Deserialize;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Rules used when no device database is configured.
+const DEFAULT_DATABASE: &str = include_str!("../../resources/devices.toml");
+
+/// Largest Bluetooth passkey (six digits).
+pub(crate) const MAX_PASSKEY: u32 = 999_999;
+
+/// Whether a client pairs with a device before using it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingPolicy {
+    /// Pairs before scanning the device services.
+    Always,
+    /// Never pairs.
+    Never,
+    /// Pairs only if the device services can't be used without pairing.
+    IfRequired,
+}
+
+impl FromStr for PairingPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.to_lowercase().replace('-', "_").as_str() {
+            "always" => Ok(PairingPolicy::Always),
+            "never" => Ok(PairingPolicy::Never),
+            "if_required" => Ok(PairingPolicy::IfRequired),
+            _ => Err(anyhow::Error::msg(format!(
+                "Unknown pairing policy '{}' (available: always, never, if-required).",
+                input
+            ))),
+        }
+    }
+}
+
+/// Device rule: every criterion it defines must match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceRule {
+    pub alias: Option<String>,
+    /// Name pattern, where `*` matches any text and `?` any character.
+    pub name: Option<String>,
+    pub address: Option<Address>,
+    /// Organizationally Unique Identifier: the first three bytes of the address.
+    pub oui: Option<[u8; 3]>,
+    pub pairing: PairingPolicy,
+    /// Passkey the device is expected to display or request while pairing.
+    pub passkey: Option<u32>,
+}
+
+impl DeviceRule {
+    pub fn matches(&self, address: Address, name: Option<&str>, alias: Option<&str>) -> bool {
+        self.address.is_none_or(|expected| expected == address)
+            && self.oui.is_none_or(|oui| address.0[..3] == oui[..])
+            && self
+                .alias
+                .as_ref()
+                .is_none_or(|expected| alias == Some(expected.as_str()))
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|pattern| name.is_some_and(|name| matches_pattern(pattern, name)))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeviceDatabaseFile {
+    #[serde(default)]
+    device: Vec<DeviceRuleEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeviceRuleEntry {
+    alias: Option<String>,
+    name: Option<String>,
+    address: Option<String>,
+    oui: Option<String>,
+    pairing: String,
+    passkey: Option<u32>,
+}
+
+impl TryFrom<DeviceRuleEntry> for DeviceRule {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: DeviceRuleEntry) -> Result<Self> {
+        if entry.alias.is_none()
+            && entry.name.is_none()
+            && entry.address.is_none()
+            && entry.oui.is_none()
+        {
+            return Err(anyhow::Error::msg(
+                "it needs an alias, name, address or oui to match.",
+            ));
+        }
+        if let Some(passkey) = entry.passkey {
+            if passkey > MAX_PASSKEY {
+                return Err(anyhow::Error::msg(format!(
+                    "passkey {} has more than six digits.",
+                    passkey
+                )));
+            }
+        }
+
+        Ok(Self {
+            address: entry
+                .address
+                .map(|address| {
+                    address
+                        .parse()
+                        .map_err(|_| anyhow::Error::msg(format!("invalid address '{}'.", address)))
+                })
+                .transpose()?,
+            oui: entry.oui.map(|oui| parse_oui(&oui)).transpose()?,
+            alias: entry.alias,
+            name: entry.name,
+            pairing: entry.pairing.parse()?,
+            passkey: entry.passkey,
+        })
+    }
+}
+
+fn parse_oui(oui: &str) -> Result<[u8; 3]> {
+    let bytes: Vec<u8> = oui
+        .split([':', '-'])
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| anyhow::Error::msg(format!("invalid oui '{}'.", oui)))?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow::Error::msg(format!("invalid oui '{}', it needs 3 bytes.", oui)))
+}
+
+/// Whether `value` matches `pattern`, where `*` matches any text and `?` any character.
+fn matches_pattern(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    // Position in the pattern after the last `*`, and the value position it was tried at.
+    let mut backtrack: Option<(usize, usize)> = None;
+    let (mut p, mut v) = (0, 0);
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((pattern_position, value_position)) => {
+                    backtrack = Some((pattern_position, value_position + 1));
+                    p = pattern_position;
+                    v = value_position + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Devices known by the reader and how to pair them. The first matching rule applies, devices
+/// without one are never paired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceDatabase {
+    rules: Vec<DeviceRule>,
+}
+
+impl DeviceDatabase {
+    /// Loads the database in `path`, or the default one (`resources/devices.toml`).
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => {
+                let content = std::fs::read_to_string(path).map_err(|error| {
+                    anyhow::Error::msg(format!(
+                        "Unable to read device database '{}': {}",
+                        path.display(),
+                        error
+                    ))
+                })?;
+                DeviceDatabase::parse(&content).map_err(|error| {
+                    anyhow::Error::msg(format!(
+                        "Invalid device database '{}': {}",
+                        path.display(),
+                        error
+                    ))
+                })
+            }
+            None => Ok(DeviceDatabase::default()),
+        }
+    }
+
+    /// Parses a TOML list of `[[device]]` rules.
+    pub fn parse(content: &str) -> Result<Self> {
+        let file: DeviceDatabaseFile = toml::from_str(content)?;
+        let rules = file
+            .device
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                DeviceRule::try_from(entry).map_err(|error| {
+                    anyhow::Error::msg(format!("device rule {}: {}", index + 1, error))
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn rules(&self) -> &[DeviceRule] {
+        &self.rules
+    }
+
+    pub fn find_rule(
+        &self,
+        address: Address,
+        name: Option<&str>,
+        alias: Option<&str>,
+    ) -> Option<&DeviceRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(address, name, alias))
+    }
+
+    /// Rule of `device`. Properties that can't be read only match rules that don't use them.
+    pub async fn device_rule(&self, device: &dyn BltDevice) -> Option<&DeviceRule> {
+        let name = device.name().await.ok().flatten();
+        let alias = device.alias().await.ok();
+        self.find_rule(device.address(), name.as_deref(), alias.as_deref())
+    }
+
+    pub async fn pairing_policy(&self, device: &dyn BltDevice) -> PairingPolicy {
+        self.device_rule(device)
+            .await
+            .map_or(PairingPolicy::Never, |rule| rule.pairing)
+    }
+}
+
+impl Default for DeviceDatabase {
+    fn default() -> Self {
+        DeviceDatabase::parse(DEFAULT_DATABASE).expect("Invalid default device database")
+    }
+}
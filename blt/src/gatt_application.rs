@@ -1,15 +1,14 @@
 use anyhow::Result;
 
+use crate::backend::local::{Application, CharacteristicControl};
 use crate::{AdapterManager, ApplicationDescriptor, ApplicationHandler};
-use bluer::{
-    gatt::local::{Application, CharacteristicControl},
-    Uuid,
-};
+use uuid::Uuid;
 
 pub struct GattApplication {
     application_definition: Application,
     characteristics_controls: Vec<CharacteristicControl>,
     application_descriptor: ApplicationDescriptor,
+    local_name: Option<String>,
 }
 
 impl GattApplication {
@@ -22,9 +21,16 @@ impl GattApplication {
             application_definition,
             characteristics_controls,
             application_descriptor,
+            local_name: None,
         }
     }
 
+    /// Name advertised instead of the service name.
+    pub fn with_local_name(mut self, local_name: Option<String>) -> Self {
+        self.local_name = local_name;
+        self
+    }
+
     pub fn service_uuid(&self) -> &Uuid {
         self.application_descriptor.service_uuid()
     }
@@ -48,7 +54,9 @@ impl GattApplication {
         let advertisement_handle = adapter_manager
             .advertise_gatt_service(
                 *self.application_descriptor.service_uuid(),
-                self.application_descriptor.service_name(),
+                self.local_name
+                    .as_deref()
+                    .unwrap_or_else(|| self.application_descriptor.service_name()),
             )
             .await?;
 
@@ -0,0 +1,198 @@
+use crate::adapter_manager::{AdapterInfo, AdapterSelector};
+use crate::application_factory::{ApplicationFactory, ApplicationMode};
+use crate::capture::CaptureSender;
+use crate::config::Config;
+use crate::shutdown::Shutdown;
+use anyhow::Result;
+use futures::future::{self, join_all};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::timeout;
+
+/// Seconds instances have to stop once they are asked to shut down.
+const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 5;
+
+/// Application run by the supervisor, on the adapter of its configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    pub name: String,
+    pub config: Config,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstanceStatus {
+    Pending,
+    Running,
+    Stopped,
+    Failed(String),
+}
+
+impl fmt::Display for InstanceStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceStatus::Pending => write!(f, "pending"),
+            InstanceStatus::Running => write!(f, "running"),
+            InstanceStatus::Stopped => write!(f, "stopped"),
+            InstanceStatus::Failed(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+/// Runs several instances at the same time. An instance failing doesn't stop the others, and the
+/// supervisor returns once all of them have stopped.
+pub struct InstanceSupervisor {
+    instances: Vec<Instance>,
+    status: watch::Sender<Vec<InstanceStatus>>,
+    shutdown_timeout: Duration,
+}
+
+impl InstanceSupervisor {
+    pub fn new(instances: Vec<Instance>) -> Self {
+        let (status, _) = watch::channel(vec![InstanceStatus::Pending; instances.len()]);
+        Self {
+            instances,
+            status,
+            shutdown_timeout: Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT),
+        }
+    }
+
+    /// Time instances have to stop on their own on shutdown, before being dropped.
+    pub fn with_shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    /// Status of every instance, in the order of [InstanceSupervisor::instances].
+    pub fn status(&self) -> watch::Receiver<Vec<InstanceStatus>> {
+        self.status.subscribe()
+    }
+
+    /// Checks that no two instances select the same adapter among `adapters`, since their
+    /// settings may match it differently (by name, address or capabilities). Instances whose
+    /// adapter is not present are left to fail on their own.
+    pub fn check_adapters(&self, adapters: &[AdapterInfo]) -> Result<()> {
+        let mut selected: Vec<(&str, &AdapterInfo)> = Vec::new();
+        for instance in &self.instances {
+            let selector = AdapterSelector::from_settings(&instance.config.adapter)?;
+            let Ok(adapter) = selector.select(adapters) else {
+                continue;
+            };
+            if let Some((other, _)) = selected
+                .iter()
+                .find(|(_, other)| other.address == adapter.address)
+            {
+                return Err(anyhow::Error::msg(format!(
+                    "Instances '{}' and '{}' use the same adapter {}.",
+                    other, instance.name, adapter.name
+                )));
+            }
+            selected.push((&instance.name, adapter));
+        }
+        Ok(())
+    }
+
+    /// Whether any instance runs in client mode, so it sends captures.
+    pub fn has_clients(&self) -> bool {
+        self.instances
+            .iter()
+            .any(|instance| instance.config.mode == Some(ApplicationMode::Client))
+    }
+
+    /// Runs every instance until they stop or Ctrl+C is pressed.
+    pub async fn run(&self, capture_sender: CaptureSender) -> Result<()> {
+        self.run_with(
+            capture_sender,
+            |config, capture_sender, shutdown| async move {
+                ApplicationFactory::launch_application(&config, capture_sender, shutdown).await
+            },
+            async {
+                if tokio::signal::ctrl_c().await.is_err() {
+                    future::pending::<()>().await;
+                }
+            },
+        )
+        .await
+    }
+
+    /// Runs every instance with `launch` until they stop or `shutdown` completes. On shutdown, the
+    /// instances are asked to stop through the [Shutdown] given to `launch`, and the ones still
+    /// running after the shutdown timeout are dropped.
+    pub async fn run_with<L, F>(
+        &self,
+        capture_sender: CaptureSender,
+        launch: L,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<()>
+    where
+        L: Fn(Config, CaptureSender, Shutdown) -> F,
+        F: Future<Output = Result<()>>,
+    {
+        let (stop, instance_shutdown) = Shutdown::channel();
+        let instances = self.instances.iter().enumerate().map(|(index, instance)| {
+            let application = launch(
+                instance.config.clone(),
+                capture_sender.clone(),
+                instance_shutdown.clone(),
+            );
+            async move {
+                self.set_status(index, InstanceStatus::Running);
+                let status = match application.await {
+                    Ok(()) => InstanceStatus::Stopped,
+                    Err(error) => InstanceStatus::Failed(format!("{:#}", error)),
+                };
+                self.set_status(index, status);
+            }
+        });
+        let mut instances = Box::pin(join_all(instances));
+
+        tokio::select! {
+            _ = &mut instances => (),
+            _ = shutdown => {
+                println!("Stopping {} instances.", self.instances.len());
+                let _ = stop.send(true);
+                if timeout(self.shutdown_timeout, &mut instances).await.is_err() {
+                    println!(
+                        "Instances still running after {} seconds, dropping them.",
+                        self.shutdown_timeout.as_secs()
+                    );
+                }
+            }
+        }
+        drop(instances);
+
+        let statuses = self.status.borrow().clone();
+        let mut failed = Vec::new();
+        for (index, status) in statuses.into_iter().enumerate() {
+            match status {
+                InstanceStatus::Failed(error) => {
+                    failed.push(format!("'{}' ({})", self.instances[index].name, error))
+                }
+                InstanceStatus::Pending | InstanceStatus::Running => {
+                    self.set_status(index, InstanceStatus::Stopped)
+                }
+                InstanceStatus::Stopped => (),
+            }
+        }
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::Error::msg(format!(
+                "{} of {} instances failed: {}.",
+                failed.len(),
+                self.instances.len(),
+                failed.join(", ")
+            )))
+        }
+    }
+
+    fn set_status(&self, index: usize, status: InstanceStatus) {
+        println!("Instance '{}' {}.", self.instances[index].name, status);
+        self.status.send_modify(|statuses| statuses[index] = status);
+    }
+}
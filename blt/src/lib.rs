@@ -4,14 +4,33 @@ pub mod application_descriptor;
 pub mod application_handler;
 pub mod application_server;
 pub mod applications;
+pub mod backend;
+pub mod backoff;
 pub mod blt_application;
+pub mod capture;
+pub mod client_manager;
+pub mod config;
+pub mod device_database;
 pub mod gatt_application;
+pub mod instance_supervisor;
+pub mod pairing_agent;
+pub mod shutdown;
+pub mod time_sync_daemon;
 
-pub use adapter_manager::AdapterManager;
-pub use application_client::ApplicationClient;
+pub use adapter_manager::{AdapterManager, AdapterSelector, DeviceInfo};
+pub use application_client::{ApplicationClient, ClientSession};
 pub use application_descriptor::ApplicationDescriptor;
 pub use application_handler::ApplicationHandler;
 pub use application_server::ApplicationServer;
 pub use applications::*;
+pub use backoff::Backoff;
 pub use blt_application::BltApplication;
+pub use capture::{Capture, CaptureDevice, CaptureSender, Reading, SIGNAL_LOSS};
+pub use client_manager::ClientManager;
+pub use config::Config;
+pub use device_database::{DeviceDatabase, PairingPolicy};
 pub use gatt_application::GattApplication;
+pub use instance_supervisor::{Instance, InstanceStatus, InstanceSupervisor};
+pub use pairing_agent::{PairingAgent, PairingAnswer, PairingHandler, PairingRequest};
+pub use shutdown::Shutdown;
+pub use time_sync_daemon::TimeSyncDaemon;
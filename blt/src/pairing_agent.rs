@@ -0,0 +1,293 @@
+use crate::config::{Config, PairingSettings};
+use crate::device_database::{DeviceDatabase, PairingPolicy, MAX_PASSKEY};
+use anyhow::Result;
+use async_trait::async_trait;
+use bluer::Address;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// What BlueZ asks the agent while pairing a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PairingRequestKind {
+    /// PIN code to send to the device (legacy pairing).
+    PinCode,
+    /// Passkey shown by the device, to be sent back to it.
+    Passkey,
+    /// Whether the device shows the same passkey (numeric comparison).
+    Confirmation(u32),
+    /// Whether to accept a pairing started by the device without a passkey (just works).
+    Authorization,
+    /// PIN code to be typed on the device.
+    DisplayPinCode(String),
+    /// Passkey to be typed on the device.
+    DisplayPasskey(u32),
+}
+
+/// Pairing request of a device. Its name and alias are read when the request arrives, so they may
+/// be unknown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairingRequest {
+    pub address: Address,
+    pub name: Option<String>,
+    pub alias: Option<String>,
+    pub kind: PairingRequestKind,
+}
+
+impl fmt::Display for PairingRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "device {}", self.address)?;
+        if let Some(alias) = &self.alias {
+            write!(f, " '{}'", alias)?;
+        }
+        match &self.kind {
+            PairingRequestKind::PinCode => write!(f, " requests a PIN code"),
+            PairingRequestKind::Passkey => write!(f, " requests a passkey"),
+            PairingRequestKind::Confirmation(passkey) => {
+                write!(f, " requests to confirm passkey {:06}", passkey)
+            }
+            PairingRequestKind::Authorization => write!(f, " requests to pair"),
+            PairingRequestKind::DisplayPinCode(pin_code) => {
+                write!(f, " needs PIN code {} to be typed on it", pin_code)
+            }
+            PairingRequestKind::DisplayPasskey(passkey) => {
+                write!(f, " needs passkey {:06} to be typed on it", passkey)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PairingAnswer {
+    PinCode(String),
+    Passkey(u32),
+    /// Confirms, authorizes or acknowledges a displayed code.
+    Accept,
+    Reject,
+}
+
+impl fmt::Display for PairingAnswer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PairingAnswer::PinCode(pin_code) => write!(f, "PIN code {}", pin_code),
+            PairingAnswer::Passkey(passkey) => write!(f, "passkey {:06}", passkey),
+            PairingAnswer::Accept => write!(f, "accepted"),
+            PairingAnswer::Reject => write!(f, "rejected"),
+        }
+    }
+}
+
+/// Answers the pairing requests of devices. Closures taking the request are handlers too.
+#[async_trait]
+pub trait PairingHandler: Send + Sync {
+    async fn answer(&self, request: &PairingRequest) -> PairingAnswer;
+}
+
+#[async_trait]
+impl<F> PairingHandler for F
+where
+    F: Fn(&PairingRequest) -> PairingAnswer + Send + Sync,
+{
+    async fn answer(&self, request: &PairingRequest) -> PairingAnswer {
+        self(request)
+    }
+}
+
+/// Where the answers of the pairing agent come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingAgentKind {
+    /// Device database and `[pairing]` settings.
+    Config,
+    /// Asks on the terminal.
+    Prompt,
+    /// No agent: BlueZ only pairs devices without passkey.
+    None,
+}
+
+impl FromStr for PairingAgentKind {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.to_lowercase().as_str() {
+            "config" => Ok(PairingAgentKind::Config),
+            "prompt" => Ok(PairingAgentKind::Prompt),
+            "none" => Ok(PairingAgentKind::None),
+            _ => Err(anyhow::Error::msg(format!(
+                "Unknown pairing agent '{}' (available: config, prompt, none).",
+                input
+            ))),
+        }
+    }
+}
+
+/// Pairing agent registered by an [AdapterManager](crate::AdapterManager). It logs every request
+/// and the answer of its handler, rejecting answers that don't fit the request.
+#[derive(Clone)]
+pub struct PairingAgent {
+    handler: Arc<dyn PairingHandler>,
+}
+
+impl PairingAgent {
+    pub fn new(handler: impl PairingHandler + 'static) -> Self {
+        Self {
+            handler: Arc::new(handler),
+        }
+    }
+
+    /// Agent selected by `pairing.agent`, none if it is `none`.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        match config.pairing.agent.parse()? {
+            PairingAgentKind::Config => Ok(Some(PairingAgent::new(ConfiguredPairing::new(
+                DeviceDatabase::load(config.client.device_database.as_deref())?,
+                config.pairing.clone(),
+            )))),
+            PairingAgentKind::Prompt => Ok(Some(PairingAgent::new(PromptPairing))),
+            PairingAgentKind::None => Ok(None),
+        }
+    }
+
+    pub async fn answer(&self, request: &PairingRequest) -> PairingAnswer {
+        let answer = match (&request.kind, self.handler.answer(request).await) {
+            (PairingRequestKind::PinCode, PairingAnswer::PinCode(pin_code))
+                if is_valid_pin_code(&pin_code) =>
+            {
+                PairingAnswer::PinCode(pin_code)
+            }
+            (PairingRequestKind::Passkey, PairingAnswer::Passkey(passkey))
+                if passkey <= MAX_PASSKEY =>
+            {
+                PairingAnswer::Passkey(passkey)
+            }
+            (
+                PairingRequestKind::Confirmation(_)
+                | PairingRequestKind::Authorization
+                | PairingRequestKind::DisplayPinCode(_)
+                | PairingRequestKind::DisplayPasskey(_),
+                PairingAnswer::Accept,
+            ) => PairingAnswer::Accept,
+            (_, PairingAnswer::Reject) => PairingAnswer::Reject,
+            (_, answer) => {
+                println!("Pairing agent: invalid answer '{}' to {}.", answer, request);
+                PairingAnswer::Reject
+            }
+        };
+        println!("Pairing agent: {}, {}.", request, answer);
+        answer
+    }
+}
+
+/// PIN codes have 1 to 16 alphanumeric characters.
+pub(crate) fn is_valid_pin_code(pin_code: &str) -> bool {
+    (1..=16).contains(&pin_code.len()) && pin_code.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Answers from the device database and the `[pairing]` settings. The passkey of the device rule,
+/// or `pairing.passkey`, is entered and must match numeric comparisons. Devices without an
+/// expected passkey are confirmed only if `pairing.confirm` is set, and pairings started by a
+/// device are authorized only if a rule allows pairing it.
+pub struct ConfiguredPairing {
+    device_database: DeviceDatabase,
+    settings: PairingSettings,
+}
+
+impl ConfiguredPairing {
+    pub fn new(device_database: DeviceDatabase, settings: PairingSettings) -> Self {
+        Self {
+            device_database,
+            settings,
+        }
+    }
+
+    fn expected_passkey(&self, request: &PairingRequest) -> Option<u32> {
+        self.device_database
+            .find_rule(
+                request.address,
+                request.name.as_deref(),
+                request.alias.as_deref(),
+            )
+            .and_then(|rule| rule.passkey)
+            .or(self.settings.passkey)
+    }
+}
+
+#[async_trait]
+impl PairingHandler for ConfiguredPairing {
+    async fn answer(&self, request: &PairingRequest) -> PairingAnswer {
+        match &request.kind {
+            PairingRequestKind::PinCode => match &self.settings.pin_code {
+                Some(pin_code) => PairingAnswer::PinCode(pin_code.clone()),
+                None => PairingAnswer::Reject,
+            },
+            PairingRequestKind::Passkey => match self.expected_passkey(request) {
+                Some(passkey) => PairingAnswer::Passkey(passkey),
+                None => PairingAnswer::Reject,
+            },
+            PairingRequestKind::Confirmation(passkey) => match self.expected_passkey(request) {
+                Some(expected) if expected == *passkey => PairingAnswer::Accept,
+                None if self.settings.confirm => PairingAnswer::Accept,
+                _ => PairingAnswer::Reject,
+            },
+            PairingRequestKind::Authorization => match self.device_database.find_rule(
+                request.address,
+                request.name.as_deref(),
+                request.alias.as_deref(),
+            ) {
+                Some(rule) if rule.pairing != PairingPolicy::Never => PairingAnswer::Accept,
+                _ => PairingAnswer::Reject,
+            },
+            PairingRequestKind::DisplayPinCode(_) | PairingRequestKind::DisplayPasskey(_) => {
+                PairingAnswer::Accept
+            }
+        }
+    }
+}
+
+/// Asks the user on the terminal.
+pub struct PromptPairing;
+
+impl PromptPairing {
+    async fn prompt(question: String) -> Option<String> {
+        tokio::task::spawn_blocking(move || {
+            print!("{}: ", question);
+            std::io::stdout().flush().ok()?;
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).ok()?;
+            Some(line.trim().to_string())
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+}
+
+#[async_trait]
+impl PairingHandler for PromptPairing {
+    async fn answer(&self, request: &PairingRequest) -> PairingAnswer {
+        let question = match &request.kind {
+            PairingRequestKind::PinCode | PairingRequestKind::Passkey => {
+                format!("Pairing {}, enter it (empty to reject)", request)
+            }
+            PairingRequestKind::Confirmation(_) | PairingRequestKind::Authorization => {
+                format!("Pairing {}, accept? (yes/no)", request)
+            }
+            PairingRequestKind::DisplayPinCode(_) | PairingRequestKind::DisplayPasskey(_) => {
+                println!("Pairing {}.", request);
+                return PairingAnswer::Accept;
+            }
+        };
+        let input = match PromptPairing::prompt(question).await {
+            Some(input) if !input.is_empty() => input,
+            _ => return PairingAnswer::Reject,
+        };
+
+        match &request.kind {
+            PairingRequestKind::PinCode => PairingAnswer::PinCode(input),
+            PairingRequestKind::Passkey => input
+                .parse()
+                .map_or(PairingAnswer::Reject, PairingAnswer::Passkey),
+            _ if matches!(input.to_lowercase().as_str(), "y" | "yes") => PairingAnswer::Accept,
+            _ => PairingAnswer::Reject,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+use futures::future;
+use std::future::Future;
+use tokio::sync::watch;
+
+/// Request to stop, observed by the applications run by the reader: Ctrl+C by default, or the
+/// instance supervisor stopping its instances.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    /// Ctrl+C if not defined.
+    receiver: Option<watch::Receiver<bool>>,
+}
+
+impl Shutdown {
+    /// Shutdown requested by pressing Ctrl+C.
+    pub fn ctrl_c() -> Self {
+        Self::default()
+    }
+
+    /// Shutdown requested by sending true to the returned sender.
+    pub fn channel() -> (watch::Sender<bool>, Self) {
+        let (sender, receiver) = watch::channel(false);
+        (
+            sender,
+            Self {
+                receiver: Some(receiver),
+            },
+        )
+    }
+
+    /// Completes once the shutdown is requested.
+    pub async fn requested(&self) {
+        match &self.receiver {
+            Some(receiver) => {
+                // A sender dropped without requesting it never does.
+                if receiver
+                    .clone()
+                    .wait_for(|requested| *requested)
+                    .await
+                    .is_err()
+                {
+                    future::pending::<()>().await;
+                }
+            }
+            None => {
+                if tokio::signal::ctrl_c().await.is_err() {
+                    future::pending::<()>().await;
+                }
+            }
+        }
+    }
+
+    /// Runs `application` until it stops or the shutdown is requested.
+    pub async fn run_until<F>(&self, application: F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        tokio::select! {
+            result = application => result,
+            _ = self.requested() => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use crate::backend::{AdapterEvent, BltCharacteristic};
+use crate::config::AdapterSettings;
+use crate::cts::{
+    find_current_time_characteristic, read_service_value, service_uuid, write_service_value,
+};
+use crate::shutdown::Shutdown;
+use crate::AdapterManager;
+use anyhow::Result;
+use bluer::Address;
+use chrono::NaiveDateTime;
+use futures::StreamExt;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::time::{sleep, timeout_at, Instant};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const DEFAULT_THRESHOLD_SECONDS: i64 = 1;
+const DEFAULT_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_HISTORY_LENGTH: usize = 96;
+/// Typical watch crystals drift less than 50 ppm.
+const DEFAULT_MAX_DRIFT_RATE: f64 = 100.0;
+
+pub struct TimeSyncConfig {
+    /// Time between synchronization rounds.
+    pub interval: Duration,
+    /// Clock offset above which a band is resynchronized.
+    pub threshold: chrono::Duration,
+    /// How long each round looks for new bands.
+    pub discovery_timeout: Duration,
+    /// Samples kept per band.
+    pub history_length: usize,
+    /// Drift, in parts per million, above which a band clock is reported as failing.
+    pub max_drift_rate: f64,
+    /// CSV file where every sample is appended, if any.
+    pub history_path: Option<PathBuf>,
+}
+
+impl Default for TimeSyncConfig {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_INTERVAL,
+            threshold: chrono::Duration::seconds(DEFAULT_THRESHOLD_SECONDS),
+            discovery_timeout: DEFAULT_DISCOVERY_TIMEOUT,
+            history_length: DEFAULT_HISTORY_LENGTH,
+            max_drift_rate: DEFAULT_MAX_DRIFT_RATE,
+            history_path: None,
+        }
+    }
+}
+
+/// Band clock offset measured in a synchronization round.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftSample {
+    /// Local time of the measurement.
+    pub measured_at: NaiveDateTime,
+    /// Band clock minus local clock.
+    pub offset: chrono::Duration,
+    /// Whether the band clock was reset after the measurement.
+    pub synchronized: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct DriftHistory {
+    samples: VecDeque<DriftSample>,
+}
+
+impl DriftHistory {
+    pub fn samples(&self) -> &VecDeque<DriftSample> {
+        &self.samples
+    }
+
+    fn push(&mut self, sample: DriftSample, history_length: usize) {
+        self.samples.push_back(sample);
+        while self.samples.len() > history_length.max(2) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Mean drift between consecutive samples, in parts per million (positive when the band clock
+    /// runs fast). None until there are two samples.
+    pub fn drift_rate(&self) -> Option<f64> {
+        let rates: Vec<f64> = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .filter_map(|(previous, sample)| {
+                let elapsed = (sample.measured_at - previous.measured_at).num_microseconds()?;
+                if elapsed <= 0 {
+                    return None;
+                }
+                let start = if previous.synchronized {
+                    0
+                } else {
+                    previous.offset.num_microseconds()?
+                };
+                let drift = sample.offset.num_microseconds()? - start;
+                Some(drift as f64 / elapsed as f64 * 1_000_000.0)
+            })
+            .collect();
+
+        if rates.is_empty() {
+            None
+        } else {
+            Some(rates.iter().sum::<f64>() / rates.len() as f64)
+        }
+    }
+}
+
+/// Long running Current Time Service client: every round it discovers bands, reconnects to every
+/// known band, measures its clock offset and resynchronizes it when it passes the threshold.
+pub struct TimeSyncDaemon {
+    adapter_manager: AdapterManager,
+    config: TimeSyncConfig,
+    local_clock: Box<dyn Fn() -> NaiveDateTime + Send + Sync>,
+    known_devices: BTreeSet<Address>,
+    histories: HashMap<Address, DriftHistory>,
+    shutdown: Shutdown,
+}
+
+impl TimeSyncDaemon {
+    pub async fn start(
+        adapter_settings: &AdapterSettings,
+        config: TimeSyncConfig,
+        shutdown: Shutdown,
+    ) -> Result<()> {
+        TimeSyncDaemon::new(AdapterManager::new(adapter_settings).await?, config)
+            .with_shutdown(shutdown)
+            .run()
+            .await
+    }
+
+    pub fn new(adapter_manager: AdapterManager, config: TimeSyncConfig) -> Self {
+        Self {
+            adapter_manager,
+            config,
+            local_clock: Box::new(|| chrono::Utc::now().naive_utc()),
+            known_devices: BTreeSet::new(),
+            histories: HashMap::new(),
+            shutdown: Shutdown::default(),
+        }
+    }
+
+    /// Replaces the reference clock (UTC) bands are synchronized to.
+    pub fn with_local_clock(
+        mut self,
+        local_clock: impl Fn() -> NaiveDateTime + Send + Sync + 'static,
+    ) -> Self {
+        self.local_clock = Box::new(local_clock);
+        self
+    }
+
+    /// Stops the daemon, Ctrl+C by default.
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    pub fn history(&self, address: &Address) -> Option<&DriftHistory> {
+        self.histories.get(address)
+    }
+
+    /// Synchronizes bands until the shutdown is requested, then shuts the adapter down.
+    pub async fn run(mut self) -> Result<()> {
+        let result = self.synchronize().await;
+        result.and(self.adapter_manager.shutdown().await)
+    }
+
+    async fn synchronize(&mut self) -> Result<()> {
+        let adapter = self.adapter_manager.adapter();
+        println!(
+            "Synchronizing bands on Bluetooth adapter {} with address {} every {} seconds. Press Ctrl+C to quit.",
+            adapter.name(),
+            adapter.address().await?,
+            self.config.interval.as_secs()
+        );
+
+        let shutdown = self.shutdown.clone();
+        loop {
+            tokio::select! {
+                _ = shutdown.requested() => break,
+                _ = async {
+                    self.synchronize_round().await;
+                    sleep(self.config.interval).await;
+                } => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Discovers bands and synchronizes every known one.
+    pub async fn synchronize_round(&mut self) {
+        if let Err(error) = self.discover_devices().await {
+            println!("Discovery failed: {}.", error);
+        }
+
+        for address in self.known_devices.clone() {
+            if let Err(error) = self.synchronize_device(address).await {
+                println!("Device {} synchronization failed: {}.", address, error);
+            }
+        }
+    }
+
+    async fn discover_devices(&mut self) -> Result<()> {
+        let adapter = self.adapter_manager.adapter();
+        let mut discover = adapter.discover_devices().await?;
+        let deadline = Instant::now() + self.config.discovery_timeout;
+
+        while let Ok(Some(event)) = timeout_at(deadline, discover.next()).await {
+            if let AdapterEvent::DeviceAdded(address) = event {
+                if self.known_devices.contains(&address) {
+                    continue;
+                }
+                // A device that can't be inspected is skipped, discovery goes on.
+                let uuids = match adapter.device(address) {
+                    Ok(device) => device.uuids().await,
+                    Err(error) => Err(error),
+                };
+                let uuids = match uuids {
+                    Ok(uuids) => uuids.unwrap_or_default(),
+                    Err(error) => {
+                        println!("Device {} skipped: {}.", address, error);
+                        continue;
+                    }
+                };
+                if uuids.contains(&service_uuid()) {
+                    println!("Discovered band {}.", address);
+                    self.known_devices.insert(address);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn synchronize_device(&mut self, address: Address) -> Result<()> {
+        let device = self.adapter_manager.adapter().device(address)?;
+        let result = match find_current_time_characteristic(device.as_ref()).await {
+            Ok(Some(characteristic)) => {
+                self.synchronize_clock(address, characteristic.as_ref())
+                    .await
+            }
+            Ok(None) => Err(anyhow::Error::msg("Current Time characteristic not found")),
+            Err(error) => Err(error),
+        };
+
+        if device.is_connected().await.unwrap_or_default() {
+            if let Err(error) = device.disconnect().await {
+                println!("Device {} disconnection failed: {}.", address, error);
+            }
+        }
+
+        result
+    }
+
+    async fn synchronize_clock(
+        &mut self,
+        address: Address,
+        characteristic: &dyn BltCharacteristic,
+    ) -> Result<()> {
+        let current_time = read_service_value(characteristic).await?;
+        let measured_at = (self.local_clock)();
+        let offset = match current_time.date_time() {
+            Some(date_time) => date_time - measured_at,
+            None => {
+                // Without a date there is no offset to record, the band is just synchronized.
+                println!("Device {}: date not known, synchronized.", address);
+                return write_service_value(&(self.local_clock)(), characteristic).await;
+            }
+        };
+        let synchronized =
+            offset.num_milliseconds().abs() > self.config.threshold.num_milliseconds();
+        if synchronized {
+            write_service_value(&(self.local_clock)(), characteristic).await?;
+        }
+
+        let sample = DriftSample {
+            measured_at,
+            offset,
+            synchronized,
+        };
+        println!(
+            "Device {}: offset {} ms{}.",
+            address,
+            offset.num_milliseconds(),
+            if synchronized { ", synchronized" } else { "" }
+        );
+        self.append_to_history_file(&address, &sample);
+
+        let history = self.histories.entry(address).or_default();
+        history.push(sample, self.config.history_length);
+        if let Some(drift_rate) = history.drift_rate() {
+            if drift_rate.abs() > self.config.max_drift_rate {
+                println!(
+                    "Device {} clock drifts {:.1} ppm (more than {} ppm), it may be failing.",
+                    address, drift_rate, self.config.max_drift_rate
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn append_to_history_file(&self, address: &Address, sample: &DriftSample) {
+        if let Some(path) = &self.config.history_path {
+            let result = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| {
+                    writeln!(
+                        file,
+                        "{},{},{},{}",
+                        address,
+                        sample.measured_at.format("%F %T%.3f"),
+                        sample.offset.num_milliseconds(),
+                        sample.synchronized
+                    )
+                });
+            if let Err(error) = result {
+                println!("Unable to write to '{}': {}.", path.display(), error);
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use blt::adapter_manager::{AdapterCapability, AdapterInfo};
+use blt::backend::{BltAdapter, LoopbackAdapter, LoopbackBus};
+use blt::config::AdapterSettings;
+use blt::{AdapterManager, AdapterSelector};
+use std::time::Duration;
+use uuid::Uuid;
+
+const SERVICE: Uuid = Uuid::from_u128(0x0000180d_0000_1000_8000_00805f9b34fb);
+
+fn adapter_manager(bus: &std::sync::Arc<LoopbackBus>) -> AdapterManager {
+    AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(bus, "reader")))
+}
+
+#[tokio::test(start_paused = true)]
+async fn scans_list_advertising_devices() {
+    let bus = LoopbackBus::new();
+    let band = LoopbackAdapter::new(&bus, "band");
+    let _advertisement = band.advertise_gatt_service(SERVICE, "Band").await.unwrap();
+    let _silent = LoopbackAdapter::new(&bus, "silent");
+
+    let devices = adapter_manager(&bus)
+        .scan(Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].address, band.address().await.unwrap());
+    assert_eq!(devices[0].name.as_deref(), Some("Band"));
+    assert_eq!(devices[0].alias, "Band");
+    assert_eq!(devices[0].uuids, vec![SERVICE]);
+    assert!(!devices[0].paired);
+}
+
+#[tokio::test(start_paused = true)]
+async fn devices_are_paired_and_unpaired() {
+    let bus = LoopbackBus::new();
+    let band = LoopbackAdapter::new(&bus, "band");
+    let _advertisement = band.advertise_gatt_service(SERVICE, "Band").await.unwrap();
+    let address = band.address().await.unwrap();
+    let adapter_manager = adapter_manager(&bus);
+
+    let device_info = adapter_manager
+        .pair_device(address, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert!(device_info.paired);
+
+    adapter_manager.unpair_device(address).await.unwrap();
+    let device = adapter_manager
+        .find_device(address, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert!(!device.is_paired().await.unwrap());
+}
+
+#[tokio::test(start_paused = true)]
+async fn unknown_devices_are_not_found() {
+    let bus = LoopbackBus::new();
+    let address = "02:00:00:00:ff:ff".parse().unwrap();
+
+    let error = adapter_manager(&bus)
+        .find_device(address, Duration::from_secs(3))
+        .await
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("not found"));
+}
+
+fn adapters() -> Vec<AdapterInfo> {
+    vec![
+        AdapterInfo {
+            name: "hci0".to_string(),
+            address: "B8:27:EB:00:00:01".parse().unwrap(),
+            capabilities: Default::default(),
+        },
+        AdapterInfo {
+            name: "hci1".to_string(),
+            address: "00:1A:7D:DA:71:13".parse().unwrap(),
+            capabilities: [AdapterCapability::Advertising, AdapterCapability::LeCoded]
+                .into_iter()
+                .collect(),
+        },
+    ]
+}
+
+fn select(settings: AdapterSettings) -> anyhow::Result<String> {
+    let adapters = adapters();
+    let adapter = AdapterSelector::from_settings(&settings)?.select(&adapters)?;
+    Ok(adapter.name.clone())
+}
+
+#[test]
+fn adapters_are_selected_by_name_address_or_capability() {
+    assert_eq!(select(AdapterSettings::default()).unwrap(), "hci0");
+    assert_eq!(
+        select(AdapterSettings {
+            name: Some("hci1".to_string()),
+            ..Default::default()
+        })
+        .unwrap(),
+        "hci1"
+    );
+    assert_eq!(
+        select(AdapterSettings {
+            address: Some("00:1a:7d:da:71:13".to_string()),
+            ..Default::default()
+        })
+        .unwrap(),
+        "hci1"
+    );
+    assert_eq!(
+        select(AdapterSettings {
+            capabilities: vec!["advertising".to_string()],
+            ..Default::default()
+        })
+        .unwrap(),
+        "hci1"
+    );
+
+    let error = select(AdapterSettings {
+        name: Some("hci0".to_string()),
+        capabilities: vec!["le_2m".to_string()],
+        ..Default::default()
+    })
+    .unwrap_err()
+    .to_string();
+    assert!(error.contains("named 'hci0' supporting le_2m"), "{}", error);
+    assert!(
+        error.contains("hci1 [00:1A:7D:DA:71:13] advertising le_coded"),
+        "{}",
+        error
+    );
+
+    let error = AdapterSelector::default().select(&[]).unwrap_err();
+    assert_eq!(error.to_string(), "No Bluetooth adapter present.");
+}
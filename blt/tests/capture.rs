@@ -0,0 +1,101 @@
+use blt::capture::CAPTURE_SCHEMA_VERSION;
+use blt::{Capture, CaptureDevice, CaptureSender, Reading};
+use uuid::Uuid;
+
+fn capture() -> Capture {
+    Capture::new("16480102".to_string())
+        .with_source(Uuid::from_u128(0x180d), Uuid::from_u128(0x2a37))
+        .with_reading(Reading::new(72.0, "bpm"))
+        .with_device_timestamp(1_650_000_000_000)
+}
+
+#[test]
+fn captures_round_trip_through_json() {
+    let capture = capture();
+    assert_eq!(capture.version, CAPTURE_SCHEMA_VERSION);
+    assert_eq!(Capture::from_json(&capture.to_json()).unwrap(), capture);
+}
+
+#[test]
+fn captures_round_trip_through_bytes() {
+    let capture = capture();
+    let bytes = capture.to_bytes();
+    assert_eq!(bytes[0], CAPTURE_SCHEMA_VERSION);
+    assert!(bytes.len() < capture.to_json().len());
+    assert_eq!(Capture::from_bytes(&bytes).unwrap(), capture);
+}
+
+#[test]
+fn version_1_captures_are_decoded() {
+    let capture = Capture::from_json(br#"{"value":"5a3c","timestamp":1650000000000}"#).unwrap();
+    assert_eq!(capture.version, 1);
+    assert_eq!(capture.id, Uuid::nil());
+    assert_eq!(capture.value, "5a3c");
+    assert_eq!(capture.timestamp, 1_650_000_000_000);
+    assert_eq!(capture.reading, None);
+}
+
+#[test]
+fn unknown_versions_are_rejected() {
+    let json = format!(
+        r#"{{"version":{},"value":"5a3c","timestamp":0}}"#,
+        CAPTURE_SCHEMA_VERSION + 1
+    );
+    assert!(Capture::from_json(json.as_bytes()).is_err());
+
+    let mut bytes = capture().to_bytes();
+    bytes[0] = CAPTURE_SCHEMA_VERSION + 1;
+    assert!(Capture::from_bytes(&bytes).is_err());
+    assert!(Capture::from_bytes(&[]).is_err());
+}
+
+#[test]
+fn capture_sender_sets_reader_and_device() {
+    let device = CaptureDevice {
+        address: "AA:BB:CC:DD:EE:FF".to_string(),
+        name: Some("InfiniTime".to_string()),
+    };
+    let (capture_sender, mut captures) = CaptureSender::channel();
+    let capture_sender = capture_sender
+        .with_reader_id("reader".to_string())
+        .with_device(device.clone());
+
+    assert!(capture_sender.send(capture()));
+    let capture = captures.try_recv().unwrap();
+    assert_eq!(capture.reader_id.as_deref(), Some("reader"));
+    assert_eq!(capture.device, Some(device));
+}
+
+#[test]
+fn signing_bytes_are_pinned_to_the_capture_version() {
+    let capture = Capture {
+        id: Uuid::from_u128(1),
+        reader_id: Some("r".to_string()),
+        timestamp: 2,
+        signature: Some("00".to_string()),
+        ..Capture::new("5a".to_string())
+    };
+    // Captures signed by this reader must still verify once the schema changes.
+    assert_eq!(
+        blt::capture::to_hex(&capture.signing_bytes().unwrap()),
+        "0200000000000000000000000000000001010100000000000000720000000200000000000000356100000200000000000000"
+    );
+    assert_eq!(
+        capture.signing_bytes().unwrap(),
+        Capture {
+            signature: None,
+            ..capture.clone()
+        }
+        .signing_bytes()
+        .unwrap()
+    );
+
+    let legacy = Capture::from_json(br#"{"value":"5a3c","timestamp":0}"#).unwrap();
+    assert!(legacy.signing_bytes().is_ok());
+    assert!(Capture {
+        version: CAPTURE_SCHEMA_VERSION + 1,
+        ..capture
+    }
+    .signing_bytes()
+    .is_err());
+}
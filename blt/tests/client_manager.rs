@@ -0,0 +1,210 @@
+use blt::backend::{BltAdapter, BltCharacteristic, LoopbackAdapter, LoopbackBus};
+use blt::config::ClientSettings;
+use blt::cts::CTS;
+use blt::current_time::CurrentTime;
+use blt::heart_rate::HeartRate;
+use blt::{
+    AdapterManager, ApplicationClient, ApplicationServer, Capture, CaptureSender, ClientManager,
+};
+use bluer::Address;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Instant};
+use uuid::Uuid;
+
+const CURRENT_TIME_CHARACTERISTIC: Uuid = Uuid::from_u128(0x00002a2b_0000_1000_8000_00805f9b34fb);
+
+async fn spawn_server(bus: &Arc<LoopbackBus>, name: &str) -> (Address, JoinHandle<()>) {
+    let adapter = LoopbackAdapter::new(bus, name);
+    let address = adapter.address().await.unwrap();
+    let server = ApplicationServer::new(
+        Box::new(HeartRate::default()),
+        AdapterManager::with_adapter(Box::new(adapter)),
+    );
+    (
+        address,
+        tokio::spawn(async move {
+            let _ = server.run().await;
+        }),
+    )
+}
+
+/// Devices of the captures received during `duration`.
+async fn capture_devices(
+    captures: &mut UnboundedReceiver<Capture>,
+    duration: Duration,
+) -> HashSet<String> {
+    let deadline = Instant::now() + duration;
+    let mut devices = HashSet::new();
+    while let Ok(Some(capture)) = tokio::time::timeout_at(deadline, captures.recv()).await {
+        devices.insert(capture.device.unwrap().address);
+    }
+    devices
+}
+
+#[tokio::test(start_paused = true)]
+async fn sessions_are_held_with_every_device_up_to_the_limit() {
+    let bus = LoopbackBus::new();
+    let (first, first_server) = spawn_server(&bus, "band_1").await;
+    let (second, second_server) = spawn_server(&bus, "band_2").await;
+    let (third, third_server) = spawn_server(&bus, "band_3").await;
+    sleep(Duration::from_secs(1)).await;
+
+    let (capture_sender, mut captures) = CaptureSender::channel();
+    // Bands going away end their session right away.
+    let settings = ClientSettings {
+        reconnect_attempts: 0,
+        ..Default::default()
+    };
+    let client = ClientManager::new(
+        ApplicationClient::new(
+            Box::new(HeartRate::default()),
+            AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader"))),
+        )
+        .with_settings(settings)
+        .with_capture_sender(capture_sender),
+    )
+    .with_max_sessions(2);
+    let client = tokio::spawn(client.run());
+
+    let devices = capture_devices(&mut captures, Duration::from_secs(30)).await;
+    assert_eq!(devices.len(), 2);
+
+    // Once a band goes away, the device waiting for a session gets it.
+    let (gone, server) = [
+        (first, &first_server),
+        (second, &second_server),
+        (third, &third_server),
+    ]
+    .into_iter()
+    .find(|(address, _)| devices.contains(&address.to_string()))
+    .unwrap();
+    server.abort();
+    sleep(Duration::from_secs(5)).await;
+    while captures.try_recv().is_ok() {}
+
+    let expected: HashSet<String> = [first, second, third]
+        .iter()
+        .filter(|address| **address != gone)
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        capture_devices(&mut captures, Duration::from_secs(30)).await,
+        expected
+    );
+    assert!(!client.is_finished());
+
+    client.abort();
+    for server in [first_server, second_server, third_server] {
+        server.abort();
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn devices_without_the_application_are_skipped() {
+    let bus = LoopbackBus::new();
+    let other = LoopbackAdapter::new(&bus, "other");
+    let _advertisement = other
+        .advertise_gatt_service(Uuid::new_v4(), "Other")
+        .await
+        .unwrap();
+    let (band, server) = spawn_server(&bus, "band").await;
+    sleep(Duration::from_secs(1)).await;
+
+    let (capture_sender, mut captures) = CaptureSender::channel();
+    let client = tokio::spawn(
+        ClientManager::new(
+            ApplicationClient::new(
+                Box::new(HeartRate::default()),
+                AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader"))),
+            )
+            .with_capture_sender(capture_sender),
+        )
+        .with_max_sessions(1)
+        .run(),
+    );
+
+    assert_eq!(
+        capture_devices(&mut captures, Duration::from_secs(30)).await,
+        HashSet::from([band.to_string()])
+    );
+    client.abort();
+    server.abort();
+}
+
+/// Current time characteristic of the CTS band, through a checker adapter.
+async fn current_time(checker: &LoopbackAdapter, band: Address) -> Box<dyn BltCharacteristic> {
+    let device = checker.device(band).unwrap();
+    device.connect().await.unwrap();
+    for service in device.services().await.unwrap() {
+        for characteristic in service.characteristics().await.unwrap() {
+            if characteristic.uuid().await.unwrap() == CURRENT_TIME_CHARACTERISTIC {
+                return characteristic;
+            }
+        }
+    }
+    panic!("Current time characteristic not found.");
+}
+
+/// Difference between the time of the CTS band and the local time.
+async fn cts_drift(checker: &LoopbackAdapter, band: Address) -> chrono::Duration {
+    let value = current_time(checker, band).await.read().await.unwrap();
+    CurrentTime::from_vector(&value)
+        .unwrap()
+        .date_time()
+        .unwrap()
+        - chrono::Utc::now().naive_utc()
+}
+
+#[tokio::test(start_paused = true)]
+async fn ended_sessions_are_held_again_after_the_readmit_delay() {
+    let bus = LoopbackBus::new();
+    let adapter = LoopbackAdapter::new(&bus, "band");
+    let band = adapter.address().await.unwrap();
+    let server = tokio::spawn(
+        ApplicationServer::new(
+            Box::new(CTS::default().with_offset(chrono::Duration::hours(2))),
+            AdapterManager::with_adapter(Box::new(adapter)),
+        )
+        .run(),
+    );
+    sleep(Duration::from_secs(1)).await;
+
+    // The CTS client synchronizes the band and ends its session, while the band stays in range.
+    let settings = ClientSettings {
+        readmit_delay: 60.0,
+        ..Default::default()
+    };
+    let client = tokio::spawn(
+        ClientManager::new(
+            ApplicationClient::new(
+                Box::new(CTS::default()),
+                AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader"))),
+            )
+            .with_settings(settings),
+        )
+        .run(),
+    );
+    sleep(Duration::from_secs(10)).await;
+    let checker = LoopbackAdapter::new(&bus, "checker");
+    assert!(cts_drift(&checker, band).await.num_seconds().abs() < 60);
+
+    // The band drifts again, and is only synchronized once admitted again.
+    let drifted = chrono::Utc::now().naive_utc() + chrono::Duration::hours(2);
+    current_time(&checker, band)
+        .await
+        .write(&CurrentTime::new(drifted).to_vector())
+        .await
+        .unwrap();
+    sleep(Duration::from_secs(10)).await;
+    assert!(cts_drift(&checker, band).await.num_minutes() > 60);
+    sleep(Duration::from_secs(60)).await;
+    assert!(cts_drift(&checker, band).await.num_seconds().abs() < 60);
+    assert!(!client.is_finished());
+
+    client.abort();
+    server.abort();
+}
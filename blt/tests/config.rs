@@ -0,0 +1,156 @@
+use blt::application_factory::{ApplicationFactory, ApplicationMode};
+use blt::Config;
+use std::path::Path;
+
+fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn example_configuration_has_the_default_values() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../resources/phonendo_reader.toml");
+    let config = Config::load_file(&path, Vec::new()).unwrap();
+    assert_eq!(config.app.as_deref(), Some("heart_rate"));
+    assert_eq!(config.mode, Some(ApplicationMode::Client));
+    assert_eq!(
+        Config {
+            app: None,
+            mode: None,
+            ..config
+        },
+        Config::default()
+    );
+}
+
+#[test]
+fn files_set_keys_of_every_section() {
+    let config = Config::parse(
+        r#"
+        app = "cts"
+        mode = "time_sync"
+        seed = 7
+
+        [client]
+        device_database = "bands.toml"
+
+        [apps.cts]
+        offset = -30
+        "#,
+        Vec::new(),
+    )
+    .unwrap();
+    assert_eq!(config.mode, Some(ApplicationMode::TimeSync));
+    assert_eq!(config.seed, Some(7));
+    assert_eq!(
+        config.client.device_database.as_deref(),
+        Some(Path::new("bands.toml"))
+    );
+    assert_eq!(config.client.pair_retries, 5);
+    assert_eq!(config.apps.cts.offset, -30);
+}
+
+#[test]
+fn environment_variables_override_keys() {
+    let config = Config::parse(
+        "mode = \"server\"\n[client]\npair_retries = 1\n",
+        vars(&[
+            ("PHONENDO_MODE", "client"),
+            ("PHONENDO_APP", "mock"),
+            ("PHONENDO_CLIENT__PAIR_RETRIES", "3"),
+            ("PHONENDO_APPS__MOCK__INTERVAL", "0.5"),
+            ("PHONENDO_MANAGER__PEERS", "[\"/ip4/10.0.0.2/tcp/4001\"]"),
+            ("PHONENDO_CONFIG", "ignored.toml"),
+            ("APP_MODE", "server"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(config.mode, Some(ApplicationMode::Client));
+    assert_eq!(config.app.as_deref(), Some("mock"));
+    assert_eq!(config.client.pair_retries, 3);
+    assert_eq!(config.apps.mock.interval, 0.5);
+    assert_eq!(config.manager.peers, ["/ip4/10.0.0.2/tcp/4001"]);
+}
+
+#[test]
+fn environment_variables_take_the_type_of_their_key() {
+    let config = Config::parse(
+        "",
+        vars(&[
+            ("PHONENDO_PAIRING__PIN_CODE", "1234"),
+            ("PHONENDO_PAIRING__CONFIRM", "true"),
+            ("PHONENDO_SERVER__LOCAL_NAME", "42"),
+            ("PHONENDO_CLIENT__RECONNECT_DELAY", "2"),
+            ("PHONENDO_SEED", "9"),
+            // Unknown keys and sections are ignored.
+            ("PHONENDO_LOG_LEVEL", "debug"),
+            ("PHONENDO_CLIENT__PAIR_RETRY", "1"),
+            ("PHONENDO_CLIENT", "1"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(config.pairing.pin_code.as_deref(), Some("1234"));
+    assert!(config.pairing.confirm);
+    assert_eq!(config.server.local_name.as_deref(), Some("42"));
+    assert_eq!(config.client.reconnect_delay, 2.0);
+    assert_eq!(config.seed, Some(9));
+}
+
+#[test]
+fn invalid_configurations_are_rejected() {
+    let error = |content: &str, overrides: &[(&str, &str)]| {
+        Config::parse(content, vars(overrides))
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert!(error("[client]\npair_retry = 1\n", &[]).contains("pair_retry"));
+    assert!(error("", &[("PHONENDO_CLIENT__PAIR_RETRIES", "many")]).contains("pair_retries"));
+    assert!(error("app = \"heartrate\"\n", &[]).contains("Unknown application"));
+    assert!(error("app = \"mock\"\nmode = \"server\"\n", &[]).contains("only runs as a client"));
+    assert!(error("mode = \"daemon\"\n", &[]).contains("daemon"));
+    assert!(error("[apps.mock]\npayload = \"text\"\n", &[]).contains("text"));
+    assert!(error("[pairing]\nagent = \"auto\"\n", &[]).contains("pairing agent"));
+    assert!(error("[adapter]\naddress = \"hci0\"\n", &[]).contains("adapter address"));
+    assert!(error("[adapter]\ncapabilities = [\"wifi\"]\n", &[]).contains("wifi"));
+    assert!(error("[pairing]\npin_code = \"\"\n", &[]).contains("pin_code"));
+    assert!(error("[client]\nmax_sessions = 0\n", &[]).contains("max_sessions"));
+    assert!(error("[client]\nreconnect_max_delay = 0.5\n", &[]).contains("reconnect_max_delay"));
+    assert!(error("[client]\nreadmit_delay = 0.0\n", &[]).contains("readmit_delay"));
+    // Values that don't fit a duration are rejected instead of panicking later.
+    assert!(error("[apps.mock]\ninterval = 1e20\n", &[]).contains("apps.mock.interval"));
+    assert!(error("[client]\nreconnect_delay = inf\n", &[]).contains("reconnect_delay"));
+    assert!(error("[client]\nreadmit_delay = 1e300\n", &[]).contains("readmit_delay"));
+    assert!(error("[apps.cts]\noffset = 9223372036854775807\n", &[]).contains("apps.cts.offset"));
+    assert!(error("[apps.cts]\ndrift_rate = nan\n", &[]).contains("apps.cts.drift_rate"));
+    assert!(error("[apps.cts]\ndrift_rate = -1e6\n", &[]).contains("apps.cts.drift_rate"));
+    assert!(error("[apps.cts]\nsync_threshold = -1\n", &[]).contains("sync_threshold"));
+    assert!(error("[time_sync]\nthreshold = 9223372036854775807\n", &[])
+        .contains("time_sync.threshold"));
+    assert!(error("[time_sync]\nthreshold = -5\n", &[]).contains("time_sync.threshold"));
+    assert!(error("[manager]\ncapture_queue_capacity = 0\n", &[]).contains("capacity"));
+    assert!(error("[manager]\ncapture_queue_eviction = \"fifo\"\n", &[]).contains("fifo"));
+    assert!(error("[manager]\ncapture_queue_retry_interval = 0\n", &[])
+        .contains("capture_queue_retry_interval"));
+    assert!(
+        error("[apps.heart_rate]\nnotification_interval = 0.0\n", &[])
+            .contains("notification_interval")
+    );
+}
+
+#[test]
+fn applications_are_built_from_the_configuration() {
+    let config = Config::parse(
+        "app = \"heart_rate\"\nseed = 3\n[apps.heart_rate]\nscenario = \"nope\"\n",
+        Vec::new(),
+    )
+    .unwrap();
+    assert!(ApplicationFactory::application(&config).is_err());
+
+    let config = Config::parse("app = \"adder\"\nseed = 3\n", Vec::new()).unwrap();
+    let application = ApplicationFactory::application(&config).unwrap();
+    assert_eq!(application.seed(), Some(3));
+
+    assert!(ApplicationFactory::application(&Config::default()).is_err());
+}
@@ -0,0 +1,56 @@
+use blt::cts::EmulatedClock;
+use blt::current_time::{AdjustReason, CurrentTime};
+use chrono::NaiveDate;
+use std::time::Duration;
+use tokio::time::sleep;
+
+#[tokio::test(start_paused = true)]
+async fn clock_drifts_at_the_configured_rate() {
+    let start = NaiveDate::from_ymd(2022, 1, 1).and_hms(0, 0, 0);
+    let fast = EmulatedClock::new(start).with_drift_rate(500.0);
+    let slow = EmulatedClock::new(start).with_drift_rate(-250.0);
+
+    sleep(Duration::from_secs(1000)).await;
+
+    assert_eq!((fast.now() - start).num_milliseconds(), 1_000_500);
+    assert_eq!((slow.now() - start).num_milliseconds(), 999_750);
+}
+
+#[tokio::test(start_paused = true)]
+async fn setting_the_clock_keeps_the_drift() {
+    let start = NaiveDate::from_ymd(2022, 1, 1).and_hms(0, 0, 0);
+    let mut clock = EmulatedClock::new(start).with_drift_rate(1000.0);
+    sleep(Duration::from_secs(3600)).await;
+
+    let date_time = NaiveDate::from_ymd(2022, 6, 1).and_hms(12, 0, 0);
+    clock.set(
+        &CurrentTime::new(date_time).with_adjust_reason(AdjustReason {
+            manual_time_update: true,
+            ..Default::default()
+        }),
+    );
+    assert_eq!(clock.now(), date_time);
+    assert!(clock.current_time().adjust_reason.manual_time_update);
+
+    sleep(Duration::from_secs(100)).await;
+    assert_eq!((clock.now() - date_time).num_milliseconds(), 100_100);
+
+    clock.offset(chrono::Duration::minutes(-5));
+    assert_eq!(
+        (clock.now() - date_time).num_milliseconds(),
+        100_100 - 300_000
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn setting_a_time_without_date_keeps_the_date() {
+    let start = NaiveDate::from_ymd(2022, 1, 1).and_hms(10, 0, 0);
+    let mut clock = EmulatedClock::new(start);
+
+    let current_time = CurrentTime::from_vector(&[0, 0, 0, 0, 17, 30, 0, 0, 0, 0]).unwrap();
+    clock.set(&current_time);
+    assert_eq!(
+        clock.now(),
+        NaiveDate::from_ymd(2022, 1, 1).and_hms(17, 30, 0)
+    );
+}
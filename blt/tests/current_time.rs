@@ -0,0 +1,118 @@
+use blt::current_time::{
+    AdjustReason, CurrentTime, DstOffset, LocalTimeInformation, ReferenceTimeInformation,
+    TimeSource, ACCURACY_UNKNOWN,
+};
+use chrono::{NaiveDate, NaiveTime, Weekday};
+
+#[test]
+fn current_time_round_trip() {
+    let date_time = NaiveDate::from_ymd(2022, 9, 14).and_hms_milli(17, 5, 42, 500);
+    let current_time = CurrentTime::new(date_time).with_adjust_reason(AdjustReason {
+        manual_time_update: true,
+        change_of_dst: true,
+        ..Default::default()
+    });
+
+    let vector = current_time.to_vector();
+    assert_eq!(vector, vec![0xE6, 0x07, 9, 14, 17, 5, 42, 3, 128, 0x09]);
+    let decoded = CurrentTime::from_vector(&vector).unwrap();
+    assert_eq!(decoded, current_time);
+    assert_eq!(decoded.day_of_week(), Some(Weekday::Wed));
+}
+
+#[test]
+fn fractions256_are_truncated_to_the_resolution() {
+    let date_time = NaiveDate::from_ymd(2022, 9, 14).and_hms_nano(0, 0, 0, 999_999_999);
+    let current_time = CurrentTime::new(date_time);
+    assert_eq!(current_time.fractions256(), 255);
+
+    let decoded = CurrentTime::from_vector(&current_time.to_vector()).unwrap();
+    assert_eq!(decoded.fractions256(), 255);
+    assert_eq!(
+        decoded.date_time().unwrap().timestamp_nanos(),
+        date_time.timestamp_nanos() - 3_906_249
+    );
+}
+
+#[test]
+fn adjust_reason_bits() {
+    for value in 0u8..0x10 {
+        assert_eq!(u8::from(AdjustReason::from(value)), value);
+    }
+    assert!(AdjustReason::from(0x02).external_reference_time_update);
+    assert!(AdjustReason::from(0x04).change_of_time_zone);
+}
+
+#[test]
+fn invalid_current_times_are_rejected() {
+    // Truncated.
+    assert!(CurrentTime::from_vector(&[0xE6, 0x07, 9, 14, 17, 5, 42, 3, 0]).is_err());
+    // February 30th.
+    assert!(CurrentTime::from_vector(&[0xE6, 0x07, 2, 30, 17, 5, 42, 0, 0, 0]).is_err());
+    // 2022-09-14 is a Wednesday, not a Monday.
+    assert!(CurrentTime::from_vector(&[0xE6, 0x07, 9, 14, 17, 5, 42, 1, 0, 0]).is_err());
+    // Unknown day of week.
+    assert!(CurrentTime::from_vector(&[0xE6, 0x07, 9, 14, 17, 5, 42, 0, 0, 0]).is_ok());
+    // Month 13, and 25 o'clock.
+    assert!(CurrentTime::from_vector(&[0xE6, 0x07, 13, 14, 17, 5, 42, 0, 0, 0]).is_err());
+    assert!(CurrentTime::from_vector(&[0, 0, 0, 0, 25, 5, 42, 0, 0, 0]).is_err());
+}
+
+#[test]
+fn unknown_date_fields_are_accepted() {
+    let vector = [0, 0, 9, 0, 17, 5, 42, 0, 128, 0];
+    let current_time = CurrentTime::from_vector(&vector).unwrap();
+    assert_eq!(
+        (current_time.year, current_time.month, current_time.day),
+        (None, Some(9), None)
+    );
+    assert_eq!(current_time.time, NaiveTime::from_hms_milli(17, 5, 42, 500));
+    assert_eq!(current_time.date_time(), None);
+    assert_eq!(current_time.day_of_week(), None);
+    assert_eq!(current_time.to_vector(), vector);
+    assert_eq!(
+        current_time.to_string(),
+        "????-09-?? 17:05:42 + 128/256 s (adjust reason: none)"
+    );
+    // The day of week is only checked against known dates.
+    assert!(CurrentTime::from_vector(&[0, 0, 0, 0, 17, 5, 42, 3, 0, 0]).is_ok());
+    assert!(CurrentTime::from_vector(&[0, 0, 0, 0, 17, 5, 42, 8, 0, 0]).is_err());
+}
+
+#[test]
+fn local_time_information_round_trip() {
+    let local_time_information = LocalTimeInformation::new(Some(-14), DstOffset::DaylightTime);
+
+    let vector = local_time_information.to_vector();
+    assert_eq!(vector, vec![0xF2, 4]);
+    assert_eq!(
+        LocalTimeInformation::from_vector(&vector).unwrap(),
+        local_time_information
+    );
+    assert_eq!(local_time_information.utc_offset_seconds(), Some(-9000));
+    assert_eq!(local_time_information.to_string(), "UTC-03:30, DST +60 min");
+
+    let unknown = LocalTimeInformation::from_vector(&[0x80, 0xFF]).unwrap();
+    assert_eq!(unknown.time_zone, None);
+    assert_eq!(unknown.dst_offset, DstOffset::Unknown);
+    assert_eq!(unknown.utc_offset_seconds(), None);
+}
+
+#[test]
+fn reference_time_information_round_trip() {
+    let reference_time_information = ReferenceTimeInformation {
+        time_source: TimeSource::Gps,
+        accuracy: ACCURACY_UNKNOWN,
+        days_since_update: 3,
+        hours_since_update: 7,
+    };
+
+    let vector = reference_time_information.to_vector();
+    assert_eq!(vector, vec![2, 255, 3, 7]);
+    assert_eq!(
+        ReferenceTimeInformation::from_vector(&vector).unwrap(),
+        reference_time_information
+    );
+    assert!(ReferenceTimeInformation::from_vector(&[2, 255, 3]).is_err());
+    assert_eq!(TimeSource::from(9), TimeSource::Reserved(9));
+}
@@ -0,0 +1,129 @@
+use blt::backend::{BltAdapter, LoopbackAdapter, LoopbackBus};
+use blt::{DeviceDatabase, PairingPolicy};
+use bluer::Address;
+use uuid::Uuid;
+
+const SERVICE: Uuid = Uuid::from_u128(0x0000180d_0000_1000_8000_00805f9b34fb);
+
+const DATABASE: &str = r#"
+    [[device]]
+    address = "C8:2B:96:00:00:01"
+    pairing = "never"
+    passkey = 123456
+
+    [[device]]
+    alias = "InfiniTime"
+    pairing = "always"
+
+    [[device]]
+    name = "Band-??-*"
+    pairing = "if-required"
+
+    [[device]]
+    oui = "C8:2B:96"
+    pairing = "always"
+"#;
+
+fn address(address: &str) -> Address {
+    address.parse().unwrap()
+}
+
+#[test]
+fn first_matching_rule_applies() {
+    let database = DeviceDatabase::parse(DATABASE).unwrap();
+    assert_eq!(database.rules().len(), 4);
+    let policy = |address, name, alias| {
+        database
+            .find_rule(address, name, alias)
+            .map(|rule| rule.pairing)
+    };
+
+    let rule = database
+        .find_rule(address("C8:2B:96:00:00:01"), None, Some("InfiniTime"))
+        .unwrap();
+    assert_eq!(rule.pairing, PairingPolicy::Never);
+    assert_eq!(rule.passkey, Some(123456));
+
+    let other = address("02:00:00:00:00:01");
+    assert_eq!(
+        policy(other, None, Some("InfiniTime")),
+        Some(PairingPolicy::Always)
+    );
+    assert_eq!(
+        policy(other, Some("Band-07-Pro"), Some("Band")),
+        Some(PairingPolicy::IfRequired)
+    );
+    assert_eq!(policy(other, Some("Band-7-Pro"), Some("Band")), None);
+    assert_eq!(
+        policy(address("C8:2B:96:12:34:56"), None, None),
+        Some(PairingPolicy::Always)
+    );
+    assert_eq!(policy(other, None, None), None);
+}
+
+#[test]
+fn invalid_rules_are_rejected() {
+    for (rule, reason) in [
+        ("pairing = \"always\"", "needs an alias"),
+        (
+            "address = \"C8:2B\"\npairing = \"always\"",
+            "invalid address",
+        ),
+        ("oui = \"C8:2B\"\npairing = \"always\"", "invalid oui"),
+        ("oui = \"C8:2B:ZZ\"\npairing = \"always\"", "invalid oui"),
+        (
+            "alias = \"Band\"\npairing = \"always\"\npasskey = 1000000",
+            "six digits",
+        ),
+        (
+            "alias = \"Band\"\npairing = \"sometimes\"",
+            "pairing policy",
+        ),
+    ] {
+        let content = format!(
+            "[[device]]\nalias = \"Other\"\npairing = \"never\"\n\n[[device]]\n{}",
+            rule
+        );
+        let error = DeviceDatabase::parse(&content).unwrap_err().to_string();
+        assert!(error.starts_with("device rule 2:"), "{}", error);
+        assert!(error.contains(reason), "{}", error);
+    }
+}
+
+#[test]
+fn default_database_pairs_infinitime() {
+    let database = DeviceDatabase::default();
+    let rule = database
+        .find_rule(address("02:00:00:00:00:01"), None, Some("InfiniTime"))
+        .unwrap();
+    assert_eq!(rule.pairing, PairingPolicy::Always);
+    assert_eq!(DeviceDatabase::load(None).unwrap(), database);
+}
+
+#[tokio::test(start_paused = true)]
+async fn devices_get_the_policy_of_their_rule() {
+    let bus = LoopbackBus::new();
+    let band = LoopbackAdapter::new(&bus, "band");
+    let _advertisement = band
+        .advertise_gatt_service(SERVICE, "Band-01-Pro")
+        .await
+        .unwrap();
+    let other = LoopbackAdapter::new(&bus, "other");
+    let _other_advertisement = other
+        .advertise_gatt_service(SERVICE, "Other")
+        .await
+        .unwrap();
+    let reader = LoopbackAdapter::new(&bus, "reader");
+    let database = DeviceDatabase::parse(DATABASE).unwrap();
+
+    let device = reader.device(band.address().await.unwrap()).unwrap();
+    assert_eq!(
+        database.pairing_policy(device.as_ref()).await,
+        PairingPolicy::IfRequired
+    );
+    let device = reader.device(other.address().await.unwrap()).unwrap();
+    assert_eq!(
+        database.pairing_policy(device.as_ref()).await,
+        PairingPolicy::Never
+    );
+}
@@ -0,0 +1,124 @@
+use blt::heart_rate::HeartRateEmulator;
+use blt::heart_rate_measurement::{HeartRateMeasurement, SensorContact};
+use blt::heart_rate_scenario::HeartRateScenario;
+use std::time::Duration;
+
+const INTERVAL: Duration = Duration::from_secs(7);
+
+#[test]
+fn rr_intervals_match_heart_rate() {
+    let mut emulator = HeartRateEmulator::new(80);
+
+    for _ in 0..200 {
+        let measurement = emulator.advance(INTERVAL);
+        if measurement.sensor_contact != SensorContact::Detected {
+            assert!(measurement.rr_intervals.is_empty());
+            continue;
+        }
+
+        let expected = 60.0 * 1024.0 / measurement.heart_rate as f64;
+        for rr_interval in &measurement.rr_intervals {
+            let deviation = (*rr_interval as f64 - expected).abs() / expected;
+            assert!(deviation <= 0.031, "{} vs {}", rr_interval, expected);
+        }
+
+        // Beats emitted in one interval add up to the interval, plus at most the time left over
+        // from the previous one (less than one beat).
+        let total: f64 = measurement.rr_intervals.iter().map(|rr| *rr as f64).sum();
+        assert!(total <= (INTERVAL.as_secs_f64() + 1.03) * 1024.0);
+        assert!(total >= (INTERVAL.as_secs_f64() - 1.03) * 1024.0);
+    }
+}
+
+#[test]
+fn energy_expended_accumulates() {
+    let mut emulator = HeartRateEmulator::new(80);
+    let mut previous = 0;
+
+    for _ in 0..50 {
+        let energy_expended = emulator.advance(INTERVAL).energy_expended.unwrap();
+        assert!(energy_expended >= previous);
+        previous = energy_expended;
+    }
+
+    // At least 4.2 kJ per minute.
+    assert!(previous as f64 >= 4.2 * 50.0 * 7.0 / 60.0 - 1.0);
+}
+
+#[test]
+fn sensor_contact_toggles() {
+    let mut emulator = HeartRateEmulator::new(80);
+    let contacts: Vec<SensorContact> = (0..1000)
+        .map(|_| emulator.advance(INTERVAL).sensor_contact)
+        .collect();
+
+    assert!(contacts.contains(&SensorContact::Detected));
+    assert!(contacts.contains(&SensorContact::NotDetected));
+}
+
+#[test]
+fn measurements_are_spec_encoded() {
+    let mut emulator = HeartRateEmulator::new(80);
+
+    for _ in 0..100 {
+        let measurement = emulator.advance(INTERVAL);
+        let decoded = HeartRateMeasurement::from_vector(&measurement.to_vector()).unwrap();
+        assert_eq!(decoded.heart_rate, measurement.heart_rate);
+        assert_eq!(decoded.sensor_contact, measurement.sensor_contact);
+        assert_eq!(decoded.energy_expended, measurement.energy_expended);
+        assert!(measurement.rr_intervals.ends_with(&decoded.rr_intervals));
+    }
+}
+
+#[test]
+fn same_seed_reproduces_the_measurements() {
+    let measurements = |seed| {
+        let mut emulator = HeartRateEmulator::new(80).with_seed(seed);
+        (0..200)
+            .map(|_| emulator.advance(INTERVAL))
+            .collect::<Vec<HeartRateMeasurement>>()
+    };
+
+    assert_eq!(measurements(42), measurements(42));
+    assert_ne!(measurements(42), measurements(43));
+}
+
+#[test]
+fn notified_rr_intervals_track_the_elapsed_time() {
+    for heart_rate in [80, 180, 250] {
+        let scenario = HeartRateScenario::parse("steady", &format!("0 {}\n", heart_rate)).unwrap();
+        let mut emulator = HeartRateEmulator::with_scenario(scenario).with_seed(7);
+
+        let mut notified = 0.0;
+        for round in 1..=100 {
+            for measurement in emulator.advance(INTERVAL).split_rr_intervals() {
+                let vector = measurement.to_vector();
+                let decoded = HeartRateMeasurement::from_vector(&vector).unwrap();
+                notified += decoded
+                    .rr_intervals
+                    .iter()
+                    .map(|rr| *rr as f64)
+                    .sum::<f64>()
+                    / 1024.0;
+            }
+
+            // Every beat is notified: only the time since the last one is missing, give or take
+            // the rounding to 1/1024 seconds.
+            let elapsed = (INTERVAL * round).as_secs_f64();
+            assert!(
+                notified < elapsed + 0.1,
+                "{} bpm: {} vs {}",
+                heart_rate,
+                notified,
+                elapsed
+            );
+            assert!(
+                notified > elapsed - 1.0,
+                "{} bpm: {} vs {}",
+                heart_rate,
+                notified,
+                elapsed
+            );
+        }
+    }
+}
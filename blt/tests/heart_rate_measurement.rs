@@ -0,0 +1,145 @@
+use blt::heart_rate_measurement::{
+    BodySensorLocation, HeartRateMeasurement, SensorContact, ValueFormat, MAX_PAYLOAD_LENGTH,
+};
+
+#[test]
+fn round_trip_every_flag_combination() {
+    for flags in 0u8..0x20 {
+        let value_format = if flags & 0x01 != 0 {
+            ValueFormat::Uint16
+        } else {
+            ValueFormat::Uint8
+        };
+        let sensor_contact = match (flags >> 1) & 0x03 {
+            2 => SensorContact::NotDetected,
+            3 => SensorContact::Detected,
+            _ => SensorContact::NotSupported,
+        };
+        let measurement = HeartRateMeasurement {
+            heart_rate: 72,
+            value_format,
+            sensor_contact,
+            energy_expended: (flags & 0x08 != 0).then_some(1234),
+            rr_intervals: if flags & 0x10 != 0 {
+                vec![820, 845, 790]
+            } else {
+                Vec::new()
+            },
+        };
+
+        let vector = measurement.to_vector();
+        // Sensor contact "not supported" may be sent as 0 or 1; we always send 0.
+        let expected_flags = if flags & 0x04 != 0 {
+            flags
+        } else {
+            flags & !0x06
+        };
+        assert_eq!(vector[0], expected_flags, "flags {:#04x}", flags);
+        assert_eq!(
+            HeartRateMeasurement::from_vector(&vector).unwrap(),
+            measurement,
+            "flags {:#04x}",
+            flags
+        );
+    }
+}
+
+#[test]
+fn decode_uint8_with_all_fields() {
+    let measurement =
+        HeartRateMeasurement::from_vector(&[0x1E, 0x48, 0xD2, 0x04, 0x34, 0x03, 0x4D, 0x03])
+            .unwrap();
+
+    assert_eq!(measurement.heart_rate, 72);
+    assert_eq!(measurement.value_format, ValueFormat::Uint8);
+    assert_eq!(measurement.sensor_contact, SensorContact::Detected);
+    assert_eq!(measurement.energy_expended, Some(1234));
+    assert_eq!(measurement.rr_intervals, vec![820, 845]);
+    assert_eq!(measurement.rr_intervals_ms(), vec![801, 825]);
+}
+
+#[test]
+fn decode_uint16_value() {
+    let measurement = HeartRateMeasurement::from_vector(&[0x01, 0x2C, 0x01]).unwrap();
+
+    assert_eq!(measurement.heart_rate, 300);
+    assert_eq!(measurement.value_format, ValueFormat::Uint16);
+    assert_eq!(measurement.sensor_contact, SensorContact::NotSupported);
+}
+
+#[test]
+fn decode_sensor_contact_not_supported_bit() {
+    let measurement = HeartRateMeasurement::from_vector(&[0x02, 0x48]).unwrap();
+
+    assert_eq!(measurement.sensor_contact, SensorContact::NotSupported);
+}
+
+#[test]
+fn large_values_are_encoded_as_uint16() {
+    let measurement = HeartRateMeasurement {
+        value_format: ValueFormat::Uint8,
+        ..HeartRateMeasurement::new(300)
+    };
+
+    assert_eq!(measurement.to_vector(), vec![0x01, 0x2C, 0x01]);
+}
+
+#[test]
+fn oldest_rr_intervals_are_dropped_when_payload_is_full() {
+    let measurement = HeartRateMeasurement {
+        energy_expended: Some(10),
+        rr_intervals: (1..=10).collect(),
+        ..HeartRateMeasurement::new(72)
+    };
+
+    let vector = measurement.to_vector();
+    assert!(vector.len() <= MAX_PAYLOAD_LENGTH);
+    let decoded = HeartRateMeasurement::from_vector(&vector).unwrap();
+    assert_eq!(decoded.rr_intervals, (3..=10).collect::<Vec<u16>>());
+}
+
+#[test]
+fn rr_intervals_are_split_across_notifications() {
+    let measurement = HeartRateMeasurement {
+        energy_expended: Some(10),
+        rr_intervals: (1..=20).collect(),
+        ..HeartRateMeasurement::new(72)
+    };
+    assert_eq!(measurement.rr_interval_room(), 8);
+
+    let measurements = measurement.clone().split_rr_intervals();
+    assert_eq!(measurements.len(), 3);
+    let mut rr_intervals = Vec::new();
+    for measurement in measurements {
+        let vector = measurement.to_vector();
+        assert!(vector.len() <= MAX_PAYLOAD_LENGTH);
+        let decoded = HeartRateMeasurement::from_vector(&vector).unwrap();
+        assert_eq!(decoded.energy_expended, Some(10));
+        rr_intervals.extend(decoded.rr_intervals);
+    }
+    assert_eq!(rr_intervals, measurement.rr_intervals);
+
+    let without_rr_intervals = HeartRateMeasurement::new(72);
+    assert_eq!(
+        without_rr_intervals.clone().split_rr_intervals(),
+        vec![without_rr_intervals]
+    );
+}
+
+#[test]
+fn truncated_measurements_are_rejected() {
+    assert!(HeartRateMeasurement::from_vector(&[]).is_err());
+    assert!(HeartRateMeasurement::from_vector(&[0x00]).is_err());
+    assert!(HeartRateMeasurement::from_vector(&[0x01, 0x48]).is_err());
+    assert!(HeartRateMeasurement::from_vector(&[0x08, 0x48, 0x01]).is_err());
+    assert!(HeartRateMeasurement::from_vector(&[0x10, 0x48, 0x01]).is_err());
+}
+
+#[test]
+fn body_sensor_location_round_trip() {
+    for value in 0..=u8::MAX {
+        assert_eq!(u8::from(BodySensorLocation::from(value)), value);
+    }
+    assert_eq!(BodySensorLocation::from(1), BodySensorLocation::Chest);
+    assert_eq!(BodySensorLocation::from(7), BodySensorLocation::Reserved(7));
+}
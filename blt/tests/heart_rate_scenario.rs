@@ -0,0 +1,107 @@
+use blt::heart_rate::HeartRateEmulator;
+use blt::heart_rate_measurement::SensorContact;
+use blt::heart_rate_scenario::{HeartRateScenario, SCENARIO_NAMES};
+use std::path::Path;
+use std::time::Duration;
+
+const INTERVAL: Duration = Duration::from_secs(7);
+
+#[test]
+fn every_named_scenario_is_available() {
+    for name in SCENARIO_NAMES {
+        let scenario = HeartRateScenario::from_name(name).unwrap();
+        assert_eq!(scenario.name, name);
+    }
+    assert!(HeartRateScenario::from_name("unknown").is_none());
+}
+
+#[test]
+fn heart_rate_is_interpolated_between_keyframes() {
+    let scenario = HeartRateScenario::parse("ramp", "0 60\n100 160 no_contact\n").unwrap();
+
+    assert_eq!(scenario.at(Duration::ZERO), (60, SensorContact::Detected));
+    assert_eq!(
+        scenario.at(Duration::from_secs(25)),
+        (85, SensorContact::Detected)
+    );
+    assert_eq!(
+        scenario.at(Duration::from_secs(100)),
+        (160, SensorContact::NotDetected)
+    );
+    // The last keyframe holds.
+    assert_eq!(
+        scenario.at(Duration::from_secs(1000)),
+        (160, SensorContact::NotDetected)
+    );
+}
+
+#[test]
+fn scenario_files_are_parsed() {
+    let path =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../resources/scenarios/interval_training.txt");
+    let scenario = HeartRateScenario::load(path.to_str().unwrap()).unwrap();
+
+    assert_eq!(scenario.name, "interval_training");
+    assert_eq!(scenario.keyframes.len(), 7);
+    assert_eq!(scenario.rr_interval_jitter, 0.05);
+    assert_eq!(
+        scenario.at(Duration::from_secs(195)),
+        (140, SensorContact::NotDetected)
+    );
+}
+
+#[test]
+fn invalid_scenarios_are_rejected() {
+    assert!(HeartRateScenario::parse("empty", "# nothing\n").is_err());
+    assert!(HeartRateScenario::parse("unsorted", "10 60\n0 70\n").is_err());
+    assert!(HeartRateScenario::parse("zero", "0 0\n").is_err());
+    assert!(HeartRateScenario::parse("contact", "0 60 maybe\n").is_err());
+    assert!(HeartRateScenario::parse("negative", "-1 60\n").is_err());
+    assert!(HeartRateScenario::parse("overflow", "1e30 60\n").is_err());
+    assert!(HeartRateScenario::parse("jitter", "rr_jitter 2\n0 60\n").is_err());
+    assert!(HeartRateScenario::load("no_such_scenario").is_err());
+}
+
+#[test]
+fn emulator_follows_the_scenario() {
+    let scenario = HeartRateScenario::from_name("signal_loss").unwrap();
+    let mut emulator = HeartRateEmulator::with_scenario(scenario.clone()).with_seed(7);
+
+    for step in 1..100 {
+        let measurement = emulator.advance(INTERVAL);
+        let (heart_rate, sensor_contact) = scenario.at(INTERVAL * step);
+        assert_eq!(measurement.heart_rate, heart_rate);
+        assert_eq!(measurement.sensor_contact, sensor_contact);
+        if sensor_contact == SensorContact::NotDetected {
+            assert!(measurement.rr_intervals.is_empty());
+        } else {
+            assert!(!measurement.rr_intervals.is_empty());
+        }
+    }
+}
+
+#[test]
+fn bradycardia_goes_below_the_random_walk_range() {
+    let mut emulator =
+        HeartRateEmulator::with_scenario(HeartRateScenario::from_name("bradycardia").unwrap());
+
+    for _ in 0..50 {
+        assert!(emulator.advance(INTERVAL).heart_rate < 50);
+    }
+}
+
+#[test]
+fn same_seed_reproduces_the_rr_intervals() {
+    let rr_intervals = |seed| {
+        let mut emulator = HeartRateEmulator::with_scenario(
+            HeartRateScenario::from_name("arrhythmic_rr").unwrap(),
+        )
+        .with_seed(seed);
+        (0..50)
+            .flat_map(|_| emulator.advance(INTERVAL).rr_intervals)
+            .collect::<Vec<u16>>()
+    };
+
+    assert_eq!(rr_intervals(1), rr_intervals(1));
+    assert_ne!(rr_intervals(1), rr_intervals(2));
+}
@@ -0,0 +1,105 @@
+use blt::backend::{AdapterPresence, BltAdapter, LoopbackAdapter, LoopbackBus};
+use blt::cts::{self, CTS};
+use blt::ping_pong::PingPong;
+use blt::{AdapterManager, ApplicationClient, ApplicationServer};
+use futures::StreamExt;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+#[tokio::test(start_paused = true)]
+async fn removed_adapters_report_presence_and_fail() {
+    let bus = LoopbackBus::new();
+    let adapter = LoopbackAdapter::new(&bus, "reader");
+    let mut presence = adapter.presence_events();
+
+    adapter.set_present(false);
+    assert_eq!(presence.next().await, Some(AdapterPresence::Lost));
+    let error = adapter.discover_devices().await.err().unwrap();
+    assert_eq!(error.to_string(), "Bluetooth adapter reader not available.");
+    assert!(adapter
+        .advertise_gatt_service(cts::service_uuid(), "CTS")
+        .await
+        .is_err());
+
+    // Removing it again changes nothing.
+    adapter.set_present(false);
+    adapter.set_present(true);
+    assert_eq!(presence.next().await, Some(AdapterPresence::Restored));
+    assert!(adapter.discover_devices().await.is_ok());
+}
+
+#[tokio::test(start_paused = true)]
+async fn servers_advertise_again_once_the_adapter_is_back() {
+    let bus = LoopbackBus::new();
+    let server_adapter = LoopbackAdapter::new(&bus, "server");
+    let server_address = server_adapter.address().await.unwrap();
+    let server = tokio::spawn(
+        ApplicationServer::new(
+            Box::new(CTS::default()),
+            AdapterManager::with_adapter(Box::new(server_adapter)),
+        )
+        .run(),
+    );
+    let scanner = AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "scanner")));
+    let advertised = |devices: Vec<blt::DeviceInfo>| {
+        devices.iter().any(|device| {
+            device.address == server_address && device.uuids == vec![cts::service_uuid()]
+        })
+    };
+
+    sleep(Duration::from_secs(1)).await;
+    assert!(advertised(
+        scanner.scan(Duration::from_secs(1)).await.unwrap()
+    ));
+
+    bus.set_present(server_address, false);
+    sleep(Duration::from_secs(1)).await;
+    assert!(!advertised(
+        scanner.scan(Duration::from_secs(1)).await.unwrap()
+    ));
+
+    bus.set_present(server_address, true);
+    sleep(Duration::from_secs(1)).await;
+    assert!(advertised(
+        scanner.scan(Duration::from_secs(1)).await.unwrap()
+    ));
+    assert!(!server.is_finished());
+    server.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn clients_resume_discovery_once_the_adapter_is_back() {
+    let bus = LoopbackBus::new();
+    let client_adapter = LoopbackAdapter::new(&bus, "client");
+    let client_address = client_adapter.address().await.unwrap();
+    let client = tokio::spawn(
+        ApplicationClient::new(
+            Box::new(PingPong),
+            AdapterManager::with_adapter(Box::new(client_adapter)),
+        )
+        .run(),
+    );
+
+    sleep(Duration::from_secs(1)).await;
+    bus.set_present(client_address, false);
+    sleep(Duration::from_secs(1)).await;
+    bus.set_present(client_address, true);
+
+    let server = tokio::spawn(
+        ApplicationServer::new(
+            Box::new(PingPong),
+            AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "server"))),
+        )
+        .run(),
+    );
+    timeout(Duration::from_secs(60), client)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    timeout(Duration::from_secs(60), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
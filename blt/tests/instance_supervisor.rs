@@ -0,0 +1,189 @@
+use blt::adapter_manager::{AdapterCapability, AdapterInfo};
+use blt::application_factory::ApplicationMode;
+use blt::{CaptureSender, Config, InstanceStatus, InstanceSupervisor};
+use futures::future;
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+const INSTANCES: &str = r#"
+    seed = 5
+
+    [[instances]]
+    app = "heart_rate"
+    mode = "client"
+    [instances.adapter]
+    name = "hci0"
+
+    [[instances]]
+    name = "clock"
+    app = "cts"
+    mode = "server"
+    seed = 9
+    [instances.adapter]
+    address = "00:1A:7D:DA:71:13"
+    pairable_timeout = 0
+"#;
+
+fn supervisor() -> InstanceSupervisor {
+    let config = Config::parse(INSTANCES, Vec::new()).unwrap();
+    InstanceSupervisor::new(config.instances().unwrap())
+}
+
+#[test]
+fn instances_take_their_keys_or_the_defaults() {
+    let supervisor = supervisor();
+    let instances = supervisor.instances();
+    assert_eq!(instances.len(), 2);
+    assert!(supervisor.has_clients());
+
+    assert_eq!(instances[0].name, "heart_rate_client");
+    assert_eq!(instances[0].config.seed, Some(5));
+    assert_eq!(instances[0].config.adapter.name.as_deref(), Some("hci0"));
+    assert_eq!(instances[0].config.adapter.pairable_timeout, 15);
+    assert!(instances[0].config.instances.is_empty());
+
+    assert_eq!(instances[1].name, "clock");
+    assert_eq!(instances[1].config.app.as_deref(), Some("cts"));
+    assert_eq!(instances[1].config.mode, Some(ApplicationMode::Server));
+    assert_eq!(instances[1].config.seed, Some(9));
+    assert_eq!(instances[1].config.adapter.pairable_timeout, 0);
+}
+
+#[test]
+fn invalid_instances_are_rejected() {
+    let error = |content: &str| Config::parse(content, Vec::new()).unwrap_err().to_string();
+
+    assert!(error("[[instances]]\napp = \"cts\"\n").contains("needs an application and a mode"));
+    assert!(error("[[instances]]\napp = \"mock\"\nmode = \"server\"\n")
+        .contains("Instance 'mock_server': Application 'mock' only runs as a client"));
+    assert!(error(
+        "mode = \"client\"\n[[instances]]\napp = \"cts\"\n[[instances]]\napp = \"adder\"\n"
+    )
+    .contains("needs an adapter name or address"));
+    assert!(error(
+        "mode = \"client\"\n[adapter]\nname = \"hci0\"\n[[instances]]\napp = \"cts\"\n\
+         [[instances]]\napp = \"adder\"\n"
+    )
+    .contains("use the same adapter"));
+    assert!(error(
+        "mode = \"client\"\n[[instances]]\napp = \"cts\"\n[instances.adapter]\nname = \"hci0\"\n\
+         [[instances]]\napp = \"cts\"\n[instances.adapter]\nname = \"hci1\"\n"
+    )
+    .contains("'cts_client' is repeated"));
+}
+
+fn adapter(name: &str, address: &str) -> AdapterInfo {
+    AdapterInfo {
+        name: name.to_string(),
+        address: address.parse().unwrap(),
+        capabilities: BTreeSet::from([AdapterCapability::Advertising]),
+    }
+}
+
+#[test]
+fn instances_resolving_to_the_same_adapter_are_rejected() {
+    let supervisor = supervisor();
+    assert!(supervisor
+        .check_adapters(&[
+            adapter("hci0", "00:1A:7D:DA:71:01"),
+            adapter("hci1", "00:1A:7D:DA:71:13"),
+        ])
+        .is_ok());
+    // Missing adapters are left to the instances.
+    assert!(supervisor
+        .check_adapters(&[adapter("hci0", "00:1A:7D:DA:71:01")])
+        .is_ok());
+
+    // hci0 is selected by the name of one instance and the address of the other.
+    let error = supervisor
+        .check_adapters(&[adapter("hci0", "00:1A:7D:DA:71:13")])
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Instances 'heart_rate_client' and 'clock' use the same adapter hci0."
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn failing_instances_dont_stop_the_others() {
+    let supervisor = supervisor();
+    let status = supervisor.status();
+
+    let error = supervisor
+        .run_with(
+            CaptureSender::default(),
+            |config, _, _| async move {
+                if config.app.as_deref() == Some("cts") {
+                    Err(anyhow::Error::msg("No Bluetooth adapter present."))
+                } else {
+                    sleep(Duration::from_secs(10)).await;
+                    Ok(())
+                }
+            },
+            future::pending(),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "1 of 2 instances failed: 'clock' (No Bluetooth adapter present.)."
+    );
+    assert_eq!(
+        *status.borrow(),
+        [
+            InstanceStatus::Stopped,
+            InstanceStatus::Failed("No Bluetooth adapter present.".to_string())
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_drops_instances_that_dont_stop() {
+    let supervisor = supervisor().with_shutdown_timeout(Duration::from_secs(2));
+    let status = supervisor.status();
+
+    supervisor
+        .run_with(
+            CaptureSender::default(),
+            |config, _, _| async move {
+                if config.mode == Some(ApplicationMode::Server) {
+                    future::pending::<()>().await;
+                }
+                sleep(Duration::from_secs(1)).await;
+                Ok(())
+            },
+            sleep(Duration::from_millis(500)),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        *status.borrow(),
+        [InstanceStatus::Stopped, InstanceStatus::Stopped]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_is_passed_to_the_instances() {
+    let supervisor = supervisor();
+    let status = supervisor.status();
+    let start = Instant::now();
+
+    supervisor
+        .run_with(
+            CaptureSender::default(),
+            |_, _, shutdown| async move {
+                shutdown.requested().await;
+                Ok(())
+            },
+            sleep(Duration::from_millis(500)),
+        )
+        .await
+        .unwrap();
+    // The instances stop right away, without waiting for the shutdown timeout.
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(
+        *status.borrow(),
+        [InstanceStatus::Stopped, InstanceStatus::Stopped]
+    );
+}
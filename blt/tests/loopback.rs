@@ -0,0 +1,268 @@
+use blt::adder::Adder;
+use blt::backend::local::ReqError;
+use blt::backend::{AdapterEvent, BltAdapter, BltCharacteristic, LoopbackAdapter, LoopbackBus};
+use blt::cts::CTS;
+use blt::current_time::CurrentTime;
+use blt::heart_rate::{HeartRate, CONTROL_POINT_NOT_SUPPORTED};
+use blt::heart_rate_measurement::{BodySensorLocation, HeartRateMeasurement};
+use blt::ping_pong::PingPong;
+use blt::{AdapterManager, ApplicationClient, ApplicationServer, BltApplication};
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use uuid::Uuid;
+
+const CURRENT_TIME_CHARACTERISTIC: Uuid = Uuid::from_u128(0x00002a2b_0000_1000_8000_00805f9b34fb);
+const HEART_RATE_MEASUREMENT_CHARACTERISTIC: Uuid =
+    Uuid::from_u128(0x00002a37_0000_1000_8000_00805f9b34fb);
+const BODY_SENSOR_LOCATION_CHARACTERISTIC: Uuid =
+    Uuid::from_u128(0x00002a38_0000_1000_8000_00805f9b34fb);
+const HEART_RATE_CONTROL_POINT_CHARACTERISTIC: Uuid =
+    Uuid::from_u128(0x00002a39_0000_1000_8000_00805f9b34fb);
+
+fn spawn_server(
+    bus: &Arc<LoopbackBus>,
+    blt_application: Box<dyn BltApplication>,
+) -> JoinHandle<anyhow::Result<()>> {
+    let adapter_manager =
+        AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(bus, "server")));
+    tokio::spawn(ApplicationServer::new(blt_application, adapter_manager).run())
+}
+
+fn spawn_client(
+    bus: &Arc<LoopbackBus>,
+    blt_application: Box<dyn BltApplication>,
+) -> JoinHandle<anyhow::Result<()>> {
+    let adapter_manager =
+        AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(bus, "client")));
+    tokio::spawn(ApplicationClient::new(blt_application, adapter_manager).run())
+}
+
+/// Connects to the first advertising device and returns the characteristic with the given UUID.
+async fn remote_characteristic(
+    adapter: &LoopbackAdapter,
+    uuid: Uuid,
+) -> anyhow::Result<Box<dyn BltCharacteristic>> {
+    let mut events = adapter.discover_devices().await?;
+    let address = loop {
+        if let Some(AdapterEvent::DeviceAdded(address)) = events.next().await {
+            break address;
+        }
+    };
+    let device = adapter.device(address)?;
+    device.connect().await?;
+    for service in device.services().await? {
+        for characteristic in service.characteristics().await? {
+            if characteristic.uuid().await? == uuid {
+                return Ok(characteristic);
+            }
+        }
+    }
+    Err(anyhow::Error::msg("Characteristic not found."))
+}
+
+#[tokio::test(start_paused = true)]
+async fn ping_pong_client_against_server() {
+    let bus = LoopbackBus::new();
+    let server = spawn_server(&bus, Box::new(PingPong));
+    let client = spawn_client(&bus, Box::new(PingPong));
+
+    timeout(Duration::from_secs(60), client)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    // The client sends "exit", which stops the server.
+    timeout(Duration::from_secs(60), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn adder_client_against_server() {
+    let bus = LoopbackBus::new();
+    let server = spawn_server(&bus, Box::new(Adder::default()));
+    let client = spawn_client(&bus, Box::new(Adder::default()));
+
+    timeout(Duration::from_secs(60), client)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    timeout(Duration::from_secs(60), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn cts_client_against_server() {
+    let bus = LoopbackBus::new();
+    let server = spawn_server(&bus, Box::new(CTS::default()));
+    let client = spawn_client(&bus, Box::new(CTS::default()));
+
+    timeout(Duration::from_secs(60), client)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert!(!server.is_finished());
+    server.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn cts_client_synchronizes_drifted_server() {
+    let bus = LoopbackBus::new();
+    let cts = CTS::default().with_offset(chrono::Duration::hours(2));
+    let server = spawn_server(&bus, Box::new(cts));
+    let client = spawn_client(&bus, Box::new(CTS::default()));
+
+    timeout(Duration::from_secs(60), client)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let adapter = LoopbackAdapter::new(&bus, "checker");
+    let current_time = remote_characteristic(&adapter, CURRENT_TIME_CHARACTERISTIC)
+        .await
+        .unwrap();
+    let current_time = CurrentTime::from_vector(&current_time.read().await.unwrap()).unwrap();
+    let diff = current_time.date_time().unwrap() - chrono::Utc::now().naive_utc();
+    assert!(diff.num_seconds().abs() < 60, "{}", diff);
+
+    server.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn cts_server_notifies_when_set() {
+    let bus = LoopbackBus::new();
+    let server = spawn_server(&bus, Box::new(CTS::default()));
+    let client = LoopbackAdapter::new(&bus, "client");
+
+    let current_time = remote_characteristic(&client, CURRENT_TIME_CHARACTERISTIC)
+        .await
+        .unwrap();
+    let mut notify_io = current_time.notify_io().await.unwrap();
+    sleep(Duration::from_secs(1)).await;
+
+    let date_time = chrono::NaiveDate::from_ymd(2001, 2, 3).and_hms(4, 5, 6);
+    current_time
+        .write(&CurrentTime::new(date_time).to_vector())
+        .await
+        .unwrap();
+
+    let mut buffer = vec![0; 512];
+    let n = timeout(Duration::from_secs(10), notify_io.read(&mut buffer))
+        .await
+        .unwrap()
+        .unwrap();
+    let notified = CurrentTime::from_vector(&buffer[..n]).unwrap();
+    assert!(
+        (notified.date_time().unwrap() - date_time)
+            .num_seconds()
+            .abs()
+            <= 1
+    );
+
+    // Invalid values are rejected.
+    assert!(current_time.write(&[0xFF; 3]).await.is_err());
+
+    server.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn heart_rate_client_against_server() {
+    let bus = LoopbackBus::new();
+    let server = spawn_server(&bus, Box::new(HeartRate::default()));
+    let client = spawn_client(&bus, Box::new(HeartRate::default()));
+
+    sleep(Duration::from_secs(60)).await;
+    assert!(!client.is_finished());
+
+    // Stopping the server closes the notification stream, which ends the client.
+    server.abort();
+    timeout(Duration::from_secs(60), client)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn heart_rate_body_sensor_location_and_control_point() {
+    let bus = LoopbackBus::new();
+    let server = spawn_server(&bus, Box::new(HeartRate::default()));
+    let client = LoopbackAdapter::new(&bus, "client");
+
+    let body_sensor_location = remote_characteristic(&client, BODY_SENSOR_LOCATION_CHARACTERISTIC)
+        .await
+        .unwrap();
+    assert_eq!(
+        BodySensorLocation::from(body_sensor_location.read().await.unwrap()[0]),
+        BodySensorLocation::Wrist
+    );
+
+    let control_point = remote_characteristic(&client, HEART_RATE_CONTROL_POINT_CHARACTERISTIC)
+        .await
+        .unwrap();
+    control_point.write(&[0x01]).await.unwrap();
+    let error = control_point.write(&[0x02]).await.unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<ReqError>(),
+        Some(&CONTROL_POINT_NOT_SUPPORTED)
+    );
+
+    server.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn heart_rate_servers_emulate_their_own_sensor() {
+    let buses = [LoopbackBus::new(), LoopbackBus::new()];
+    let servers: Vec<_> = buses
+        .iter()
+        .map(|bus| spawn_server(bus, Box::new(HeartRate::default())))
+        .collect();
+    sleep(Duration::from_secs(120)).await;
+
+    let mut measurements = Vec::new();
+    for bus in &buses {
+        let client = LoopbackAdapter::new(bus, "client");
+        measurements.push(
+            remote_characteristic(&client, HEART_RATE_MEASUREMENT_CHARACTERISTIC)
+                .await
+                .unwrap(),
+        );
+        if measurements.len() == 1 {
+            remote_characteristic(&client, HEART_RATE_CONTROL_POINT_CHARACTERISTIC)
+                .await
+                .unwrap()
+                .write(&[0x01])
+                .await
+                .unwrap();
+        }
+    }
+
+    // Only the energy expended of the first server is reset.
+    let mut energy_expended = Vec::new();
+    for measurement in &measurements {
+        let value = measurement.read().await.unwrap();
+        energy_expended.push(
+            HeartRateMeasurement::from_vector(&value)
+                .unwrap()
+                .energy_expended,
+        );
+    }
+    assert_eq!(energy_expended[0], Some(0));
+    assert!(energy_expended[1] > Some(0));
+
+    for server in servers {
+        server.abort();
+    }
+}
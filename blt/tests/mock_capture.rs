@@ -0,0 +1,76 @@
+use blt::heart_rate_measurement::HeartRateMeasurement;
+use blt::heart_rate_scenario::HeartRateScenario;
+use blt::mock_capture::{MockCapture, MockPayload};
+use blt::CaptureSender;
+use std::time::Duration;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+fn from_hex(value: &str) -> Vec<u8> {
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&value[index..index + 2], 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn uuid_captures_are_random_uuids() {
+    let mut generator = MockCapture::default().generator();
+    let first = Uuid::parse_str(&generator.next_capture().value).unwrap();
+    let second = Uuid::parse_str(&generator.next_capture().value).unwrap();
+    assert_eq!(first.get_version_num(), 4);
+    assert_ne!(first, second);
+}
+
+#[test]
+fn same_seed_generates_same_values() {
+    for payload in [MockPayload::Uuid, MockPayload::HeartRate] {
+        let mock_capture = MockCapture::default().with_payload(payload).with_seed(7);
+        let (mut first, mut second) = (mock_capture.generator(), mock_capture.generator());
+        for _ in 0..10 {
+            assert_eq!(first.next_capture().value, second.next_capture().value);
+        }
+    }
+}
+
+#[test]
+fn heart_rate_captures_play_the_scenario() {
+    let mut generator = MockCapture::default()
+        .with_payload(MockPayload::HeartRate)
+        .with_scenario(HeartRateScenario::from_name("bradycardia").unwrap())
+        .generator();
+    for _ in 0..10 {
+        let value = from_hex(&generator.next_capture().value);
+        let measurement = HeartRateMeasurement::from_vector(&value).unwrap();
+        assert!((44..=48).contains(&measurement.heart_rate));
+    }
+}
+
+#[test]
+fn payloads_are_parsed() {
+    assert_eq!("uuid".parse::<MockPayload>().unwrap(), MockPayload::Uuid);
+    assert_eq!(
+        "Heart_Rate".parse::<MockPayload>().unwrap(),
+        MockPayload::HeartRate
+    );
+    assert!("ecg".parse::<MockPayload>().is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn captures_are_sent_every_interval() {
+    let (capture_sender, mut captures) = CaptureSender::channel();
+    let mock_capture = MockCapture::default().with_interval(Duration::from_secs(5));
+    tokio::spawn(async move { mock_capture.start(capture_sender).await });
+
+    let start = Instant::now();
+    for index in 0..3 {
+        assert!(captures.recv().await.is_some());
+        assert_eq!(start.elapsed(), Duration::from_secs(5 * index));
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn generation_stops_when_captures_are_not_accepted() {
+    let mock_capture = MockCapture::default();
+    mock_capture.start(CaptureSender::default()).await.unwrap();
+}
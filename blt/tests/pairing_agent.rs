@@ -0,0 +1,175 @@
+use blt::backend::{BltAdapter, LoopbackAdapter, LoopbackBus, LoopbackPairing};
+use blt::config::PairingSettings;
+use blt::pairing_agent::{ConfiguredPairing, PairingRequestKind};
+use blt::{AdapterManager, DeviceDatabase, PairingAgent, PairingAnswer, PairingRequest};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+const SERVICE: Uuid = Uuid::from_u128(0x0000180d_0000_1000_8000_00805f9b34fb);
+
+const DATABASE: &str = r#"
+    [[device]]
+    alias = "Band"
+    pairing = "always"
+    passkey = 123456
+"#;
+
+fn configured_agent(settings: PairingSettings) -> PairingAgent {
+    PairingAgent::new(ConfiguredPairing::new(
+        DeviceDatabase::parse(DATABASE).unwrap(),
+        settings,
+    ))
+}
+
+fn request(alias: &str, kind: PairingRequestKind) -> PairingRequest {
+    PairingRequest {
+        address: "02:00:00:00:00:01".parse().unwrap(),
+        name: Some(alias.to_string()),
+        alias: Some(alias.to_string()),
+        kind,
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn devices_are_paired_with_the_passkey_of_their_rule() {
+    let bus = LoopbackBus::new();
+    let band = LoopbackAdapter::new(&bus, "band");
+    let _advertisement = band.advertise_gatt_service(SERVICE, "Band").await.unwrap();
+    band.set_pairing(LoopbackPairing::Passkey(123456));
+    let address = band.address().await.unwrap();
+    let mut adapter_manager =
+        AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader")));
+
+    let error = adapter_manager
+        .pair_device(address, Duration::from_secs(1))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("requires a pairing agent"));
+
+    adapter_manager
+        .register_pairing_agent(configured_agent(PairingSettings::default()))
+        .await
+        .unwrap();
+    let device_info = adapter_manager
+        .pair_device(address, Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert!(device_info.paired);
+
+    adapter_manager.unpair_device(address).await.unwrap();
+    band.set_pairing(LoopbackPairing::Passkey(654321));
+    let error = adapter_manager
+        .pair_device(address, Duration::from_secs(1))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("Authentication"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn callbacks_answer_pairing_requests() {
+    let bus = LoopbackBus::new();
+    let band = LoopbackAdapter::new(&bus, "band");
+    let _advertisement = band.advertise_gatt_service(SERVICE, "Other").await.unwrap();
+    band.set_pairing(LoopbackPairing::PinCode("0000".to_string()));
+    let address = band.address().await.unwrap();
+    let mut adapter_manager =
+        AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader")));
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    adapter_manager
+        .register_pairing_agent(PairingAgent::new(move |request: &PairingRequest| {
+            seen.lock().unwrap().push(request.clone());
+            PairingAnswer::PinCode("0000".to_string())
+        }))
+        .await
+        .unwrap();
+    adapter_manager
+        .pair_device(address, Duration::from_secs(1))
+        .await
+        .unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].address, address);
+    assert_eq!(requests[0].alias.as_deref(), Some("Other"));
+    assert_eq!(requests[0].kind, PairingRequestKind::PinCode);
+}
+
+#[tokio::test]
+async fn configured_answers_follow_rules_and_settings() {
+    let agent = configured_agent(PairingSettings::default());
+    let answer = |alias, kind| {
+        let agent = agent.clone();
+        async move { agent.answer(&request(alias, kind)).await }
+    };
+
+    assert_eq!(
+        answer("Band", PairingRequestKind::Passkey).await,
+        PairingAnswer::Passkey(123456)
+    );
+    assert_eq!(
+        answer("Band", PairingRequestKind::Confirmation(123456)).await,
+        PairingAnswer::Accept
+    );
+    assert_eq!(
+        answer("Band", PairingRequestKind::Confirmation(111111)).await,
+        PairingAnswer::Reject
+    );
+    assert_eq!(
+        answer("Band", PairingRequestKind::Authorization).await,
+        PairingAnswer::Accept
+    );
+    assert_eq!(
+        answer("Other", PairingRequestKind::Passkey).await,
+        PairingAnswer::Reject
+    );
+    assert_eq!(
+        answer("Other", PairingRequestKind::Confirmation(111111)).await,
+        PairingAnswer::Reject
+    );
+    assert_eq!(
+        answer("Other", PairingRequestKind::Authorization).await,
+        PairingAnswer::Reject
+    );
+    assert_eq!(
+        answer("Other", PairingRequestKind::PinCode).await,
+        PairingAnswer::Reject
+    );
+
+    let agent = configured_agent(PairingSettings {
+        pin_code: Some("1234".to_string()),
+        confirm: true,
+        ..Default::default()
+    });
+    assert_eq!(
+        agent
+            .answer(&request("Other", PairingRequestKind::Confirmation(111111)))
+            .await,
+        PairingAnswer::Accept
+    );
+    assert_eq!(
+        agent
+            .answer(&request("Other", PairingRequestKind::PinCode))
+            .await,
+        PairingAnswer::PinCode("1234".to_string())
+    );
+}
+
+#[tokio::test]
+async fn answers_that_dont_fit_the_request_are_rejected() {
+    let agent = PairingAgent::new(|_: &PairingRequest| PairingAnswer::Passkey(1_000_000));
+    assert_eq!(
+        agent
+            .answer(&request("Band", PairingRequestKind::Passkey))
+            .await,
+        PairingAnswer::Reject
+    );
+    assert_eq!(
+        agent
+            .answer(&request("Band", PairingRequestKind::Confirmation(123456)))
+            .await,
+        PairingAnswer::Reject
+    );
+}
@@ -0,0 +1,215 @@
+use blt::backend::{BltAdapter, LoopbackAdapter, LoopbackBus};
+use blt::config::ClientSettings;
+use blt::heart_rate::HeartRate;
+use blt::{
+    AdapterManager, ApplicationClient, ApplicationServer, Backoff, Capture, CaptureSender, Shutdown,
+};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+#[test]
+fn backoff_doubles_up_to_the_max_delay() {
+    let backoff = Backoff::from_settings(&ClientSettings::default());
+    let delays: Vec<u64> = (1..=7)
+        .map(|attempt| backoff.base_delay(attempt).as_secs())
+        .collect();
+    assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30]);
+    assert_eq!(backoff.attempts, 5);
+    assert_eq!(backoff.base_delay(u32::MAX), Duration::from_secs(30));
+
+    for attempt in 1..=7 {
+        let delay = backoff.delay(attempt);
+        assert!(delay <= backoff.base_delay(attempt));
+        assert!(delay >= backoff.base_delay(attempt) / 2);
+    }
+}
+
+#[test]
+fn signal_loss_captures_record_the_gap() {
+    let capture = Capture::signal_loss(Duration::from_millis(7500));
+    assert!(capture.is_signal_loss());
+    assert_eq!(capture.value, blt::SIGNAL_LOSS);
+    let reading = capture.reading.unwrap();
+    assert_eq!((reading.value, reading.unit.as_str()), (7.5, "s"));
+    assert!(!Capture::new("00".to_string()).is_signal_loss());
+}
+
+#[tokio::test(start_paused = true)]
+async fn clients_reconnect_to_bands_back_in_range() {
+    let bus = LoopbackBus::new();
+    let band = LoopbackAdapter::new(&bus, "band");
+    let band_address = band.address().await.unwrap();
+    let server = tokio::spawn(
+        ApplicationServer::new(
+            Box::new(HeartRate::default()),
+            AdapterManager::with_adapter(Box::new(band)),
+        )
+        .run(),
+    );
+
+    let (capture_sender, mut captures) = CaptureSender::channel();
+    let client = tokio::spawn(
+        ApplicationClient::new(
+            Box::new(HeartRate::default()),
+            AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader"))),
+        )
+        .with_capture_sender(capture_sender)
+        .run(),
+    );
+    let capture = timeout(Duration::from_secs(30), captures.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!capture.is_signal_loss());
+
+    // The band walks out of range for a while.
+    bus.set_present(band_address, false);
+    sleep(Duration::from_secs(10)).await;
+    bus.set_present(band_address, true);
+
+    let mut signal_loss = None;
+    while signal_loss.is_none() {
+        let capture = timeout(Duration::from_secs(60), captures.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        if capture.is_signal_loss() {
+            signal_loss = capture.reading;
+        }
+    }
+    assert!(signal_loss.unwrap().value >= 10.0);
+
+    // Notifications are received again.
+    let capture = timeout(Duration::from_secs(30), captures.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!capture.is_signal_loss());
+    assert_eq!(capture.device.unwrap().address, band_address.to_string());
+    assert!(!client.is_finished());
+
+    client.abort();
+    server.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn clients_give_up_on_bands_that_do_not_come_back() {
+    let bus = LoopbackBus::new();
+    let band = LoopbackAdapter::new(&bus, "band");
+    let band_address = band.address().await.unwrap();
+    let server = tokio::spawn(
+        ApplicationServer::new(
+            Box::new(HeartRate::default()),
+            AdapterManager::with_adapter(Box::new(band)),
+        )
+        .run(),
+    );
+
+    let (capture_sender, mut captures) = CaptureSender::channel();
+    let settings = ClientSettings {
+        reconnect_attempts: 2,
+        ..Default::default()
+    };
+    let client = tokio::spawn(
+        ApplicationClient::new(
+            Box::new(HeartRate::default()),
+            AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader"))),
+        )
+        .with_settings(settings)
+        .with_capture_sender(capture_sender)
+        .run(),
+    );
+    timeout(Duration::from_secs(30), captures.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The band walks out of range for good.
+    bus.set_present(band_address, false);
+    let error = timeout(Duration::from_secs(60), client)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("not reconnected after 2 attempts"));
+
+    let mut last = None;
+    while let Ok(capture) = captures.try_recv() {
+        last = Some(capture);
+    }
+    assert!(last.unwrap().is_signal_loss());
+
+    server.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_stops_reconnection() {
+    let bus = LoopbackBus::new();
+    let band = LoopbackAdapter::new(&bus, "band");
+    let band_address = band.address().await.unwrap();
+    let server = tokio::spawn(
+        ApplicationServer::new(
+            Box::new(HeartRate::default()),
+            AdapterManager::with_adapter(Box::new(band)),
+        )
+        .run(),
+    );
+
+    let (capture_sender, mut captures) = CaptureSender::channel();
+    let (stop, shutdown) = Shutdown::channel();
+    let client = tokio::spawn(
+        ApplicationClient::new(
+            Box::new(HeartRate::default()),
+            AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader"))),
+        )
+        .with_capture_sender(capture_sender)
+        .with_shutdown(shutdown)
+        .run(),
+    );
+    timeout(Duration::from_secs(30), captures.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The band walks out of range and the reader is stopped while reconnecting it.
+    bus.set_present(band_address, false);
+    sleep(Duration::from_secs(5)).await;
+    stop.send(true).unwrap();
+    timeout(Duration::from_millis(100), client)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let mut last = None;
+    while let Ok(capture) = captures.try_recv() {
+        last = Some(capture);
+    }
+    assert!(last.unwrap().is_signal_loss());
+
+    server.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn shutdown_stops_the_server() {
+    let bus = LoopbackBus::new();
+    let (stop, shutdown) = Shutdown::channel();
+    let server = tokio::spawn(
+        ApplicationServer::new(
+            Box::new(HeartRate::default()),
+            AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "band"))),
+        )
+        .with_shutdown(shutdown)
+        .run(),
+    );
+
+    sleep(Duration::from_secs(5)).await;
+    stop.send(true).unwrap();
+    timeout(Duration::from_millis(100), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+}
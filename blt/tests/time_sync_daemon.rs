@@ -0,0 +1,134 @@
+use blt::backend::{BltAdapter, LoopbackAdapter, LoopbackBus};
+use blt::cts::{EmulatedClock, CTS};
+use blt::time_sync_daemon::TimeSyncConfig;
+use blt::{AdapterManager, ApplicationServer, TimeSyncDaemon};
+use std::fs;
+use std::time::Duration;
+use tokio::time::sleep;
+use uuid::Uuid;
+
+#[tokio::test(start_paused = true)]
+async fn daemon_resynchronizes_and_tracks_drift() {
+    let bus = LoopbackBus::new();
+    let server_adapter = LoopbackAdapter::new(&bus, "band");
+    let address = server_adapter.address().await.unwrap();
+    let cts = CTS::default()
+        .with_offset(chrono::Duration::hours(1))
+        .with_drift_rate(5000.0);
+    let server = tokio::spawn(
+        ApplicationServer::new(
+            Box::new(cts),
+            AdapterManager::with_adapter(Box::new(server_adapter)),
+        )
+        .run(),
+    );
+
+    let history_path =
+        std::env::temp_dir().join(format!("phonendo_time_sync_{}.csv", std::process::id()));
+    let _ = fs::remove_file(&history_path);
+    let config = TimeSyncConfig {
+        interval: Duration::from_secs(60),
+        threshold: chrono::Duration::seconds(1),
+        discovery_timeout: Duration::from_secs(5),
+        history_path: Some(history_path.clone()),
+        ..Default::default()
+    };
+    let local_clock = EmulatedClock::new(chrono::Utc::now().naive_utc());
+    let mut daemon = TimeSyncDaemon::new(
+        AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader"))),
+        config,
+    )
+    .with_local_clock(move || local_clock.now());
+
+    for _ in 0..3 {
+        daemon.synchronize_round().await;
+        sleep(Duration::from_secs(60)).await;
+    }
+
+    let history = daemon.history(&address).unwrap();
+    let samples = history.samples();
+    assert_eq!(samples.len(), 3);
+
+    // The band starts one hour ahead, so the first round resynchronizes it.
+    assert!((samples[0].offset.num_seconds() - 3600).abs() <= 1);
+    assert!(samples[0].synchronized);
+    // From then on it only drifts 5 ms per second, below the threshold.
+    for sample in samples.iter().skip(1) {
+        assert!(!sample.synchronized);
+    }
+    assert!(samples[2].offset > samples[1].offset);
+    let drift_rate = history.drift_rate().unwrap();
+    assert!((drift_rate - 5000.0).abs() < 50.0, "{}", drift_rate);
+
+    let lines = fs::read_to_string(&history_path).unwrap();
+    assert_eq!(lines.lines().count(), 3);
+    assert!(lines
+        .lines()
+        .all(|line| line.starts_with(&address.to_string())));
+    let _ = fs::remove_file(&history_path);
+
+    server.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn daemon_ignores_devices_without_cts() {
+    let bus = LoopbackBus::new();
+    let server_adapter = LoopbackAdapter::new(&bus, "other");
+    let address = server_adapter.address().await.unwrap();
+    let server = tokio::spawn(
+        ApplicationServer::new(
+            Box::new(blt::ping_pong::PingPong),
+            AdapterManager::with_adapter(Box::new(server_adapter)),
+        )
+        .run(),
+    );
+
+    let mut daemon = TimeSyncDaemon::new(
+        AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader"))),
+        TimeSyncConfig {
+            discovery_timeout: Duration::from_secs(5),
+            ..Default::default()
+        },
+    );
+    daemon.synchronize_round().await;
+    assert!(daemon.history(&address).is_none());
+
+    server.abort();
+}
+
+#[tokio::test(start_paused = true)]
+async fn daemon_skips_devices_gone_before_being_inspected() {
+    let bus = LoopbackBus::new();
+    let server_adapter = LoopbackAdapter::new(&bus, "band");
+    let address = server_adapter.address().await.unwrap();
+
+    let mut daemon = TimeSyncDaemon::new(
+        AdapterManager::with_adapter(Box::new(LoopbackAdapter::new(&bus, "reader"))),
+        TimeSyncConfig {
+            discovery_timeout: Duration::from_secs(5),
+            ..Default::default()
+        },
+    );
+    let appear = async {
+        sleep(Duration::from_secs(1)).await;
+        // A device advertising only for a moment is reported before the band.
+        let ghost = LoopbackAdapter::new(&bus, "ghost");
+        let advertisement = ghost
+            .advertise_gatt_service(Uuid::new_v4(), "Ghost")
+            .await
+            .unwrap();
+        drop(advertisement);
+        drop(ghost);
+        ApplicationServer::new(
+            Box::new(CTS::default()),
+            AdapterManager::with_adapter(Box::new(server_adapter)),
+        )
+        .run()
+        .await
+    };
+    tokio::select! {
+        () = daemon.synchronize_round() => (),
+        _ = appear => panic!("The band stopped."),
+    }
+    assert_eq!(daemon.history(&address).unwrap().samples().len(), 1);
+}
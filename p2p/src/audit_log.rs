@@ -0,0 +1,305 @@
+use crate::identity::{from_hex, ReaderIdentity};
+use anyhow::Result;
+use blt::capture::to_hex;
+use blt::config::ManagerSettings;
+use blt::Capture;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+const DEFAULT_PATH: &str = "phonendo_audit.jsonl";
+/// Entries per Merkle batch unless told otherwise.
+pub const DEFAULT_MERKLE_BATCH_SIZE: usize = 100;
+
+/// Previous hash of the first entry.
+const GENESIS_HASH: [u8; 32] = [0; 32];
+
+/// Domain separation of the Merkle tree leaves and inner nodes.
+const MERKLE_LEAF: u8 = 0;
+const MERKLE_NODE: u8 = 1;
+
+pub struct AuditLogConfig {
+    /// File where the entries are appended, one JSON entry per line.
+    pub path: PathBuf,
+}
+
+impl AuditLogConfig {
+    pub fn from_settings(settings: &ManagerSettings) -> Self {
+        Self {
+            path: settings.audit_log_path.clone(),
+        }
+    }
+}
+
+impl Default for AuditLogConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from(DEFAULT_PATH),
+        }
+    }
+}
+
+/// Entry of the audit log. Its hash covers its index, the hash of the previous entry and the
+/// capture exactly as written, so changing, removing or reordering entries breaks the chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub index: u64,
+    /// Hex encoded SHA-256 hash of the previous entry, zeros for the first one.
+    pub previous_hash: String,
+    /// Hex encoded SHA-256 hash of this entry.
+    pub hash: String,
+    pub capture: Box<RawValue>,
+}
+
+impl AuditEntry {
+    pub fn capture(&self) -> Result<Capture> {
+        Capture::from_json(self.capture.get().as_bytes())
+    }
+
+    fn compute_hash(index: u64, previous_hash: &[u8], capture: &RawValue) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(index.to_be_bytes());
+        hasher.update(previous_hash);
+        hasher.update(capture.get().as_bytes());
+        hasher.finalize().into()
+    }
+}
+
+/// Merkle root of a batch of consecutive entries, to be anchored on the ledger.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleBatch {
+    pub first_index: u64,
+    pub last_index: u64,
+    /// Hex encoded root of the tree whose leaves are the entry hashes.
+    pub root: String,
+}
+
+/// Append only, hash chained log of every capture taken by the reader.
+pub struct AuditLog {
+    path: PathBuf,
+    next_index: u64,
+    last_hash: [u8; 32],
+}
+
+impl AuditLog {
+    /// Opens the log, checking the chain left by previous runs. A last entry left half written,
+    /// e.g. by a power loss, is removed. Any other broken chain is an error, it must be
+    /// investigated before appending to it.
+    pub fn open(config: AuditLogConfig) -> Result<Self> {
+        let mut log = Self {
+            path: config.path,
+            next_index: 0,
+            last_hash: GENESIS_HASH,
+        };
+        if log.path.exists() {
+            log.recover_last_entry()?;
+            if let Some(entry) = read_audit_log(&log.path)?.last() {
+                log.next_index = entry.index + 1;
+                log.last_hash = from_hex(&entry.hash)?
+                    .try_into()
+                    .map_err(|_| anyhow::Error::msg("Invalid audit entry hash."))?;
+            }
+        }
+        Ok(log)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Truncates the log back to its last complete line if the last one isn't a valid entry, and
+    /// ends it with a newline if the write stopped just before it.
+    fn recover_last_entry(&self) -> Result<()> {
+        let content = fs::read(&self.path)?;
+        let lines = content.strip_suffix(b"\n").unwrap_or(&content);
+        let start = lines
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |index| index + 1);
+        let last = &lines[start..];
+        if last.is_empty() {
+            return Ok(());
+        }
+
+        let file = OpenOptions::new().append(true).open(&self.path)?;
+        if serde_json::from_slice::<AuditEntry>(last).is_err() {
+            file.set_len(start as u64)?;
+            println!(
+                "Audit log '{}': truncated last entry removed.",
+                self.path.display()
+            );
+        } else if !content.ends_with(b"\n") {
+            (&file).write_all(b"\n")?;
+        } else {
+            return Ok(());
+        }
+        file.sync_data()?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> u64 {
+        self.next_index
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next_index == 0
+    }
+
+    /// Appends `capture`, writing it to disk before returning.
+    pub fn append(&mut self, capture: &Capture) -> Result<AuditEntry> {
+        let capture = RawValue::from_string(String::from_utf8(capture.to_json())?)?;
+        let hash = AuditEntry::compute_hash(self.next_index, &self.last_hash, &capture);
+        let entry = AuditEntry {
+            index: self.next_index,
+            previous_hash: to_hex(&self.last_hash),
+            hash: to_hex(&hash),
+            capture,
+        };
+
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)?;
+        file.sync_data()?;
+
+        self.next_index += 1;
+        self.last_hash = hash;
+        Ok(entry)
+    }
+}
+
+/// Reads the log in `path`, checking every entry against the previous one.
+pub fn read_audit_log(path: &Path) -> Result<Vec<AuditEntry>> {
+    let content = fs::read_to_string(path).map_err(|error| {
+        anyhow::Error::msg(format!(
+            "Unable to read audit log '{}': {}",
+            path.display(),
+            error
+        ))
+    })?;
+
+    let mut entries: Vec<AuditEntry> = Vec::new();
+    let mut previous_hash = to_hex(&GENESIS_HASH);
+    for (line_index, line) in content.lines().enumerate() {
+        let broken = |reason: &str| {
+            anyhow::Error::msg(format!(
+                "Audit log '{}' is broken at line {}: {}.",
+                path.display(),
+                line_index + 1,
+                reason
+            ))
+        };
+
+        let entry: AuditEntry = serde_json::from_str(line).map_err(|_| broken("invalid entry"))?;
+        if entry.index != entries.len() as u64 {
+            return Err(broken("unexpected index"));
+        }
+        if entry.previous_hash != previous_hash {
+            return Err(broken("previous hash mismatch"));
+        }
+        let hash = AuditEntry::compute_hash(
+            entry.index,
+            &from_hex(&entry.previous_hash)?,
+            &entry.capture,
+        );
+        if entry.hash != to_hex(&hash) {
+            return Err(broken("hash mismatch"));
+        }
+        previous_hash = entry.hash.clone();
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Checks the whole chain in `path`, returning the number of entries.
+pub fn verify_audit_log(path: &Path) -> Result<usize> {
+    Ok(read_audit_log(path)?.len())
+}
+
+/// Roots of every complete batch of `batch_size` entries. The last batch is left out until it is
+/// complete, so an exported root never changes.
+pub fn merkle_roots(entries: &[AuditEntry], batch_size: usize) -> Result<Vec<MerkleBatch>> {
+    if batch_size == 0 {
+        return Err(anyhow::Error::msg("Merkle batch size must be positive."));
+    }
+    entries
+        .chunks_exact(batch_size)
+        .map(|batch| {
+            let hashes = batch
+                .iter()
+                .map(|entry| from_hex(&entry.hash))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(MerkleBatch {
+                first_index: batch[0].index,
+                last_index: batch[batch.len() - 1].index,
+                root: to_hex(&merkle_root(&hashes)),
+            })
+        })
+        .collect()
+}
+
+/// Root of the Merkle tree of `leaves`. An odd node is promoted to the next level unchanged.
+pub fn merkle_root<T: AsRef<[u8]>>(leaves: &[T]) -> [u8; 32] {
+    let mut level: Vec<[u8; 32]> = leaves
+        .iter()
+        .map(|leaf| {
+            Sha256::new()
+                .chain_update([MERKLE_LEAF])
+                .chain_update(leaf)
+                .finalize()
+                .into()
+        })
+        .collect();
+    if level.is_empty() {
+        return Sha256::digest([]).into();
+    }
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => Sha256::new()
+                    .chain_update([MERKLE_NODE])
+                    .chain_update(left)
+                    .chain_update(right)
+                    .finalize()
+                    .into(),
+                [odd] => *odd,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// Signs every capture received with `identity` and appends it to `log` before passing it on to
+/// `forward`, so the log records the captures as they are sent. Returns when the captures channel
+/// closes, or with an error if a capture could not be signed or logged.
+pub async fn audit_captures(
+    log: &mut AuditLog,
+    identity: &ReaderIdentity,
+    mut captures: UnboundedReceiver<Capture>,
+    forward: UnboundedSender<Capture>,
+) -> Result<()> {
+    while let Some(mut capture) = captures.recv().await {
+        identity.sign(&mut capture)?;
+        log.append(&capture).map_err(|error| {
+            anyhow::Error::msg(format!(
+                "Unable to append capture to audit log '{}': {}",
+                log.path().display(),
+                error
+            ))
+        })?;
+        if forward.send(capture).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use crate::codec::RawCodec;
+use crate::protocol::{CAPTURE_PROTOCOL, DISCOVER_PROTOCOL};
+use libp2p::request_response::{self, ProtocolSupport};
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::swarm::NetworkBehaviour;
+use libp2p::{mdns, ping, PeerId, StreamProtocol};
+use std::io;
+use std::time::Duration;
+
+/// Network behaviour shared by phonendo nodes: mDNS discovery, liveness checks, and the
+/// `/discover/1.0.0` and `/capture/1.0.0` request-response protocols.
+#[derive(NetworkBehaviour)]
+pub struct PhonendoBehaviour {
+    pub mdns: Toggle<mdns::tokio::Behaviour>,
+    pub ping: ping::Behaviour,
+    pub discover: request_response::Behaviour<RawCodec>,
+    pub capture: request_response::Behaviour<RawCodec>,
+}
+
+impl PhonendoBehaviour {
+    /// Captures go out of readers and into phonendo_manager (`capture_support`), while both ask
+    /// and answer `/discover/1.0.0`. `mdns_query_interval` None disables mDNS discovery.
+    pub fn new(
+        local_peer_id: PeerId,
+        capture_support: ProtocolSupport,
+        mdns_query_interval: Option<Duration>,
+        request_timeout: Duration,
+    ) -> io::Result<Self> {
+        let mdns = match mdns_query_interval {
+            Some(query_interval) => Some(mdns::tokio::Behaviour::new(
+                mdns::Config {
+                    query_interval,
+                    ..Default::default()
+                },
+                local_peer_id,
+            )?),
+            None => None,
+        };
+        let config = request_response::Config::default().with_request_timeout(request_timeout);
+
+        Ok(Self {
+            mdns: Toggle::from(mdns),
+            ping: ping::Behaviour::new(ping::Config::new()),
+            discover: request_response::Behaviour::with_codec(
+                RawCodec,
+                [(
+                    StreamProtocol::new(DISCOVER_PROTOCOL),
+                    ProtocolSupport::Full,
+                )],
+                config.clone(),
+            ),
+            capture: request_response::Behaviour::with_codec(
+                RawCodec,
+                [(StreamProtocol::new(CAPTURE_PROTOCOL), capture_support)],
+                config,
+            ),
+        })
+    }
+}
@@ -0,0 +1,40 @@
+use anyhow::Result;
+use p2p::{merkle_roots, read_audit_log, AuditLogConfig, DEFAULT_MERKLE_BATCH_SIZE};
+use std::env;
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: audit_log verify [path] | audit_log roots [path] [batch_size]";
+
+/// Walks the audit log of a reader: `verify` checks its hash chain and `roots` prints the Merkle
+/// root of every complete batch, one JSON object per line, ready to be anchored on the ledger.
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = args
+        .get(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| AuditLogConfig::default().path);
+
+    match args.first().map(String::as_str) {
+        Some("verify") => {
+            let entries = read_audit_log(&path)?;
+            println!(
+                "Audit log '{}' is valid ({} entries).",
+                path.display(),
+                entries.len()
+            );
+        }
+        Some("roots") => {
+            let batch_size = match args.get(2) {
+                Some(batch_size) => batch_size.parse().map_err(|_| {
+                    anyhow::Error::msg(format!("Invalid batch size '{}'.", batch_size))
+                })?,
+                None => DEFAULT_MERKLE_BATCH_SIZE,
+            };
+            for batch in merkle_roots(&read_audit_log(&path)?, batch_size)? {
+                println!("{}", serde_json::to_string(&batch)?);
+            }
+        }
+        _ => return Err(anyhow::Error::msg(USAGE)),
+    }
+    Ok(())
+}
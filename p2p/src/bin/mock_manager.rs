@@ -0,0 +1,38 @@
+use anyhow::Result;
+use p2p::{CaptureReply, MockManager, MockManagerConfig};
+use std::env;
+use std::time::Duration;
+
+/// Comma separated replies to the first captures: true, false, timeout or disconnect.
+const MOCK_MANAGER_SCRIPT: &str = "MOCK_MANAGER_SCRIPT";
+/// Reply to the captures received once the script is over, true by default.
+const MOCK_MANAGER_REPLY: &str = "MOCK_MANAGER_REPLY";
+
+const MDNS_QUERY_INTERVAL: Duration = Duration::from_secs(20);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut config = MockManagerConfig {
+        mdns_query_interval: Some(MDNS_QUERY_INTERVAL),
+        ..Default::default()
+    };
+    if let Ok(script) = env::var(MOCK_MANAGER_SCRIPT) {
+        config.script = script
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<CaptureReply>>>()?;
+    }
+    if let Ok(reply) = env::var(MOCK_MANAGER_REPLY) {
+        config.default_reply = reply.parse()?;
+    }
+
+    let manager = MockManager::start(config).await?;
+    println!(
+        "Mock phonendo_manager {} listening on {}. Press Ctrl+C to quit.",
+        manager.peer_id(),
+        manager.listen_address()
+    );
+    tokio::signal::ctrl_c().await?;
+    println!("{} captures received.", manager.captures().len());
+    Ok(())
+}
@@ -0,0 +1,290 @@
+use crate::protocol::{decode_capture, encode_capture};
+use anyhow::Result;
+pub use blt::config::EvictionPolicy;
+use blt::config::ManagerSettings;
+use blt::Capture;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_PATH: &str = "phonendo_captures.jsonl";
+const DEFAULT_CAPACITY: usize = 100_000;
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// Acknowledged bytes kept in the queue file before it is compacted.
+const COMPACTION_THRESHOLD: u64 = 1 << 20;
+
+pub struct CaptureQueueConfig {
+    /// File where pending captures are kept, one JSON capture per line. The offset of the first
+    /// pending one is kept next to it, in the same path ending in `.offset`.
+    pub path: PathBuf,
+    /// Maximum number of pending captures.
+    pub capacity: usize,
+    pub eviction_policy: EvictionPolicy,
+    /// Time between attempts to send pending captures while phonendo_manager is unavailable.
+    pub retry_interval: Duration,
+}
+
+impl CaptureQueueConfig {
+    pub fn from_settings(settings: &ManagerSettings) -> Result<Self> {
+        Ok(Self {
+            path: settings.capture_queue_path.clone(),
+            capacity: settings.capture_queue_capacity,
+            eviction_policy: settings.capture_queue_eviction.parse()?,
+            retry_interval: Duration::from_secs(settings.capture_queue_retry_interval),
+        })
+    }
+}
+
+impl Default for CaptureQueueConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from(DEFAULT_PATH),
+            capacity: DEFAULT_CAPACITY,
+            eviction_policy: EvictionPolicy::DropOldest,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+        }
+    }
+}
+
+/// Captures waiting to be stored by phonendo_manager, in arrival order. Every change is written to
+/// disk before returning, so pending captures survive restarts.
+///
+/// New captures are appended to the queue file and stored ones are only skipped, by moving the
+/// offset of the first pending capture. The file is compacted once the skipped captures take more
+/// room than the pending ones. If the reader stops while compacting, stored captures may be sent
+/// again, but pending ones are never lost.
+pub struct CaptureQueue {
+    config: CaptureQueueConfig,
+    /// Pending captures and the length of their line in the queue file.
+    captures: VecDeque<(Capture, u64)>,
+    /// Offset of the first pending capture in the queue file.
+    offset: u64,
+    /// Length of the queue file.
+    length: u64,
+}
+
+impl CaptureQueue {
+    /// Opens the queue, loading the captures left pending by previous runs.
+    pub fn open(config: CaptureQueueConfig) -> Result<Self> {
+        let mut captures = VecDeque::new();
+        if config.path.exists() {
+            let content = fs::read(&config.path).map_err(|error| {
+                anyhow::Error::msg(format!(
+                    "Unable to read capture queue '{}': {}",
+                    config.path.display(),
+                    error
+                ))
+            })?;
+            let offset = read_offset(&offset_path(&config.path)).min(content.len() as u64);
+            for (index, line) in content[offset as usize..]
+                .split(|&byte| byte == b'\n')
+                .enumerate()
+            {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                // A line may be truncated if the reader stopped while writing it.
+                match decode_capture(line) {
+                    Ok(capture) => {
+                        let length = line.len() as u64 + 1;
+                        captures.push_back((capture, length))
+                    }
+                    Err(error) => println!(
+                        "Capture queue '{}', pending line {} skipped: {}",
+                        config.path.display(),
+                        index + 1,
+                        error
+                    ),
+                }
+            }
+        }
+
+        let mut queue = Self {
+            config,
+            captures,
+            offset: 0,
+            length: 0,
+        };
+        if queue.captures.len() > queue.config.capacity {
+            let excess = queue.captures.len() - queue.config.capacity;
+            match queue.config.eviction_policy {
+                EvictionPolicy::DropOldest => drop(queue.captures.drain(..excess)),
+                EvictionPolicy::DropNewest => queue.captures.truncate(queue.config.capacity),
+            }
+            println!(
+                "{} pending captures evicted from the capture queue.",
+                excess
+            );
+        }
+        queue.compact()?;
+        if !queue.is_empty() {
+            println!("{} pending captures loaded.", queue.len());
+        }
+
+        Ok(queue)
+    }
+
+    pub fn retry_interval(&self) -> Duration {
+        self.config.retry_interval
+    }
+
+    pub fn len(&self) -> usize {
+        self.captures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.captures.is_empty()
+    }
+
+    /// Oldest pending capture.
+    pub fn front(&self) -> Option<&Capture> {
+        self.captures.front().map(|(capture, _)| capture)
+    }
+
+    /// Adds `capture` to the queue. Returns false if it was dropped because the queue is full.
+    pub fn push(&mut self, capture: Capture) -> Result<bool> {
+        if self.captures.len() >= self.config.capacity {
+            match self.config.eviction_policy {
+                EvictionPolicy::DropNewest => return Ok(false),
+                EvictionPolicy::DropOldest => {
+                    self.skip_front()?;
+                }
+            }
+        }
+
+        let length = self.append(&capture)?;
+        self.captures.push_back((capture, length));
+        Ok(true)
+    }
+
+    /// Removes the oldest pending capture, once phonendo_manager has stored it.
+    pub fn pop_front(&mut self) -> Result<Option<Capture>> {
+        let capture = self.skip_front()?;
+        let skipped = self.offset;
+        let pending = self.length - self.offset;
+        // The capture is already removed on disk, truncating or compacting is only about the file
+        // size.
+        if self.is_empty() {
+            if let Err(error) = self.truncate() {
+                println!("Unable to truncate capture queue: {}.", error);
+            }
+        } else if skipped >= COMPACTION_THRESHOLD && skipped >= pending {
+            if let Err(error) = self.compact() {
+                println!("Unable to compact capture queue: {}.", error);
+            }
+        }
+        Ok(capture)
+    }
+
+    /// Moves the offset past the oldest pending capture, on disk and then in memory.
+    fn skip_front(&mut self) -> Result<Option<Capture>> {
+        let length = match self.captures.front() {
+            Some((_, length)) => *length,
+            None => return Ok(None),
+        };
+        write_offset(&self.config.path, self.offset + length)?;
+        self.offset += length;
+        Ok(self.captures.pop_front().map(|(capture, _)| capture))
+    }
+
+    /// Appends `capture` to the queue file, returning the length of its line.
+    fn append(&mut self, capture: &Capture) -> Result<u64> {
+        let mut line = encode_capture(capture);
+        line.push(b'\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.path)?;
+        file.write_all(&line)?;
+        file.sync_data()?;
+        self.length += line.len() as u64;
+        Ok(line.len() as u64)
+    }
+
+    /// Empties the queue file once no capture is pending. As when compacting, the offset is reset
+    /// first.
+    fn truncate(&mut self) -> Result<()> {
+        write_offset(&self.config.path, 0)?;
+        let truncated = OpenOptions::new()
+            .write(true)
+            .open(&self.config.path)
+            .and_then(|file| {
+                file.set_len(0)?;
+                file.sync_data()
+            });
+        if let Err(error) = truncated {
+            let _ = write_offset(&self.config.path, self.offset);
+            return Err(error.into());
+        }
+        self.offset = 0;
+        self.length = 0;
+        Ok(())
+    }
+
+    /// Replaces the queue file with one holding only the pending captures, through a temporary
+    /// file so that it is never left half written. The offset is reset before the file is replaced,
+    /// so an interruption in between makes the stored captures pending again instead of losing
+    /// pending ones.
+    fn compact(&mut self) -> Result<()> {
+        let mut path = self.config.path.clone().into_os_string();
+        path.push(".tmp");
+        let path = PathBuf::from(path);
+
+        let mut file = File::create(&path)?;
+        let mut lengths = Vec::with_capacity(self.captures.len());
+        for (capture, _) in &self.captures {
+            let mut line = encode_capture(capture);
+            line.push(b'\n');
+            file.write_all(&line)?;
+            lengths.push(line.len() as u64);
+        }
+        file.sync_data()?;
+        write_offset(&self.config.path, 0)?;
+        if let Err(error) = fs::rename(&path, &self.config.path) {
+            let _ = write_offset(&self.config.path, self.offset);
+            return Err(error.into());
+        }
+        for ((_, length), new_length) in self.captures.iter_mut().zip(&lengths) {
+            *length = *new_length;
+        }
+        self.offset = 0;
+        self.length = lengths.iter().sum();
+        Ok(())
+    }
+}
+
+fn offset_path(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(".offset");
+    PathBuf::from(path)
+}
+
+/// Offset stored in `path`, 0 if there is none.
+fn read_offset(path: &Path) -> u64 {
+    match fs::read_to_string(path) {
+        Ok(content) => content.trim().parse().unwrap_or_else(|_| {
+            println!(
+                "Invalid capture queue offset '{}', every capture is pending.",
+                path.display()
+            );
+            0
+        }),
+        Err(_) => 0,
+    }
+}
+
+/// Stores the offset of the first pending capture of the queue in `queue_path`.
+fn write_offset(queue_path: &Path, offset: u64) -> Result<()> {
+    let path = offset_path(queue_path);
+    let mut temporary = path.clone().into_os_string();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut file = File::create(&temporary)?;
+    file.write_all(offset.to_string().as_bytes())?;
+    file.sync_data()?;
+    fs::rename(&temporary, &path)?;
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::request_response::Codec;
+use libp2p::StreamProtocol;
+use std::io;
+
+/// Largest request or response accepted.
+const MAX_MESSAGE_LENGTH: u64 = 1024 * 1024;
+
+/// Codec of the phonendo protocols (as phonendo_manager speaks them): each side writes its whole message and closes its
+/// write half, so messages are raw bytes delimited by the end of the stream.
+#[derive(Debug, Clone, Default)]
+pub struct RawCodec;
+
+impl RawCodec {
+    async fn read<T>(io: &mut T) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut message = Vec::new();
+        io.take(MAX_MESSAGE_LENGTH + 1)
+            .read_to_end(&mut message)
+            .await?;
+        if message.len() as u64 > MAX_MESSAGE_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message too long",
+            ));
+        }
+        Ok(message)
+    }
+
+    async fn write<T>(io: &mut T, message: Vec<u8>) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&message).await?;
+        io.flush().await
+    }
+}
+
+#[async_trait]
+impl Codec for RawCodec {
+    type Protocol = StreamProtocol;
+    type Request = Vec<u8>;
+    type Response = Vec<u8>;
+
+    async fn read_request<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        RawCodec::read(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        RawCodec::read(io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        request: Vec<u8>,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        RawCodec::write(io, request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &StreamProtocol,
+        io: &mut T,
+        response: Vec<u8>,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        RawCodec::write(io, response).await
+    }
+}
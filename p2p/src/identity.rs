@@ -0,0 +1,132 @@
+use anyhow::Result;
+use blt::capture::to_hex;
+use blt::Capture;
+use libp2p::identity::{Keypair, PublicKey};
+use libp2p::PeerId;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Ed25519 keypair of a reader: it is its libp2p identity (the peer id is derived from the public
+/// key) and signs every capture it sends.
+#[derive(Clone)]
+pub struct ReaderIdentity {
+    keypair: Keypair,
+}
+
+impl ReaderIdentity {
+    pub fn generate() -> Self {
+        Self {
+            keypair: Keypair::generate_ed25519(),
+        }
+    }
+
+    /// Loads the identity stored in `path`, creating it on first run.
+    pub fn load_or_generate(path: &Path) -> Result<Self> {
+        if path.exists() {
+            let bytes = fs::read(path)?;
+            let keypair = Keypair::from_protobuf_encoding(&bytes).map_err(|error| {
+                anyhow::Error::msg(format!(
+                    "Invalid reader identity '{}': {}",
+                    path.display(),
+                    error
+                ))
+            })?;
+            if keypair.key_type() != libp2p::identity::KeyType::Ed25519 {
+                return Err(anyhow::Error::msg(format!(
+                    "Reader identity '{}' is not an Ed25519 key.",
+                    path.display()
+                )));
+            }
+            return Ok(Self { keypair });
+        }
+
+        let identity = ReaderIdentity::generate();
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        // Only readable by its owner from the start, not after the key is written.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(path)?;
+        file.write_all(&identity.keypair.to_protobuf_encoding()?)?;
+        file.sync_all()?;
+        println!(
+            "Reader identity {} created in '{}'.",
+            identity.peer_id(),
+            path.display()
+        );
+        Ok(identity)
+    }
+
+    pub fn keypair(&self) -> &Keypair {
+        &self.keypair
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.keypair.public().to_peer_id()
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.keypair.public()
+    }
+
+    /// Sets the reader id of `capture` and signs it.
+    pub fn sign(&self, capture: &mut Capture) -> Result<()> {
+        capture.reader_id = Some(self.peer_id().to_string());
+        capture.signature = None;
+        let signature = self.keypair.sign(&capture.signing_bytes()?)?;
+        capture.signature = Some(to_hex(&signature));
+        Ok(())
+    }
+}
+
+/// Checks that `capture` was signed by the reader in its reader id, which embeds the public key.
+pub fn verify_capture(capture: &Capture) -> Result<()> {
+    let peer_id = capture_peer_id(capture)?;
+    let public_key = PublicKey::try_decode_protobuf(peer_id.as_ref().digest())
+        .map_err(|_| anyhow::Error::msg("Reader id does not embed its public key."))?;
+    verify_capture_with_key(capture, &public_key)
+}
+
+/// Checks that `capture` was signed with `public_key`, and that its reader id matches it.
+pub fn verify_capture_with_key(capture: &Capture, public_key: &PublicKey) -> Result<()> {
+    if capture_peer_id(capture)? != public_key.to_peer_id() {
+        return Err(anyhow::Error::msg(
+            "Capture reader id does not match the public key.",
+        ));
+    }
+    let signature = capture
+        .signature
+        .as_deref()
+        .ok_or_else(|| anyhow::Error::msg("Capture is not signed."))
+        .and_then(from_hex)?;
+    if !public_key.verify(&capture.signing_bytes()?, &signature) {
+        return Err(anyhow::Error::msg("Invalid capture signature."));
+    }
+    Ok(())
+}
+
+pub(crate) fn capture_peer_id(capture: &Capture) -> Result<PeerId> {
+    capture
+        .reader_id
+        .as_deref()
+        .ok_or_else(|| anyhow::Error::msg("Capture has no reader id."))?
+        .parse()
+        .map_err(|_| anyhow::Error::msg("Capture reader id is not a peer id."))
+}
+
+pub(crate) fn from_hex(value: &str) -> Result<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return Err(anyhow::Error::msg("Odd length hex string."));
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|index| {
+            u8::from_str_radix(value.get(index..index + 2).unwrap_or_default(), 16)
+                .map_err(|_| anyhow::Error::msg("Invalid hex string."))
+        })
+        .collect()
+}
@@ -0,0 +1,21 @@
+pub mod audit_log;
+pub mod behaviour;
+pub mod capture_queue;
+pub mod codec;
+pub mod identity;
+pub mod mock_manager;
+pub mod protocol;
+pub mod reader_node;
+
+pub use audit_log::{
+    audit_captures, merkle_root, merkle_roots, read_audit_log, verify_audit_log, AuditEntry,
+    AuditLog, AuditLogConfig, MerkleBatch, DEFAULT_MERKLE_BATCH_SIZE,
+};
+pub use behaviour::PhonendoBehaviour;
+pub use capture_queue::{CaptureQueue, CaptureQueueConfig, EvictionPolicy};
+pub use codec::RawCodec;
+pub use identity::{verify_capture, verify_capture_with_key, ReaderIdentity};
+pub use mock_manager::{CaptureReply, MockManager, MockManagerConfig, ReceivedCapture};
+pub use reader_node::{
+    build_swarm, forward_captures, CaptureOutcome, ReaderNode, ReaderNodeConfig,
+};
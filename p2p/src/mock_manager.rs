@@ -0,0 +1,311 @@
+use crate::behaviour::{PhonendoBehaviour, PhonendoBehaviourEvent};
+use crate::identity::{capture_peer_id, verify_capture, verify_capture_with_key};
+use crate::protocol::{
+    decode_capture, ReaderInfo, CAPTURE_DISCARDED, CAPTURE_STORED, DISCOVER_REQUEST,
+    MANAGER_NODE_TYPE,
+};
+use crate::reader_node::build_swarm;
+use anyhow::Result;
+use futures::StreamExt;
+use libp2p::identity::Keypair;
+use libp2p::request_response::{self, ProtocolSupport, ResponseChannel};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{Multiaddr, PeerId, Swarm};
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+const DEFAULT_LISTEN_ADDRESS: &str = "/ip4/127.0.0.1/tcp/0";
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the mock manager answers a capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureReply {
+    /// Replies "true".
+    Store,
+    /// Replies "false".
+    Discard,
+    /// Never replies, so the request times out.
+    Timeout,
+    /// Closes the connection without replying.
+    Disconnect,
+}
+
+impl FromStr for CaptureReply {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "true" | "store" => Ok(CaptureReply::Store),
+            "false" | "discard" => Ok(CaptureReply::Discard),
+            "timeout" => Ok(CaptureReply::Timeout),
+            "disconnect" => Ok(CaptureReply::Disconnect),
+            _ => Err(anyhow::Error::msg(format!(
+                "Unknown capture reply '{}' (available: true, false, timeout, disconnect).",
+                input
+            ))),
+        }
+    }
+}
+
+pub struct MockManagerConfig {
+    pub listen_address: Multiaddr,
+    /// None disables mDNS discovery.
+    pub mdns_query_interval: Option<Duration>,
+    /// Reply to the `/discover/1.0.0` handshake.
+    pub node_type: Vec<u8>,
+    /// Replies to the first captures, in order.
+    pub script: Vec<CaptureReply>,
+    /// Reply to the captures received once the script is over.
+    pub default_reply: CaptureReply,
+}
+
+impl Default for MockManagerConfig {
+    fn default() -> Self {
+        Self {
+            listen_address: DEFAULT_LISTEN_ADDRESS.parse().unwrap(),
+            mdns_query_interval: None,
+            node_type: MANAGER_NODE_TYPE.to_vec(),
+            script: Vec::new(),
+            default_reply: CaptureReply::Store,
+        }
+    }
+}
+
+/// Capture received by the mock manager.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedCapture {
+    pub peer: PeerId,
+    pub payload: Vec<u8>,
+    pub reply: CaptureReply,
+    /// Whether the capture is signed by the peer that sent it.
+    pub verified: bool,
+}
+
+struct MockManagerState {
+    script: VecDeque<CaptureReply>,
+    default_reply: CaptureReply,
+    captures: Vec<ReceivedCapture>,
+    readers: HashMap<PeerId, ReaderInfo>,
+}
+
+/// Stand-in for phonendo_manager: it answers the discover handshake, records every capture it
+/// receives and answers them as scripted. The swarm runs in its own task until it is dropped.
+pub struct MockManager {
+    peer_id: PeerId,
+    listen_address: Multiaddr,
+    state: Arc<Mutex<MockManagerState>>,
+    capture_received: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl MockManager {
+    pub async fn start(config: MockManagerConfig) -> Result<Self> {
+        let mut swarm = build_swarm(
+            Keypair::generate_ed25519(),
+            ProtocolSupport::Inbound,
+            config.mdns_query_interval,
+            DEFAULT_REQUEST_TIMEOUT,
+        )?;
+        swarm.listen_on(config.listen_address)?;
+        let listen_address = loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = swarm.select_next_some().await {
+                break address;
+            }
+        };
+        let peer_id = *swarm.local_peer_id();
+
+        let state = Arc::new(Mutex::new(MockManagerState {
+            script: config.script.into(),
+            default_reply: config.default_reply,
+            captures: Vec::new(),
+            readers: HashMap::new(),
+        }));
+        let capture_received = Arc::new(Notify::new());
+        let task = tokio::spawn(
+            EventLoop {
+                swarm,
+                node_type: config.node_type,
+                state: state.clone(),
+                capture_received: capture_received.clone(),
+                unanswered: Vec::new(),
+            }
+            .run(),
+        );
+
+        Ok(Self {
+            peer_id,
+            listen_address,
+            state,
+            capture_received,
+            task,
+        })
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    pub fn listen_address(&self) -> Multiaddr {
+        self.listen_address.clone()
+    }
+
+    /// Adds replies to the end of the script.
+    pub fn push_replies(&self, replies: &[CaptureReply]) {
+        self.state.lock().unwrap().script.extend(replies);
+    }
+
+    pub fn set_default_reply(&self, reply: CaptureReply) {
+        self.state.lock().unwrap().default_reply = reply;
+    }
+
+    /// Every capture received, in arrival order.
+    pub fn captures(&self) -> Vec<ReceivedCapture> {
+        self.state.lock().unwrap().captures.clone()
+    }
+
+    /// Readers that answered the discover handshake.
+    pub fn readers(&self) -> HashMap<PeerId, ReaderInfo> {
+        self.state.lock().unwrap().readers.clone()
+    }
+
+    /// Waits until `count` captures have been received, returning false after `duration`.
+    pub async fn wait_for_captures(&self, count: usize, duration: Duration) -> bool {
+        timeout(duration, async {
+            loop {
+                let notified = self.capture_received.notified();
+                if self.captures().len() >= count {
+                    break;
+                }
+                notified.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+}
+
+impl Drop for MockManager {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct EventLoop {
+    swarm: Swarm<PhonendoBehaviour>,
+    node_type: Vec<u8>,
+    state: Arc<Mutex<MockManagerState>>,
+    capture_received: Arc<Notify>,
+    /// Captures answered with [CaptureReply::Timeout]: dropping their channels would close the
+    /// streams and the reader would not wait for the timeout.
+    unanswered: Vec<ResponseChannel<Vec<u8>>>,
+}
+
+impl EventLoop {
+    async fn run(mut self) {
+        loop {
+            let event = self.swarm.select_next_some().await;
+            self.handle_event(event);
+        }
+    }
+
+    fn handle_event(&mut self, event: SwarmEvent<PhonendoBehaviourEvent>) {
+        match event {
+            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                self.swarm
+                    .behaviour_mut()
+                    .discover
+                    .send_request(&peer_id, DISCOVER_REQUEST.to_vec());
+            }
+            SwarmEvent::Behaviour(PhonendoBehaviourEvent::Discover(
+                request_response::Event::Message {
+                    peer,
+                    message: request_response::Message::Response { response, .. },
+                },
+            )) => {
+                if let Ok(reader_info) = ReaderInfo::from_json(&response) {
+                    self.state.lock().unwrap().readers.insert(peer, reader_info);
+                }
+            }
+            SwarmEvent::Behaviour(PhonendoBehaviourEvent::Discover(
+                request_response::Event::Message {
+                    message: request_response::Message::Request { channel, .. },
+                    ..
+                },
+            )) => {
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .discover
+                    .send_response(channel, self.node_type.clone());
+            }
+            SwarmEvent::Behaviour(PhonendoBehaviourEvent::Capture(
+                request_response::Event::Message {
+                    peer,
+                    message:
+                        request_response::Message::Request {
+                            request, channel, ..
+                        },
+                },
+            )) => self.handle_capture(peer, request, channel),
+            _ => (),
+        }
+    }
+
+    fn handle_capture(
+        &mut self,
+        peer: PeerId,
+        payload: Vec<u8>,
+        channel: ResponseChannel<Vec<u8>>,
+    ) {
+        let reply = {
+            let mut state = self.state.lock().unwrap();
+            let reply = state.script.pop_front().unwrap_or(state.default_reply);
+            let verified = decode_capture(&payload)
+                .and_then(|capture| {
+                    if capture_peer_id(&capture)? != peer {
+                        return Err(anyhow::Error::msg("Capture sent by another peer."));
+                    }
+                    match state.readers.get(&peer) {
+                        Some(reader_info) => {
+                            verify_capture_with_key(&capture, &reader_info.public_key()?)
+                        }
+                        None => verify_capture(&capture),
+                    }
+                })
+                .is_ok();
+            println!(
+                "Capture {} from {}, reply {:?}.",
+                String::from_utf8_lossy(&payload),
+                peer,
+                reply
+            );
+            state.captures.push(ReceivedCapture {
+                peer,
+                payload,
+                reply,
+                verified,
+            });
+            reply
+        };
+        self.capture_received.notify_waiters();
+
+        let capture = &mut self.swarm.behaviour_mut().capture;
+        match reply {
+            CaptureReply::Store => {
+                let _ = capture.send_response(channel, CAPTURE_STORED.to_vec());
+            }
+            CaptureReply::Discard => {
+                let _ = capture.send_response(channel, CAPTURE_DISCARDED.to_vec());
+            }
+            CaptureReply::Timeout => self.unanswered.push(channel),
+            CaptureReply::Disconnect => {
+                let _ = self.swarm.disconnect_peer_id(peer);
+            }
+        }
+    }
+}
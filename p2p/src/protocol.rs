@@ -0,0 +1,74 @@
+use crate::identity::{from_hex, ReaderIdentity};
+use anyhow::Result;
+use blt::capture::to_hex;
+use blt::Capture;
+use libp2p::identity::PublicKey;
+use serde::{Deserialize, Serialize};
+
+/// Protocol used to find out the type of a peer.
+pub const DISCOVER_PROTOCOL: &str = "/discover/1.0.0";
+
+/// Protocol used to send captures to phonendo_manager.
+pub const CAPTURE_PROTOCOL: &str = "/capture/1.0.0";
+
+/// Request sent over [DISCOVER_PROTOCOL].
+pub const DISCOVER_REQUEST: &[u8] = b"discover";
+
+/// Reply to [DISCOVER_REQUEST] of phonendo_manager nodes.
+pub const MANAGER_NODE_TYPE: &[u8] = b"phonendo_manager";
+
+/// Replies to captures: stored or discarded by phonendo_manager.
+pub const CAPTURE_STORED: &[u8] = b"true";
+pub const CAPTURE_DISCARDED: &[u8] = b"false";
+
+/// Capture payload: the JSON capture envelope.
+pub fn encode_capture(capture: &Capture) -> Vec<u8> {
+    capture.to_json()
+}
+
+pub fn decode_capture(payload: &[u8]) -> Result<Capture> {
+    Capture::from_json(payload)
+}
+
+/// Reply to [DISCOVER_REQUEST] of reader nodes.
+pub const READER_NODE_TYPE: &str = "phonendo_reader";
+
+/// Reader reply to [DISCOVER_REQUEST]: its type, peer id and hex encoded (protobuf) public key,
+/// with which its captures can be verified.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReaderInfo {
+    pub node_type: String,
+    pub peer_id: String,
+    pub public_key: String,
+}
+
+impl ReaderInfo {
+    pub fn new(identity: &ReaderIdentity) -> Self {
+        Self {
+            node_type: READER_NODE_TYPE.to_string(),
+            peer_id: identity.peer_id().to_string(),
+            public_key: to_hex(&identity.public_key().encode_protobuf()),
+        }
+    }
+
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap()
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        let reader_info: ReaderInfo = serde_json::from_slice(json)?;
+        if reader_info.node_type != READER_NODE_TYPE {
+            return Err(anyhow::Error::msg("Not a reader node."));
+        }
+        Ok(reader_info)
+    }
+
+    /// Public key, checked against the peer id.
+    pub fn public_key(&self) -> Result<PublicKey> {
+        let public_key = PublicKey::try_decode_protobuf(&from_hex(&self.public_key)?)?;
+        if public_key.to_peer_id().to_string() != self.peer_id {
+            return Err(anyhow::Error::msg("Public key does not match the peer id."));
+        }
+        Ok(public_key)
+    }
+}
@@ -0,0 +1,459 @@
+use crate::behaviour::{PhonendoBehaviour, PhonendoBehaviourEvent};
+use crate::capture_queue::CaptureQueue;
+use crate::identity::ReaderIdentity;
+use crate::protocol::{
+    encode_capture, ReaderInfo, CAPTURE_STORED, DISCOVER_REQUEST, MANAGER_NODE_TYPE,
+};
+use anyhow::Result;
+use blt::config::ManagerSettings;
+use blt::Capture;
+use futures::StreamExt;
+use libp2p::identity::Keypair;
+use libp2p::request_response::{self, OutboundFailure, OutboundRequestId, ProtocolSupport};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{mdns, noise, ping, tcp, Multiaddr, PeerId, Swarm, SwarmBuilder};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+const DEFAULT_LISTEN_ADDRESS: &str = "/ip4/127.0.0.1/tcp/0";
+const DEFAULT_MDNS_QUERY_INTERVAL: Duration = Duration::from_secs(20);
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct ReaderNodeConfig {
+    pub listen_address: Multiaddr,
+    /// None disables mDNS discovery.
+    pub mdns_query_interval: Option<Duration>,
+    /// Peers dialed on start, besides the ones found with mDNS.
+    pub peers: Vec<Multiaddr>,
+    pub request_timeout: Duration,
+    /// Random by default, see [ReaderIdentity::load_or_generate] to keep it between runs.
+    pub identity: ReaderIdentity,
+}
+
+impl ReaderNodeConfig {
+    /// Reader node of the `[manager]` settings, loading (or creating) its identity.
+    pub fn from_settings(settings: &ManagerSettings) -> Result<Self> {
+        let multiaddr = |key: &str, address: &str| {
+            address.parse::<Multiaddr>().map_err(|error| {
+                anyhow::Error::msg(format!("Invalid manager.{} '{}': {}", key, address, error))
+            })
+        };
+        Ok(Self {
+            listen_address: multiaddr("listen_address", &settings.listen_address)?,
+            mdns_query_interval: match settings.mdns_query_interval {
+                0 => None,
+                seconds => Some(Duration::from_secs(seconds)),
+            },
+            peers: settings
+                .peers
+                .iter()
+                .map(|peer| multiaddr("peers", peer))
+                .collect::<Result<_>>()?,
+            request_timeout: Duration::from_secs(settings.request_timeout),
+            identity: ReaderIdentity::load_or_generate(&settings.identity_path)?,
+        })
+    }
+}
+
+impl Default for ReaderNodeConfig {
+    fn default() -> Self {
+        Self {
+            listen_address: DEFAULT_LISTEN_ADDRESS.parse().unwrap(),
+            mdns_query_interval: Some(DEFAULT_MDNS_QUERY_INTERVAL),
+            peers: Vec::new(),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            identity: ReaderIdentity::generate(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureOutcome {
+    Stored,
+    Discarded,
+}
+
+enum Command {
+    Capture {
+        payload: Vec<u8>,
+        reply: oneshot::Sender<Result<CaptureOutcome>>,
+    },
+    Manager {
+        reply: oneshot::Sender<Option<PeerId>>,
+    },
+    ListenAddresses {
+        reply: oneshot::Sender<Vec<Multiaddr>>,
+    },
+}
+
+/// libp2p node that finds phonendo_manager and sends it captures. The swarm runs in its own task
+/// until the node is dropped.
+pub struct ReaderNode {
+    identity: ReaderIdentity,
+    commands: UnboundedSender<Command>,
+    task: JoinHandle<()>,
+}
+
+impl ReaderNode {
+    pub async fn start(config: ReaderNodeConfig) -> Result<Self> {
+        let mut swarm = build_swarm(
+            config.identity.keypair().clone(),
+            ProtocolSupport::Outbound,
+            config.mdns_query_interval,
+            config.request_timeout,
+        )?;
+        swarm.listen_on(config.listen_address.clone())?;
+        for peer in &config.peers {
+            swarm.dial(peer.clone())?;
+        }
+
+        println!("Reader node {} started.", config.identity.peer_id());
+
+        let (commands, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(
+            EventLoop {
+                swarm,
+                commands: receiver,
+                reader_info: ReaderInfo::new(&config.identity).to_json(),
+                manager: None,
+                peers: config.peers,
+                probed_peers: HashSet::new(),
+                pending_captures: HashMap::new(),
+            }
+            .run(),
+        );
+
+        Ok(Self {
+            identity: config.identity,
+            commands,
+            task,
+        })
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.identity.peer_id()
+    }
+
+    pub fn identity(&self) -> &ReaderIdentity {
+        &self.identity
+    }
+
+    /// phonendo_manager peer, if one has been found.
+    pub async fn manager(&self) -> Option<PeerId> {
+        let (reply, receiver) = oneshot::channel();
+        self.commands.send(Command::Manager { reply }).ok()?;
+        receiver.await.ok().flatten()
+    }
+
+    pub async fn listen_addresses(&self) -> Vec<Multiaddr> {
+        let (reply, receiver) = oneshot::channel();
+        if self
+            .commands
+            .send(Command::ListenAddresses { reply })
+            .is_err()
+        {
+            return Vec::new();
+        }
+        receiver.await.unwrap_or_default()
+    }
+
+    /// Sends `capture` to phonendo_manager, returning whether it was stored. It is signed first,
+    /// unless this reader already signed it.
+    pub async fn capture(&self, capture: &Capture) -> Result<CaptureOutcome> {
+        let payload = if capture.signature.is_some()
+            && capture.reader_id == Some(self.peer_id().to_string())
+        {
+            encode_capture(capture)
+        } else {
+            let mut capture = capture.clone();
+            self.identity.sign(&mut capture)?;
+            encode_capture(&capture)
+        };
+
+        let (reply, receiver) = oneshot::channel();
+        self.commands
+            .send(Command::Capture { payload, reply })
+            .map_err(|_| anyhow::Error::msg("Reader node stopped."))?;
+        receiver
+            .await
+            .map_err(|_| anyhow::Error::msg("Reader node stopped."))?
+    }
+}
+
+impl Drop for ReaderNode {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Queues every capture received and sends the pending ones to phonendo_manager in order, until
+/// the capture channel is closed. Captures leave the queue once the manager has answered them.
+pub async fn forward_captures(
+    node: &ReaderNode,
+    queue: &mut CaptureQueue,
+    mut captures: UnboundedReceiver<Capture>,
+) {
+    loop {
+        flush_captures(node, queue, &mut captures).await;
+        let capture = if queue.is_empty() {
+            captures.recv().await
+        } else {
+            match timeout(queue.retry_interval(), captures.recv()).await {
+                Ok(capture) => capture,
+                Err(_) => continue,
+            }
+        };
+        match capture {
+            Some(capture) => enqueue_capture(queue, capture),
+            None => break,
+        }
+    }
+}
+
+fn enqueue_capture(queue: &mut CaptureQueue, capture: Capture) {
+    match queue.push(capture) {
+        Ok(true) => (),
+        Ok(false) => println!("Capture queue full. Capture will be lost."),
+        Err(error) => println!("Unable to queue capture: {}. Capture will be lost.", error),
+    }
+}
+
+/// Sends pending captures until the queue is empty or one of them is not answered. Captures leave
+/// the queue once the manager answers, whether it stores or discards them.
+async fn flush_captures(
+    node: &ReaderNode,
+    queue: &mut CaptureQueue,
+    captures: &mut UnboundedReceiver<Capture>,
+) {
+    loop {
+        while let Ok(capture) = captures.try_recv() {
+            enqueue_capture(queue, capture);
+        }
+        let capture = match queue.front() {
+            Some(capture) => capture.clone(),
+            None => return,
+        };
+
+        match node.capture(&capture).await {
+            Ok(outcome) => {
+                match outcome {
+                    CaptureOutcome::Stored => println!("Capture stored by manager."),
+                    // Sending it again would get the same answer and hold back the captures
+                    // behind it.
+                    CaptureOutcome::Discarded => {
+                        println!("Capture discarded by manager. It will not be sent again.")
+                    }
+                }
+                if let Err(error) = queue.pop_front() {
+                    println!("Unable to update capture queue: {}.", error);
+                    return;
+                }
+            }
+            Err(error) => {
+                println!("{} {} captures pending.", error, queue.len());
+                return;
+            }
+        }
+    }
+}
+
+/// Swarm listening for TCP connections secured with Noise and multiplexed with Mplex.
+pub fn build_swarm(
+    keypair: Keypair,
+    capture_support: ProtocolSupport,
+    mdns_query_interval: Option<Duration>,
+    request_timeout: Duration,
+) -> Result<Swarm<PhonendoBehaviour>> {
+    Ok(SwarmBuilder::with_existing_identity(keypair)
+        .with_tokio()
+        .with_tcp(
+            tcp::Config::default(),
+            noise::Config::new,
+            libp2p_mplex::MplexConfig::default,
+        )?
+        .with_behaviour(|key| -> Result<_, Box<dyn Error + Send + Sync>> {
+            Ok(PhonendoBehaviour::new(
+                key.public().to_peer_id(),
+                capture_support,
+                mdns_query_interval,
+                request_timeout,
+            )?)
+        })?
+        .with_swarm_config(|swarm_config| {
+            swarm_config.with_idle_connection_timeout(IDLE_CONNECTION_TIMEOUT)
+        })
+        .build())
+}
+
+struct EventLoop {
+    swarm: Swarm<PhonendoBehaviour>,
+    commands: UnboundedReceiver<Command>,
+    /// Reply to discover requests.
+    reader_info: Vec<u8>,
+    manager: Option<PeerId>,
+    /// Configured peers, dialed again when the manager is lost.
+    peers: Vec<Multiaddr>,
+    /// Peers asked for their type since the manager was lost.
+    probed_peers: HashSet<PeerId>,
+    pending_captures: HashMap<OutboundRequestId, oneshot::Sender<Result<CaptureOutcome>>>,
+}
+
+impl EventLoop {
+    async fn run(mut self) {
+        loop {
+            tokio::select! {
+                event = self.swarm.select_next_some() => self.handle_event(event),
+                command = self.commands.recv() => match command {
+                    Some(command) => self.handle_command(command),
+                    None => break,
+                },
+            }
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Capture { payload, reply } => match self.manager {
+                Some(manager) => {
+                    let request_id = self
+                        .swarm
+                        .behaviour_mut()
+                        .capture
+                        .send_request(&manager, payload);
+                    self.pending_captures.insert(request_id, reply);
+                }
+                None => {
+                    let _ = reply.send(Err(anyhow::Error::msg("phonendo_manager unavailable.")));
+                }
+            },
+            Command::Manager { reply } => {
+                let _ = reply.send(self.manager);
+            }
+            Command::ListenAddresses { reply } => {
+                let _ = reply.send(self.swarm.listeners().cloned().collect());
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: SwarmEvent<PhonendoBehaviourEvent>) {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => println!("Listening on {}.", address),
+            SwarmEvent::ConnectionEstablished {
+                peer_id, endpoint, ..
+            } => {
+                // Remembered so that requests can dial the peer again if the connection drops.
+                if endpoint.is_dialer() {
+                    self.swarm
+                        .add_peer_address(peer_id, endpoint.get_remote_address().clone());
+                }
+                self.probe(peer_id);
+            }
+            SwarmEvent::Behaviour(PhonendoBehaviourEvent::Mdns(mdns::Event::Discovered(peers))) => {
+                for (peer_id, address) in peers {
+                    self.swarm.add_peer_address(peer_id, address);
+                    self.probe(peer_id);
+                }
+            }
+            SwarmEvent::Behaviour(PhonendoBehaviourEvent::Discover(
+                request_response::Event::Message {
+                    message: request_response::Message::Request { channel, .. },
+                    ..
+                },
+            )) => {
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .discover
+                    .send_response(channel, self.reader_info.clone());
+            }
+            SwarmEvent::Behaviour(PhonendoBehaviourEvent::Discover(
+                request_response::Event::Message {
+                    peer,
+                    message: request_response::Message::Response { response, .. },
+                },
+            )) if response == MANAGER_NODE_TYPE && self.manager.is_none() => {
+                println!("Added phonendo_manager peer {}.", peer);
+                self.manager = Some(peer);
+            }
+            // Peers that don't support the protocol are not asked again.
+            SwarmEvent::Behaviour(PhonendoBehaviourEvent::Discover(
+                request_response::Event::OutboundFailure { peer, error, .. },
+            )) if !matches!(error, OutboundFailure::UnsupportedProtocols) => {
+                self.probed_peers.remove(&peer);
+            }
+            SwarmEvent::Behaviour(PhonendoBehaviourEvent::Capture(
+                request_response::Event::Message {
+                    message:
+                        request_response::Message::Response {
+                            request_id,
+                            response,
+                        },
+                    ..
+                },
+            )) => {
+                if let Some(reply) = self.pending_captures.remove(&request_id) {
+                    let _ = reply.send(Ok(if response == CAPTURE_STORED {
+                        CaptureOutcome::Stored
+                    } else {
+                        CaptureOutcome::Discarded
+                    }));
+                }
+            }
+            SwarmEvent::Behaviour(PhonendoBehaviourEvent::Capture(
+                request_response::Event::OutboundFailure {
+                    peer,
+                    request_id,
+                    error,
+                },
+            )) => {
+                if matches!(
+                    error,
+                    OutboundFailure::DialFailure | OutboundFailure::UnsupportedProtocols
+                ) {
+                    self.lose_manager(&peer);
+                }
+                if let Some(reply) = self.pending_captures.remove(&request_id) {
+                    let _ = reply.send(Err(anyhow::Error::msg(format!(
+                        "Capture failed: {}.",
+                        error
+                    ))));
+                }
+            }
+            SwarmEvent::Behaviour(PhonendoBehaviourEvent::Ping(ping::Event {
+                peer,
+                result: Err(_),
+                ..
+            })) => self.lose_manager(&peer),
+            _ => (),
+        }
+    }
+
+    /// Asks `peer_id` for its type, unless the manager is already known.
+    fn probe(&mut self, peer_id: PeerId) {
+        if self.manager.is_none() && self.probed_peers.insert(peer_id) {
+            self.swarm
+                .behaviour_mut()
+                .discover
+                .send_request(&peer_id, DISCOVER_REQUEST.to_vec());
+        }
+    }
+
+    fn lose_manager(&mut self, peer_id: &PeerId) {
+        if self.manager == Some(*peer_id) {
+            println!("phonendo_manager peer {} unavailable.", peer_id);
+            self.manager = None;
+            self.probed_peers.clear();
+            for peer in &self.peers {
+                if let Err(error) = self.swarm.dial(peer.clone()) {
+                    println!("Unable to dial {}: {}.", peer, error);
+                }
+            }
+        }
+    }
+}
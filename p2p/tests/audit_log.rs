@@ -0,0 +1,175 @@
+use blt::Capture;
+use p2p::{
+    audit_captures, merkle_root, merkle_roots, read_audit_log, verify_audit_log,
+    verify_capture_with_key, AuditLog, AuditLogConfig, ReaderIdentity,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+fn audit_log_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "phonendo_audit_{}_{}.jsonl",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn capture(index: i64) -> Capture {
+    Capture {
+        id: Uuid::from_u128(index as u128 + 1),
+        timestamp: index,
+        ..Capture::new(format!("{:02x}", index))
+    }
+}
+
+fn write_log(path: &Path, count: i64) {
+    let mut log = AuditLog::open(AuditLogConfig {
+        path: path.to_path_buf(),
+    })
+    .unwrap();
+    for index in 0..count {
+        log.append(&capture(index)).unwrap();
+    }
+}
+
+#[test]
+fn entries_are_chained_across_runs() {
+    let path = audit_log_path("chained");
+    write_log(&path, 2);
+
+    let mut log = AuditLog::open(AuditLogConfig { path: path.clone() }).unwrap();
+    assert_eq!(log.len(), 2);
+    let entry = log.append(&capture(2)).unwrap();
+    assert_eq!(entry.index, 2);
+
+    let entries = read_audit_log(&path).unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].previous_hash, "0".repeat(64));
+    assert_eq!(entries[2].previous_hash, entries[1].hash);
+    assert_eq!(entries[2].capture().unwrap(), capture(2));
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn truncated_last_entries_are_removed() {
+    let path = audit_log_path("truncated");
+    write_log(&path, 2);
+    let content = fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    fs::write(
+        &path,
+        format!("{}\n{}", lines[0], &lines[1][..lines[1].len() / 2]),
+    )
+    .unwrap();
+
+    let mut log = AuditLog::open(AuditLogConfig { path: path.clone() }).unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log.append(&capture(1)).unwrap().index, 1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+    // A last entry only missing its newline is kept.
+    fs::write(&path, content.trim_end()).unwrap();
+    let log = AuditLog::open(AuditLogConfig { path: path.clone() }).unwrap();
+    assert_eq!(log.len(), 2);
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn tampered_logs_are_detected() {
+    let path = audit_log_path("tampered");
+    write_log(&path, 3);
+    let content = fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+
+    // Changed reading.
+    fs::write(
+        &path,
+        content.replacen("\"value\":\"01\"", "\"value\":\"ff\"", 1),
+    )
+    .unwrap();
+    let error = verify_audit_log(&path).unwrap_err().to_string();
+    assert!(error.contains("line 2"), "{}", error);
+
+    // Removed entry.
+    fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+    assert!(verify_audit_log(&path).is_err());
+
+    // Broken logs are not appended to.
+    assert!(AuditLog::open(AuditLogConfig { path: path.clone() }).is_err());
+
+    fs::write(&path, content).unwrap();
+    assert_eq!(verify_audit_log(&path).unwrap(), 3);
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn merkle_roots_cover_complete_batches() {
+    let path = audit_log_path("merkle");
+    write_log(&path, 7);
+    let entries = read_audit_log(&path).unwrap();
+
+    let batches = merkle_roots(&entries, 3).unwrap();
+    assert_eq!(batches.len(), 2);
+    assert_eq!((batches[0].first_index, batches[0].last_index), (0, 2));
+    assert_eq!((batches[1].first_index, batches[1].last_index), (3, 5));
+    assert_ne!(batches[0].root, batches[1].root);
+    assert_eq!(merkle_roots(&entries, 3).unwrap(), batches);
+    assert!(merkle_roots(&entries, 0).is_err());
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn merkle_root_depends_on_every_leaf_and_its_order() {
+    let leaves = [b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
+    let root = merkle_root(&leaves);
+    assert_eq!(root, merkle_root(&leaves));
+    assert_ne!(
+        root,
+        merkle_root(&[b"b".to_vec(), b"a".to_vec(), b"c".to_vec()])
+    );
+    assert_ne!(root, merkle_root(&leaves[..2]));
+    assert_ne!(merkle_root(&leaves[..1]), merkle_root::<Vec<u8>>(&[]));
+}
+
+#[tokio::test]
+async fn captures_are_signed_and_logged_before_being_forwarded() {
+    let path = audit_log_path("forward");
+    let mut log = AuditLog::open(AuditLogConfig { path: path.clone() }).unwrap();
+    let (sender, captures) = mpsc::unbounded_channel();
+    let (forward, mut forwarded) = mpsc::unbounded_channel();
+    for index in 0..2 {
+        sender.send(capture(index)).unwrap();
+    }
+    drop(sender);
+
+    let identity = ReaderIdentity::generate();
+    audit_captures(&mut log, &identity, captures, forward)
+        .await
+        .unwrap();
+    for (index, entry) in read_audit_log(&path).unwrap().iter().enumerate() {
+        // Captures are logged signed, as they are forwarded.
+        let forwarded = forwarded.recv().await.unwrap();
+        assert_eq!(entry.capture().unwrap(), forwarded);
+        assert!(verify_capture_with_key(&forwarded, &identity.public_key()).is_ok());
+        assert_eq!(
+            Capture {
+                reader_id: None,
+                signature: None,
+                ..forwarded
+            },
+            capture(index as i64)
+        );
+    }
+    assert_eq!(verify_audit_log(&path).unwrap(), 2);
+
+    let _ = fs::remove_file(&path);
+}
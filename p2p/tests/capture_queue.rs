@@ -0,0 +1,137 @@
+use blt::Capture;
+use p2p::{CaptureQueue, CaptureQueueConfig, EvictionPolicy};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+fn queue_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "phonendo_capture_queue_{}_{}.jsonl",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(path.with_extension("jsonl.offset"));
+    path
+}
+
+fn capture(index: i64) -> Capture {
+    Capture {
+        id: Uuid::from_u128(index as u128 + 1),
+        timestamp: index,
+        ..Capture::new(format!("{:02x}", index))
+    }
+}
+
+fn config(path: &Path, capacity: usize, eviction_policy: EvictionPolicy) -> CaptureQueueConfig {
+    CaptureQueueConfig {
+        path: path.to_path_buf(),
+        capacity,
+        eviction_policy,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn pending_captures_survive_reopening() {
+    let path = queue_path("reopen");
+    let mut queue = CaptureQueue::open(config(&path, 10, EvictionPolicy::DropOldest)).unwrap();
+    for index in 0..3 {
+        assert!(queue.push(capture(index)).unwrap());
+    }
+    assert_eq!(queue.pop_front().unwrap(), Some(capture(0)));
+    drop(queue);
+
+    let mut queue = CaptureQueue::open(config(&path, 10, EvictionPolicy::DropOldest)).unwrap();
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue.pop_front().unwrap(), Some(capture(1)));
+    assert_eq!(queue.pop_front().unwrap(), Some(capture(2)));
+    assert_eq!(queue.pop_front().unwrap(), None);
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn stored_captures_are_skipped_without_rewriting_the_queue() {
+    let path = queue_path("offset");
+    let mut queue = CaptureQueue::open(config(&path, 10, EvictionPolicy::DropOldest)).unwrap();
+    for index in 0..3 {
+        assert!(queue.push(capture(index)).unwrap());
+    }
+    let content = fs::read_to_string(&path).unwrap();
+    assert_eq!(queue.pop_front().unwrap(), Some(capture(0)));
+    assert_eq!(queue.pop_front().unwrap(), Some(capture(1)));
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+    drop(queue);
+
+    let mut queue = CaptureQueue::open(config(&path, 10, EvictionPolicy::DropOldest)).unwrap();
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue.pop_front().unwrap(), Some(capture(2)));
+
+    // Empty queues are truncated.
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    let mut offset_path = path.clone().into_os_string();
+    offset_path.push(".offset");
+    assert_eq!(fs::read_to_string(offset_path).unwrap().trim(), "0");
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn truncated_lines_are_skipped() {
+    let path = queue_path("truncated");
+    fs::write(
+        &path,
+        "{\"value\":\"00\",\"timestamp\":0}\n{\"value\":\"01\",\"time",
+    )
+    .unwrap();
+
+    let queue = CaptureQueue::open(config(&path, 10, EvictionPolicy::DropOldest)).unwrap();
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue.front().unwrap().value, "00");
+    assert_eq!(queue.front().unwrap().version, 1);
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn full_queues_evict_the_oldest_capture() {
+    let path = queue_path("drop_oldest");
+    let mut queue = CaptureQueue::open(config(&path, 2, EvictionPolicy::DropOldest)).unwrap();
+    for index in 0..3 {
+        assert!(queue.push(capture(index)).unwrap());
+    }
+    drop(queue);
+
+    let mut queue = CaptureQueue::open(config(&path, 2, EvictionPolicy::DropOldest)).unwrap();
+    assert_eq!(queue.pop_front().unwrap(), Some(capture(1)));
+    assert_eq!(queue.pop_front().unwrap(), Some(capture(2)));
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn full_queues_drop_the_newest_capture() {
+    let path = queue_path("drop_newest");
+    let mut queue = CaptureQueue::open(config(&path, 2, EvictionPolicy::DropNewest)).unwrap();
+    assert!(queue.push(capture(0)).unwrap());
+    assert!(queue.push(capture(1)).unwrap());
+    assert!(!queue.push(capture(2)).unwrap());
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue.front(), Some(&capture(0)));
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn eviction_policies_are_parsed() {
+    assert_eq!(
+        "drop_oldest".parse::<EvictionPolicy>().unwrap(),
+        EvictionPolicy::DropOldest
+    );
+    assert_eq!(
+        "DROP_NEWEST".parse::<EvictionPolicy>().unwrap(),
+        EvictionPolicy::DropNewest
+    );
+    assert!("drop_random".parse::<EvictionPolicy>().is_err());
+}
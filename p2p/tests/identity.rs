@@ -0,0 +1,68 @@
+use blt::{Capture, Reading};
+use p2p::{verify_capture, verify_capture_with_key, ReaderIdentity};
+use std::fs;
+
+fn signed_capture(identity: &ReaderIdentity) -> Capture {
+    let mut capture = Capture::new("16480102".to_string()).with_reading(Reading::new(72.0, "bpm"));
+    identity.sign(&mut capture).unwrap();
+    capture
+}
+
+#[test]
+fn identities_persist_between_runs() {
+    let path = std::env::temp_dir().join(format!("phonendo_reader_{}.key", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let identity = ReaderIdentity::load_or_generate(&path).unwrap();
+    let reloaded = ReaderIdentity::load_or_generate(&path).unwrap();
+    assert_eq!(identity.peer_id(), reloaded.peer_id());
+    assert_ne!(identity.peer_id(), ReaderIdentity::generate().peer_id());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    fs::write(&path, b"not a key").unwrap();
+    assert!(ReaderIdentity::load_or_generate(&path).is_err());
+
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn signed_captures_are_verified() {
+    let identity = ReaderIdentity::generate();
+    let capture = signed_capture(&identity);
+    assert_eq!(capture.reader_id, Some(identity.peer_id().to_string()));
+    assert!(verify_capture(&capture).is_ok());
+    assert!(verify_capture_with_key(&capture, &identity.public_key()).is_ok());
+
+    // The signature survives the JSON and binary forms.
+    assert!(verify_capture(&Capture::from_json(&capture.to_json()).unwrap()).is_ok());
+    assert!(verify_capture(&Capture::from_bytes(&capture.to_bytes()).unwrap()).is_ok());
+}
+
+#[test]
+fn tampered_captures_are_detected() {
+    let identity = ReaderIdentity::generate();
+    let capture = signed_capture(&identity);
+
+    let mut tampered = capture.clone();
+    tampered.reading = Some(Reading::new(180.0, "bpm"));
+    assert!(verify_capture(&tampered).is_err());
+
+    let mut tampered = capture.clone();
+    tampered.timestamp += 1;
+    assert!(verify_capture(&tampered).is_err());
+
+    // Signing again with another identity changes the reader id, which no longer matches the key.
+    let mut impersonated = capture.clone();
+    ReaderIdentity::generate().sign(&mut impersonated).unwrap();
+    assert!(verify_capture(&impersonated).is_ok());
+    assert!(verify_capture_with_key(&impersonated, &identity.public_key()).is_err());
+
+    let mut unsigned = capture;
+    unsigned.signature = None;
+    assert!(verify_capture(&unsigned).is_err());
+}
@@ -0,0 +1,271 @@
+use blt::Capture;
+use p2p::protocol::decode_capture;
+use p2p::{
+    forward_captures, verify_capture_with_key, CaptureOutcome, CaptureQueue, CaptureQueueConfig,
+    CaptureReply, MockManager, MockManagerConfig, ReaderIdentity, ReaderNode, ReaderNodeConfig,
+};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, timeout};
+use uuid::Uuid;
+
+async fn start_manager(script: Vec<CaptureReply>) -> MockManager {
+    MockManager::start(MockManagerConfig {
+        script,
+        ..Default::default()
+    })
+    .await
+    .unwrap()
+}
+
+async fn start_reader(manager: &MockManager) -> ReaderNode {
+    ReaderNode::start(ReaderNodeConfig {
+        mdns_query_interval: None,
+        peers: vec![manager.listen_address()],
+        request_timeout: Duration::from_millis(500),
+        ..Default::default()
+    })
+    .await
+    .unwrap()
+}
+
+async fn wait_for_manager(node: &ReaderNode) -> bool {
+    timeout(Duration::from_secs(10), async {
+        while node.manager().await.is_none() {
+            sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+fn capture_queue(name: &str) -> (PathBuf, CaptureQueue) {
+    let path = std::env::temp_dir().join(format!(
+        "phonendo_reader_node_{}_{}.jsonl",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_file(&path);
+    let _ = fs::remove_file(path.with_extension("jsonl.offset"));
+    let queue = CaptureQueue::open(CaptureQueueConfig {
+        path: path.clone(),
+        retry_interval: Duration::from_millis(100),
+        ..Default::default()
+    })
+    .unwrap();
+    (path, queue)
+}
+
+fn capture(index: i64) -> Capture {
+    Capture {
+        id: Uuid::from_u128(index as u128 + 1),
+        timestamp: index,
+        ..Capture::new(format!("{:02x}", index))
+    }
+}
+
+/// Captures received by `manager`, without the reader signature.
+fn received_captures(manager: &MockManager) -> Vec<Capture> {
+    manager
+        .captures()
+        .iter()
+        .map(|received| {
+            assert!(received.verified);
+            Capture {
+                reader_id: None,
+                signature: None,
+                ..decode_capture(&received.payload).unwrap()
+            }
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn captures_are_sent_to_the_manager() {
+    let manager = start_manager(Vec::new()).await;
+    let node = start_reader(&manager).await;
+    assert!(wait_for_manager(&node).await);
+    assert_eq!(node.manager().await, Some(manager.peer_id()));
+
+    let capture = Capture::new("5a3c".to_string());
+    assert_eq!(
+        node.capture(&capture).await.unwrap(),
+        CaptureOutcome::Stored
+    );
+    assert_eq!(received_captures(&manager), vec![capture]);
+    assert_eq!(manager.captures()[0].peer, node.peer_id());
+}
+
+#[tokio::test]
+async fn peers_of_other_types_are_not_managers() {
+    let manager = MockManager::start(MockManagerConfig {
+        node_type: b"phonendo_reader".to_vec(),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    let node = start_reader(&manager).await;
+
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(node.manager().await, None);
+    assert!(node.capture(&capture(0)).await.is_err());
+}
+
+#[tokio::test]
+async fn scripted_replies_are_reported() {
+    let manager = start_manager(vec![
+        CaptureReply::Discard,
+        CaptureReply::Timeout,
+        CaptureReply::Store,
+    ])
+    .await;
+    let node = start_reader(&manager).await;
+    assert!(wait_for_manager(&node).await);
+
+    assert_eq!(
+        node.capture(&capture(0)).await.unwrap(),
+        CaptureOutcome::Discarded
+    );
+    assert!(node.capture(&capture(1)).await.is_err());
+    assert_eq!(
+        node.capture(&capture(2)).await.unwrap(),
+        CaptureOutcome::Stored
+    );
+}
+
+#[tokio::test]
+async fn pending_captures_are_forwarded_in_order() {
+    let manager = start_manager(Vec::new()).await;
+    let node = start_reader(&manager).await;
+
+    // Captures queued while the manager was not found yet.
+    let (path, mut queue) = capture_queue("forward");
+    let (sender, captures) = mpsc::unbounded_channel();
+    for index in 0..3 {
+        sender.send(capture(index)).unwrap();
+    }
+    let forward = timeout(Duration::from_secs(10), async {
+        forward_captures(&node, &mut queue, captures).await
+    });
+    let send_later = async {
+        assert!(wait_for_manager(&node).await);
+        sender.send(capture(3)).unwrap();
+        drop(sender);
+    };
+    let (forwarded, _) = tokio::join!(forward, send_later);
+    assert!(forwarded.is_ok());
+
+    assert_eq!(
+        received_captures(&manager),
+        (0..4).map(capture).collect::<Vec<_>>()
+    );
+    assert!(queue.is_empty());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+    let _ = fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn unacknowledged_captures_are_resent() {
+    let manager = start_manager(vec![CaptureReply::Timeout]).await;
+    let node = start_reader(&manager).await;
+    assert!(wait_for_manager(&node).await);
+
+    let (path, mut queue) = capture_queue("resent");
+    let (sender, captures) = mpsc::unbounded_channel();
+    sender.send(capture(0)).unwrap();
+    sender.send(capture(1)).unwrap();
+    let forward = forward_captures(&node, &mut queue, captures);
+    let close_when_received = async {
+        assert!(manager.wait_for_captures(3, Duration::from_secs(10)).await);
+        drop(sender);
+    };
+    let (forwarded, _) = tokio::join!(
+        timeout(Duration::from_secs(10), forward),
+        close_when_received
+    );
+    assert!(forwarded.is_ok());
+
+    // The first capture is sent until it is stored, and the second one waits for it. The manager
+    // may see a capture more than once, it is not deduplicated by the reader.
+    assert_eq!(
+        received_captures(&manager),
+        vec![capture(0), capture(0), capture(1)]
+    );
+    assert!(queue.is_empty());
+
+    let _ = fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn discarded_captures_are_not_resent() {
+    let manager = start_manager(vec![CaptureReply::Discard]).await;
+    let node = start_reader(&manager).await;
+    assert!(wait_for_manager(&node).await);
+
+    let (path, mut queue) = capture_queue("discarded");
+    let (sender, captures) = mpsc::unbounded_channel();
+    sender.send(capture(0)).unwrap();
+    sender.send(capture(1)).unwrap();
+    drop(sender);
+    let forwarded = timeout(
+        Duration::from_secs(10),
+        forward_captures(&node, &mut queue, captures),
+    )
+    .await;
+    assert!(forwarded.is_ok());
+
+    assert_eq!(received_captures(&manager), vec![capture(0), capture(1)]);
+    assert!(queue.is_empty());
+
+    let _ = fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn dropped_connections_are_recovered() {
+    let manager = start_manager(vec![CaptureReply::Disconnect]).await;
+    let node = start_reader(&manager).await;
+    assert!(wait_for_manager(&node).await);
+
+    assert!(node.capture(&capture(0)).await.is_err());
+    assert!(wait_for_manager(&node).await);
+    assert_eq!(
+        node.capture(&capture(0)).await.unwrap(),
+        CaptureOutcome::Stored
+    );
+    assert!(manager.wait_for_captures(2, Duration::from_secs(1)).await);
+}
+
+#[tokio::test]
+async fn readers_answer_discover_with_their_public_key() {
+    let manager = start_manager(Vec::new()).await;
+    let identity = ReaderIdentity::generate();
+    let node = ReaderNode::start(ReaderNodeConfig {
+        mdns_query_interval: None,
+        peers: vec![manager.listen_address()],
+        identity: identity.clone(),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    assert!(wait_for_manager(&node).await);
+    assert_eq!(node.peer_id(), identity.peer_id());
+
+    timeout(Duration::from_secs(10), async {
+        while manager.readers().is_empty() {
+            sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let reader_info = &manager.readers()[&node.peer_id()];
+    assert_eq!(reader_info.peer_id, node.peer_id().to_string());
+    assert_eq!(reader_info.public_key().unwrap(), identity.public_key());
+
+    node.capture(&capture(0)).await.unwrap();
+    let received = decode_capture(&manager.captures()[0].payload).unwrap();
+    assert_eq!(received.reader_id, Some(node.peer_id().to_string()));
+    assert!(verify_capture_with_key(&received, &identity.public_key()).is_ok());
+}
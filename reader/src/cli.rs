@@ -0,0 +1,84 @@
+use blt::application_factory::APPLICATIONS;
+use bluer::Address;
+use clap::builder::PossibleValuesParser;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[clap(
+    name = "reader",
+    version,
+    about = "Reads Bluetooth LE bands and forwards their captures to phonendo_manager."
+)]
+pub struct Cli {
+    /// Configuration file [default: phonendo_reader.toml, or PHONENDO_CONFIG]
+    #[clap(long, short, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Without a command, the application and mode of the configuration file are run.
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Serves an application as a Bluetooth GATT server
+    Serve {
+        #[clap(value_parser = PossibleValuesParser::new(APPLICATIONS))]
+        app: String,
+        /// Seed of the random values generated by the application
+        #[clap(long)]
+        seed: Option<u64>,
+        /// Name advertised instead of the service name
+        #[clap(long)]
+        local_name: Option<String>,
+    },
+    /// Connects to a device serving an application and sends its readings to phonendo_manager
+    Connect {
+        #[clap(value_parser = PossibleValuesParser::new(APPLICATIONS))]
+        app: String,
+        /// Seed of the random values generated by the application
+        #[clap(long)]
+        seed: Option<u64>,
+        /// Multiaddress of a manager to dial, besides the ones found with mDNS
+        #[clap(long = "peer", value_name = "MULTIADDR")]
+        peers: Vec<String>,
+        /// Devices held in session at the same time
+        #[clap(long)]
+        max_sessions: Option<usize>,
+    },
+    /// Keeps the clock of every band providing the Current Time Service in sync
+    TimeSync,
+    /// Lists the devices found nearby
+    Scan {
+        /// Seconds to scan
+        #[clap(long, default_value_t = 10)]
+        duration: u64,
+    },
+    /// Shows the properties of a device
+    Info {
+        #[clap(value_parser)]
+        address: Address,
+        /// Seconds to look for the device if the adapter doesn't know it
+        #[clap(long, default_value_t = 10)]
+        timeout: u64,
+    },
+    /// Pairs a device
+    Pair {
+        #[clap(value_parser)]
+        address: Address,
+        /// Seconds to look for the device if the adapter doesn't know it
+        #[clap(long, default_value_t = 10)]
+        timeout: u64,
+        /// Answers of the pairing requests, overrides pairing.agent
+        #[clap(long, value_parser = PossibleValuesParser::new(["config", "prompt", "none"]))]
+        agent: Option<String>,
+    },
+    /// Removes a device and its pairing
+    Unpair {
+        #[clap(value_parser)]
+        address: Address,
+    },
+    /// Lists the applications and the modes they run in
+    ListApps,
+}
@@ -1,8 +1,228 @@
+mod cli;
+
 use anyhow::Result;
-use blt::application_factory::ApplicationFactory;
+use blt::application_factory::{ApplicationFactory, ApplicationMode, APPLICATIONS};
+use blt::backend::BluerAdapter;
+use blt::{
+    AdapterManager, CaptureSender, Config, DeviceInfo, InstanceSupervisor, PairingAgent, Shutdown,
+};
+use clap::Parser;
+use cli::{Cli, Command};
+use p2p::{
+    audit_captures, forward_captures, AuditLog, AuditLogConfig, CaptureQueue, CaptureQueueConfig,
+    ReaderNode, ReaderNodeConfig,
+};
+use std::env;
+use std::future::Future;
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::sync::mpsc;
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    ApplicationFactory::launch_application().await?;
-    Ok(())
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {:#}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let mut config = match &cli.config {
+        Some(path) => Config::load_file(path, env::vars())?,
+        None => Config::load()?,
+    };
+
+    match cli.command {
+        None => launch(&config).await,
+        Some(Command::Serve {
+            app,
+            seed,
+            local_name,
+        }) => {
+            config.app = Some(app);
+            config.mode = Some(ApplicationMode::Server);
+            config.instances.clear();
+            config.seed = seed.or(config.seed);
+            config.server.local_name = local_name.or(config.server.local_name);
+            config.validate()?;
+            launch(&config).await
+        }
+        Some(Command::Connect {
+            app,
+            seed,
+            peers,
+            max_sessions,
+        }) => {
+            config.app = Some(app);
+            config.mode = Some(ApplicationMode::Client);
+            config.instances.clear();
+            config.seed = seed.or(config.seed);
+            config.manager.peers.extend(peers);
+            config.client.max_sessions = max_sessions.unwrap_or(config.client.max_sessions);
+            config.validate()?;
+            launch(&config).await
+        }
+        Some(Command::TimeSync) => {
+            config.mode = Some(ApplicationMode::TimeSync);
+            config.instances.clear();
+            launch(&config).await
+        }
+        Some(Command::Scan { duration }) => {
+            let adapter_manager = AdapterManager::new(&config.adapter).await?;
+            let result: Result<()> = async {
+                println!("Scanning for {} seconds...", duration);
+                let devices = adapter_manager.scan(Duration::from_secs(duration)).await?;
+                for device in &devices {
+                    println!(
+                        "{} '{}'{}{} ({} services)",
+                        device.address,
+                        device.alias,
+                        if device.paired { " paired" } else { "" },
+                        if device.connected { " connected" } else { "" },
+                        device.uuids.len()
+                    );
+                }
+                println!("{} devices found.", devices.len());
+                Ok(())
+            }
+            .await;
+            result.and(adapter_manager.shutdown().await)
+        }
+        Some(Command::Info { address, timeout }) => {
+            let adapter_manager = AdapterManager::new(&config.adapter).await?;
+            let result: Result<()> = async {
+                let device = adapter_manager
+                    .find_device(address, Duration::from_secs(timeout))
+                    .await?;
+                print_device_info(&DeviceInfo::read(device.as_ref()).await?);
+                Ok(())
+            }
+            .await;
+            result.and(adapter_manager.shutdown().await)
+        }
+        Some(Command::Pair {
+            address,
+            timeout,
+            agent,
+        }) => {
+            config.pairing.agent = agent.unwrap_or(config.pairing.agent);
+            let mut adapter_manager = AdapterManager::new(&config.adapter).await?;
+            let result: Result<()> = async {
+                if let Some(agent) = PairingAgent::from_config(&config)? {
+                    adapter_manager.register_pairing_agent(agent).await?;
+                }
+                let device_info = adapter_manager
+                    .pair_device(address, Duration::from_secs(timeout))
+                    .await?;
+                println!("Device {} '{}' paired.", address, device_info.alias);
+                Ok(())
+            }
+            .await;
+            result.and(adapter_manager.shutdown().await)
+        }
+        Some(Command::Unpair { address }) => {
+            let adapter_manager = AdapterManager::new(&config.adapter).await?;
+            let result = adapter_manager.unpair_device(address).await;
+            if result.is_ok() {
+                println!("Device {} removed.", address);
+            }
+            result.and(adapter_manager.shutdown().await)
+        }
+        Some(Command::ListApps) => {
+            for app in APPLICATIONS {
+                let modes: Vec<String> = ApplicationFactory::modes(app)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                println!("{} ({})", app, modes.join(", "));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Runs the instances of `config`, or its application and mode, sending the captures of clients
+/// to phonendo_manager.
+async fn launch(config: &Config) -> Result<()> {
+    if !config.instances.is_empty() {
+        let supervisor = InstanceSupervisor::new(config.instances()?);
+        supervisor.check_adapters(&BluerAdapter::available().await?)?;
+        return if supervisor.has_clients() {
+            send_captures(config, |capture_sender| supervisor.run(capture_sender)).await
+        } else {
+            supervisor.run(CaptureSender::default()).await
+        };
+    }
+
+    match config.mode {
+        Some(ApplicationMode::Client) => {
+            send_captures(config, |capture_sender| {
+                ApplicationFactory::launch_application(config, capture_sender, Shutdown::ctrl_c())
+            })
+            .await
+        }
+        Some(_) => {
+            ApplicationFactory::launch_application(
+                config,
+                CaptureSender::default(),
+                Shutdown::ctrl_c(),
+            )
+            .await
+        }
+        None => Err(anyhow::Error::msg(
+            "No command given and no mode configured, see 'reader --help'.",
+        )),
+    }
+}
+
+/// Runs `clients`, logging their captures and forwarding them to phonendo_manager. Once they
+/// stop, the captures still on their way are logged and queued before returning.
+async fn send_captures<C, F>(config: &Config, clients: C) -> Result<()>
+where
+    C: FnOnce(CaptureSender) -> F,
+    F: Future<Output = Result<()>>,
+{
+    let mut queue = CaptureQueue::open(CaptureQueueConfig::from_settings(&config.manager)?)?;
+    let mut audit_log = AuditLog::open(AuditLogConfig::from_settings(&config.manager))?;
+    let node = ReaderNode::start(ReaderNodeConfig::from_settings(&config.manager)?).await?;
+    let (capture_sender, captures) = CaptureSender::channel();
+    let capture_sender = capture_sender.with_reader_id(node.peer_id().to_string());
+    let (audited_sender, audited_captures) = mpsc::unbounded_channel();
+
+    // Each stage ends when the one before it closes its sender: the clients when they stop, and
+    // the audit once it has logged every capture they sent.
+    let clients = clients(capture_sender);
+    let pipeline = async {
+        let (audited, ()) = tokio::join!(
+            audit_captures(&mut audit_log, node.identity(), captures, audited_sender),
+            forward_captures(&node, &mut queue, audited_captures),
+        );
+        audited
+    };
+    tokio::pin!(clients, pipeline);
+    tokio::select! {
+        result = &mut clients => {
+            pipeline.await?;
+            result
+        }
+        result = &mut pipeline => {
+            result?;
+            clients.await
+        }
+    }
+}
+
+fn print_device_info(device: &DeviceInfo) {
+    println!("Device {}", device.address);
+    println!("\tName: {}", device.name.as_deref().unwrap_or("-"));
+    println!("\tAlias: {}", device.alias);
+    println!("\tPaired: {}", device.paired);
+    println!("\tConnected: {}", device.connected);
+    println!("\tServices:");
+    for uuid in &device.uuids {
+        println!("\t\t{}", uuid);
+    }
 }